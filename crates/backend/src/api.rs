@@ -1,56 +1,295 @@
-use std::{collections::HashSet, fmt::Display};
+use std::{
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
 
-use actix_web::{web, HttpResponse, Scope};
+use actix_web::{
+    body::BoxBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    error::ErrorUnauthorized,
+    http::{
+        header::{ContentDisposition, CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+        Method, StatusCode,
+    },
+    middleware::{from_fn, Next},
+    web, Either, Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, Responder, Scope,
+};
 use apalis_core::{storage::Storage, task::task_id::TaskId};
+use futures::StreamExt;
 use serde::{de::DeserializeOwned, Serialize};
-use shared::{BackendExt, Filter, GetJobsResult};
-use tokio::sync::RwLock;
+use serde_json::{json, Value};
+use shared::{BackendExt, Filter, Namespace, RequestExt};
 
+#[cfg(feature = "federation")]
+use crate::federation::Federation;
+use crate::{
+    audit::{Audit, AuditFilter},
+    auth::{Auth, Headers, Identity},
+    bridge::poll::Watcher,
+    export::ExportQuery,
+    import::{ImportOptions, MAX_IMPORT_SIZE},
+    metrics::Metrics,
+    redact::{RawQuery, Redaction},
+    retention::Retention,
+    schema::JobSchema,
+    service::{etag_matches, Board, Body, Enqueue, NamespaceError, Reply, IDEMPOTENCY_KEY},
+    sse::Broadcaster,
+};
+
+/// Serves a [`Board`] with actix-web.
+///
+/// Sql storages keep the progress of jobs and the outcome of idempotent pushes in tables of the board.
+/// Create them with `shared::sqlite::setup`, `shared::postgres::setup` or `shared::mysql::setup`
+/// next to the storage's own `setup`, before adding the storage:
+///
+/// ```rust,ignore
+/// SqliteStorage::setup(&pool).await?;
+/// shared::sqlite::setup(&pool).await?;
+/// let scope = ApiBuilder::new().add_storage(&SqliteStorage::new(pool), "emails").build();
+/// ```
 pub struct ApiBuilder {
-    scope: Scope,
-    list: HashSet<String>,
+    board: Board,
+    events: Option<web::Data<Mutex<Broadcaster>>>,
 }
 
 impl ApiBuilder {
-    pub fn add_storage<J, S>(mut self, storage: &S, namespace: &str) -> Self
+    pub fn add_storage<J, S>(self, storage: &S, namespace: impl Into<Namespace>) -> Self
     where
-        J: Serialize + DeserializeOwned + 'static,
-        S: BackendExt<J> + Clone,
-        S: Storage<Job = J>,
-        S: 'static + Send,
-        S::Context: Serialize,
-        S::Request: Serialize,
-        <S as Storage>::Error: Display,
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
     {
-        self.list.insert(namespace.to_string());
+        Self {
+            board: self.board.add_storage(storage, namespace),
+            ..self
+        }
+    }
 
+    /// Serve `storage` with the schema of its jobs, pushed jobs that don't match it are rejected with a 422
+    ///
+    /// ```rust,ignore
+    /// ApiBuilder::new().add_storage_with_schema(&storage, "emails", JobSchema::of::<Email>())
+    /// ```
+    pub fn add_storage_with_schema<J, S>(
+        self,
+        storage: &S,
+        namespace: impl Into<Namespace>,
+        schema: JobSchema,
+    ) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
         Self {
-            scope: self.scope.service(
-                Scope::new(namespace)
-                    .app_data(web::Data::new(RwLock::new(storage.clone())))
-                    .route("", web::get().to(get_jobs::<J, S>)) // Fetch jobs in queue
-                    .route("/workers", web::get().to(get_workers::<J, S>)) // Fetch jobs in queue
-                    .route("/job", web::put().to(push_job::<J, S>)) // Allow add jobs via api
-                    .route("/job/{job_id}", web::get().to(get_job::<J, S>)), // Allow fetch specific job
-            ),
-            list: self.list,
+            board: self
+                .board
+                .add_storage_with_schema(storage, namespace, schema),
+            ..self
         }
     }
 
-    pub fn build(self) -> Scope {
-        async fn fetch_queues(queues: web::Data<HashSet<String>>) -> HttpResponse {
-            HttpResponse::Ok().json(queues)
+    /// Same as [`ApiBuilder::add_storage`] with the storage of `watcher`, which is started
+    /// to send the changes it finds to `broadcaster`.
+    /// Useful for backends that can't push changes like sqlite and mysql.
+    ///
+    /// ```rust,ignore
+    /// let watcher = Watcher::new(storage).interval(Duration::from_secs(1));
+    /// HttpServer::new(move || {
+    ///     let scope = ApiBuilder::new()
+    ///         .add_storage_watched(&watcher, "emails", &broadcaster)
+    ///         .events(&broadcaster)
+    ///         .build();
+    ///     App::new().service(scope)
+    /// })
+    /// ```
+    pub fn add_storage_watched<J, S>(
+        self,
+        watcher: &Watcher<J, S>,
+        namespace: impl Into<Namespace>,
+        broadcaster: &web::Data<Mutex<Broadcaster>>,
+    ) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
+        let namespace = namespace.into();
+        watcher.spawn(&namespace.key(), broadcaster.clone());
+        self.add_storage(watcher.storage(), namespace)
+    }
+
+    /// Serve the namespaces of the remote boards of `federation` as `{source}:{namespace}`
+    #[cfg(feature = "federation")]
+    pub fn federate(self, federation: &Federation) -> Self {
+        Self {
+            board: self.board.federate(federation),
+            ..self
+        }
+    }
+
+    /// Keep the stats of the storages added after this for `ttl`, a second by default
+    pub fn cache_stats_for(self, ttl: Duration) -> Self {
+        Self {
+            board: self.board.cache_stats_for(ttl),
+            ..self
+        }
+    }
+
+    /// Require every request to be authenticated by `auth`
+    pub fn with_auth(self, auth: Auth) -> Self {
+        Self {
+            board: self.board.with_auth(auth),
+            ..self
+        }
+    }
+
+    /// Describe the jobs of `namespace`, either derived with [`JobSchema::of`] or supplied with [`JobSchema::new`].
+    /// Pushed jobs that don't match the schema are rejected with a 422.
+    pub fn job_schema(self, namespace: &str, schema: JobSchema) -> Self {
+        Self {
+            board: self.board.job_schema(namespace, schema),
+            ..self
+        }
+    }
+
+    /// List the jobs of `namespace` with payloads of up to `bytes` of json, larger ones are cut to a preview
+    /// and only `GET /backend/{namespace}/job/{job_id}` serves them in full. 4 KiB by default.
+    pub fn preview_limit(self, namespace: &str, bytes: usize) -> Self {
+        Self {
+            board: self.board.preview_limit(namespace, bytes),
+            ..self
+        }
+    }
+
+    /// Record every mutating call in `audit`, readable at `GET /backend/audit`
+    pub fn with_audit(self, audit: Audit) -> Self {
+        Self {
+            board: self.board.with_audit(audit),
+            ..self
+        }
+    }
+
+    /// Serve `metrics` at `GET /backend/metrics` and count every request of the api in it
+    pub fn with_metrics(self, metrics: Metrics) -> Self {
+        Self {
+            board: self.board.with_metrics(metrics),
+            ..self
+        }
+    }
+
+    /// Remove finished jobs per the rules of `retention`, its runs are readable at `GET /backend/retention`
+    pub fn with_retention(self, retention: Retention) -> Self {
+        Self {
+            board: self.board.with_retention(retention),
+            ..self
+        }
+    }
+
+    /// Redact the payloads of jobs before they leave the api, admins can ask for them with `?raw=true`
+    pub fn with_redaction(self, redaction: Redaction) -> Self {
+        Self {
+            board: self.board.with_redaction(redaction),
+            ..self
         }
+    }
 
-        self.scope
-            .app_data(web::Data::new(self.list))
-            .route("", web::get().to(fetch_queues))
+    /// Remember the jobs pushed with an `Idempotency-Key` header for `ttl`, a day by default
+    pub fn remember_keys_for(self, ttl: Duration) -> Self {
+        Self {
+            board: self.board.remember_keys_for(ttl),
+            ..self
+        }
+    }
+
+    /// Serve the events sent to `broadcaster` at `GET /backend/events`, redacted like the payloads of the board
+    pub fn events(mut self, broadcaster: &web::Data<Mutex<Broadcaster>>) -> Self {
+        self.events = Some(broadcaster.clone());
+        self.board = self.board.serving_events();
+        self
+    }
+
+    /// Builds the scope, panics on a [`NamespaceError`]
+    pub fn build(self) -> Scope {
+        self.try_build().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Builds the scope unless two namespaces collide or a route of the board shadows one
+    pub fn try_build(self) -> Result<Scope, NamespaceError> {
+        self.board.check()?;
+        self.board.start_retention();
+        let metrics = self.board.metrics().cloned();
+        let scope = Scope::new("")
+            .wrap(from_fn(move |req: ServiceRequest, next: Next<BoxBody>| {
+                track(metrics.clone(), req, next)
+            }))
+            .route("", web::get().to(list_namespaces))
+            .route("/groups", web::get().to(list_groups))
+            .route("/openapi.json", web::get().to(get_openapi))
+            .route("/healthz", web::get().to(healthz))
+            .route("/readyz", web::get().to(readyz));
+        // Fixed routes go before `/{namespace}` so it doesn't shadow them, their names are reserved by `check`
+        let scope = match self.events {
+            Some(broadcaster) => {
+                if let Ok(mut broadcaster) = broadcaster.lock() {
+                    broadcaster.set_redaction(self.board.redaction().clone());
+                }
+                scope.service(
+                    web::resource("/events")
+                        .app_data(broadcaster)
+                        .route(web::get().to(events)),
+                )
+            }
+            None => scope,
+        };
+        let scope = if self.board.has_audit() {
+            scope.route("/audit", web::get().to(list_audit))
+        } else {
+            scope
+        };
+        let scope = if self.board.has_retention() {
+            scope.route("/retention", web::get().to(list_retention))
+        } else {
+            scope
+        };
+        let scope = if self.board.metrics().is_some() {
+            scope.route("/metrics", web::get().to(get_metrics))
+        } else {
+            scope
+        };
+        let scope = scope
+            .app_data(web::Data::new(self.board))
+            .route("/{namespace}", web::get().to(list_jobs)) // Fetch jobs in queue
+            .route("/{namespace}/workers", web::get().to(list_workers)) // Fetch workers of the queue
+            .route("/{namespace}/export", web::get().to(export_jobs)) // Stream every job in a state as NDJSON or CSV
+            .route("/{namespace}/job", web::put().to(push_job)) // Allow add jobs via api
+            .route("/{namespace}/jobs", web::put().to(push_jobs)) // Add or schedule a batch of jobs
+            .service(
+                web::resource("/{namespace}/import") // Queue the jobs of an NDJSON file
+                    .app_data(web::PayloadConfig::new(MAX_IMPORT_SIZE))
+                    .route(web::put().to(import_jobs)),
+            )
+            .route("/{namespace}/job/{job_id}", web::get().to(get_job)) // Allow fetch specific job
+            .route("/{namespace}/schema", web::get().to(get_schema)); // Describe the jobs accepted by `/job`
+        Ok(Scope::new("backend").service(scope))
     }
 
     pub fn new() -> Self {
         Self {
-            scope: Scope::new("backend"),
-            list: HashSet::new(),
+            board: Board::new(),
+            events: None,
         }
     }
 }
@@ -61,64 +300,440 @@ impl Default for ApiBuilder {
     }
 }
 
-async fn push_job<J, S>(job: web::Json<J>, storage: web::Data<RwLock<S>>) -> HttpResponse
-where
-    J: Serialize + DeserializeOwned + 'static,
-    S: Storage<Job = J> + Clone,
-    S::Error: Display,
-{
-    let res = storage.write().await.push(job.into_inner()).await;
-    match res {
-        Ok(parts) => {
-            HttpResponse::Ok().body(format!("Job with ID [{}] added to queue", parts.task_id))
+impl From<Board> for ApiBuilder {
+    fn from(board: Board) -> Self {
+        Self {
+            board,
+            events: None,
         }
-        Err(e) => HttpResponse::InternalServerError().body(format!("{e}")),
     }
 }
 
-async fn get_jobs<J, S>(storage: web::Data<RwLock<S>>, filter: web::Query<Filter>) -> HttpResponse
-where
-    J: Serialize + DeserializeOwned + 'static,
-    S: Storage<Job = J> + BackendExt<J> + Send,
-    S::Request: Serialize,
-{
-    dbg!(&filter);
-    // TODO: fix unwrap
-    let stats = storage.read().await.stats().await.unwrap_or_default();
-    let res = storage
-        .read()
+/// Serves the embedded frontend, meant to be the default service of the app
+/// so every path the api doesn't handle reaches it.
+///
+/// ```rust,ignore
+/// App::new()
+///     .service(web::scope("/api/v1").service(ApiBuilder::new().add_storage(&storage, "emails").build()))
+///     .default_service(ui())
+/// ```
+#[cfg(feature = "ui")]
+pub fn ui() -> actix_web::Route {
+    web::get().to(serve_ui)
+}
+
+#[cfg(feature = "ui")]
+async fn serve_ui(req: HttpRequest) -> HttpResponse {
+    use actix_web::http::header::CACHE_CONTROL;
+    use std::borrow::Cow;
+
+    let Some(asset) = crate::ui::resolve(req.path()) else {
+        return HttpResponse::NotFound().finish();
+    };
+    let cache_control = asset.cache_control();
+    let body = match asset.body {
+        Cow::Borrowed(body) => web::Bytes::from_static(body),
+        Cow::Owned(body) => web::Bytes::from(body),
+    };
+    HttpResponse::Ok()
+        .content_type(asset.mime)
+        .insert_header((CACHE_CONTROL, cache_control))
+        .body(body)
+}
+
+impl Responder for Reply {
+    type Body = BoxBody;
+
+    fn respond_to(self, req: &HttpRequest) -> HttpResponse {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut res = HttpResponse::build(status);
+        if let Some(etag) = self.etag() {
+            let if_none_match = req
+                .headers()
+                .get(IF_NONE_MATCH)
+                .and_then(|value| value.to_str().ok());
+            if req.method() == Method::GET
+                && if_none_match.is_some_and(|tags| etag_matches(tags, &etag))
+            {
+                return HttpResponse::NotModified()
+                    .insert_header((ETAG, etag))
+                    .finish();
+            }
+            // Revalidated every time, an unchanged listing costs a 304
+            res.insert_header((ETAG, etag))
+                .insert_header((CACHE_CONTROL, "no-cache"));
+        }
+        match self.body {
+            Body::Empty => res.finish(),
+            Body::Text(text) => res.body(text),
+            Body::Json(value) => res.json(value),
+            Body::Raw { content_type, body } => res.content_type(content_type).body(body),
+        }
+    }
+}
+
+impl FromRequest for Identity {
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            if let Some(identity) = req.extensions().get::<Identity>() {
+                return Ok(identity.clone());
+            }
+            let Some(board) = req.app_data::<web::Data<Board>>() else {
+                return Ok(Identity::anonymous());
+            };
+            let headers: Headers = req
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+                .collect();
+            let identity = board
+                .identify(&headers)
+                .await
+                .ok_or_else(|| ErrorUnauthorized("unauthorized"))?;
+            req.extensions_mut().insert(identity.clone());
+            Ok(identity)
+        })
+    }
+}
+
+/// Counts the requests of the api when it has [`Metrics`]
+async fn track(
+    metrics: Option<Metrics>,
+    req: ServiceRequest,
+    next: Next<BoxBody>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(metrics) = metrics else {
+        return next.call(req).await;
+    };
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let res = next.call(req).await?;
+    let route = res
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    metrics.observe(&method, &route, res.status().as_u16(), start.elapsed());
+    Ok(res)
+}
+
+async fn events(broadcaster: web::Data<Mutex<Broadcaster>>, identity: Identity) -> HttpResponse {
+    let Ok(mut broadcaster) = broadcaster.lock() else {
+        return HttpResponse::InternalServerError().finish();
+    };
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((CACHE_CONTROL, "no-cache"))
+        .streaming(broadcaster.new_client_for(&identity))
+}
+
+async fn healthz(board: web::Data<Board>) -> Reply {
+    board.healthz()
+}
+
+async fn readyz(board: web::Data<Board>, identity: Option<Identity>) -> Reply {
+    board.readyz(identity.as_ref()).await
+}
+
+async fn get_metrics(board: web::Data<Board>, identity: Identity) -> Reply {
+    board.render_metrics(&identity).await
+}
+
+async fn list_namespaces(board: web::Data<Board>, identity: Identity) -> Reply {
+    board.list_namespaces(&identity).await
+}
+
+async fn list_groups(board: web::Data<Board>, identity: Identity) -> Reply {
+    board.list_groups(&identity).await
+}
+
+async fn get_openapi(
+    board: web::Data<Board>,
+    identity: Identity,
+    req: HttpRequest,
+) -> HttpResponse {
+    // The document is relative to wherever the api was mounted
+    let server = req
+        .path()
+        .strip_suffix("/backend/openapi.json")
+        .unwrap_or_default();
+    let mut doc = board.openapi(&identity);
+    doc["servers"] = json!([{ "url": if server.is_empty() { "/" } else { server } }]);
+    HttpResponse::Ok().json(doc)
+}
+
+async fn list_jobs(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+    filter: web::Query<Filter>,
+    query: web::Query<RawQuery>,
+) -> Reply {
+    board
+        .list_jobs(&identity, &namespace, &filter, query.raw)
+        .await
+}
+
+async fn list_workers(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+) -> Reply {
+    board.list_workers(&identity, &namespace).await
+}
+
+async fn export_jobs(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+    query: web::Query<ExportQuery>,
+) -> Either<HttpResponse, Reply> {
+    let export = match board.export_jobs(&identity, &namespace, &query) {
+        Ok(export) => export,
+        Err(reply) => return Either::Right(reply),
+    };
+    let chunks = export
+        .chunks
+        .map(|chunk| chunk.map(web::Bytes::from).map_err(std::io::Error::other));
+    Either::Left(
+        HttpResponse::Ok()
+            .content_type(export.content_type)
+            .insert_header(ContentDisposition::attachment(export.filename))
+            .streaming(chunks),
+    )
+}
+
+async fn push_job(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+    payload: web::Json<Value>,
+    req: HttpRequest,
+) -> Reply {
+    board
+        .push_job(
+            &identity,
+            &namespace,
+            payload.into_inner(),
+            idempotency_key(&req),
+        )
+        .await
+}
+
+async fn push_jobs(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+    batch: web::Json<Vec<Enqueue>>,
+    req: HttpRequest,
+) -> Reply {
+    board
+        .push_jobs(
+            &identity,
+            &namespace,
+            batch.into_inner(),
+            idempotency_key(&req),
+        )
+        .await
+}
+
+fn idempotency_key(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(IDEMPOTENCY_KEY)
+        .and_then(|v| v.to_str().ok())
+}
+
+async fn import_jobs(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+    options: web::Query<ImportOptions>,
+    ndjson: String,
+) -> Reply {
+    board
+        .import_jobs(&identity, &namespace, &ndjson, &options)
+        .await
+}
+
+async fn get_job(
+    board: web::Data<Board>,
+    identity: Identity,
+    path: web::Path<(String, TaskId)>,
+    query: web::Query<RawQuery>,
+) -> Reply {
+    let (namespace, job_id) = path.into_inner();
+    board
+        .get_job(&identity, &namespace, &job_id, query.raw)
         .await
-        .list_jobs(&filter.status, filter.page)
+}
+
+async fn get_schema(
+    board: web::Data<Board>,
+    identity: Identity,
+    namespace: web::Path<String>,
+) -> Reply {
+    board.get_schema(&identity, &namespace)
+}
+
+async fn list_retention(board: web::Data<Board>, identity: Identity) -> Reply {
+    board.list_retention(&identity)
+}
+
+async fn list_audit(
+    board: web::Data<Board>,
+    identity: Identity,
+    filter: web::Query<AuditFilter>,
+) -> Reply {
+    board.list_audit(&identity, &filter).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::header::CONTENT_TYPE, test, App};
+
+    use super::*;
+    use crate::testing::Fake;
+
+    #[actix_web::test]
+    async fn events_are_not_shadowed_by_namespaces() {
+        let broadcaster = Broadcaster::create();
+        let board = Board::new().add_fake("emails", Arc::new(Fake::default()));
+        let app = test::init_service(
+            App::new().service(ApiBuilder::from(board).events(&broadcaster).build()),
+        )
+        .await;
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/backend/events").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_TYPE], "text/event-stream");
+
+        let res = test::call_service(
+            &app,
+            test::TestRequest::get().uri("/backend/emails").to_request(),
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn events_and_openapi_need_credentials() {
+        let broadcaster = Broadcaster::create();
+        let auth = Auth::new().bearer("s3cr3t", Identity::viewer("alice").only(["emails"]));
+        let board = Board::new()
+            .add_fake("emails", Arc::new(Fake::default()))
+            .add_fake("invoices", Arc::new(Fake::default()))
+            .with_auth(auth);
+        let app = test::init_service(
+            App::new().service(ApiBuilder::from(board).events(&broadcaster).build()),
+        )
         .await;
-    match res {
-        Ok(jobs) => HttpResponse::Ok().json(GetJobsResult { stats, jobs }),
-        Err(_) => HttpResponse::InternalServerError().json("get_jobs_failed"), //TODO
-    }
-}
-
-async fn get_workers<J, S>(storage: web::Data<RwLock<S>>) -> HttpResponse
-where
-    J: Serialize + DeserializeOwned + 'static,
-    S: Storage<Job = J> + BackendExt<J> + Clone,
-{
-    let workers = storage.read().await.list_workers().await;
-    match workers {
-        Ok(workers) => HttpResponse::Ok().json(workers),
-        Err(_) => HttpResponse::InternalServerError().body("get_workers_failed"), //TODO
-    }
-}
-
-async fn get_job<J, S>(job_id: web::Path<TaskId>, storage: web::Data<RwLock<S>>) -> HttpResponse
-where
-    J: Serialize + DeserializeOwned + 'static,
-    S: Storage<Job = J> + 'static,
-    S::Error: Display,
-    S::Context: Serialize,
-{
-    let res = storage.write().await.fetch_by_id(&job_id).await;
-    match res {
-        Ok(Some(job)) => HttpResponse::Ok().json(job),
-        Ok(None) => HttpResponse::NotFound().finish(),
-        Err(e) => HttpResponse::InternalServerError().body(format!("{e}")),
+
+        for uri in ["/backend/events", "/backend/openapi.json"] {
+            let res =
+                test::call_service(&app, test::TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(res.status(), StatusCode::UNAUTHORIZED, "{uri}");
+        }
+
+        let req = test::TestRequest::get()
+            .uri("/backend/openapi.json")
+            .insert_header(("authorization", "Bearer s3cr3t"))
+            .to_request();
+        let doc: Value = test::call_and_read_body_json(&app, req).await;
+        let paths = doc["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/backend/emails"));
+        assert!(!paths.keys().any(|path| path.contains("invoices")));
+    }
+
+    #[actix_web::test]
+    async fn unchanged_listings_are_not_modified() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new().add_fake("emails", queue.clone());
+        let app = test::init_service(App::new().service(ApiBuilder::from(board).build())).await;
+        let list = |etag: Option<&str>| {
+            let mut req = test::TestRequest::get().uri("/backend/emails");
+            if let Some(etag) = etag {
+                req = req.insert_header((IF_NONE_MATCH, etag.to_string()));
+            }
+            req.to_request()
+        };
+
+        let res = test::call_service(&app, list(None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CACHE_CONTROL], "no-cache");
+        let etag = res.headers()[ETAG].to_str().unwrap().to_string();
+
+        let res = test::call_service(&app, list(Some(&etag))).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        let weak = format!("W/{etag}");
+        let res = test::call_service(&app, list(Some(&weak))).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        queue
+            .jobs
+            .lock()
+            .unwrap()
+            .push(("1".to_string(), json!({ "task_id": "1", "args": {} })));
+        let res = test::call_service(&app, list(Some(&etag))).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_ne!(res.headers()[ETAG].to_str().unwrap(), etag);
+    }
+
+    #[actix_web::test]
+    async fn namespaces_named_after_a_route_are_rejected() {
+        let board = Board::new().add_fake("events", Arc::new(Fake::default()));
+        assert_eq!(
+            ApiBuilder::from(board).try_build().err(),
+            Some(NamespaceError::Reserved("events".to_string()))
+        );
+    }
+
+    #[actix_web::test]
+    async fn storages_are_added_with_their_schema() {
+        use apalis_sql::sqlite::{SqlitePool, SqliteStorage};
+        use serde_json::{json, Value};
+
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        SqliteStorage::setup(&pool).await.unwrap();
+        shared::sqlite::setup(&pool).await.unwrap();
+        let storage: SqliteStorage<Value> = SqliteStorage::new(pool);
+        let schema = JobSchema::new(json!({
+            "type": "object",
+            "properties": { "to": { "type": "string" } },
+            "required": ["to"]
+        }))
+        .unwrap();
+        let app = test::init_service(
+            App::new().service(
+                ApiBuilder::new()
+                    .add_storage_with_schema(&storage, "emails", schema)
+                    .build(),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/backend/emails/schema")
+            .to_request();
+        let described: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(described["required"], json!(["to"]));
+        let req = test::TestRequest::put()
+            .uri("/backend/emails/job")
+            .set_json(json!({ "to": 42 }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 422);
+        let req = test::TestRequest::put()
+            .uri("/backend/emails/job")
+            .set_json(json!({ "to": "test@example.com" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
     }
 }
@@ -0,0 +1,290 @@
+use std::{path::PathBuf, sync::Arc};
+
+use futures::future::BoxFuture;
+pub use shared::{AuditEntry, AuditFilter};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    sync::Mutex,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuditError {
+    #[error("io::Error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("serde_json::Error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("sqlx::Error: {0}")]
+    Sqlx(#[from] sqlx::Error),
+}
+
+/// Where audit entries are kept
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, entry: AuditEntry) -> BoxFuture<'_, Result<(), AuditError>>;
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a AuditFilter,
+    ) -> BoxFuture<'a, Result<Vec<AuditEntry>, AuditError>>;
+}
+
+/// The audit trail shared by all namespaces of an api
+#[derive(Clone)]
+pub struct Audit(Arc<dyn AuditSink>);
+
+impl Audit {
+    pub fn new<S: AuditSink>(sink: S) -> Self {
+        Self(Arc::new(sink))
+    }
+
+    pub async fn record(&self, entry: AuditEntry) -> Result<(), AuditError> {
+        self.0.record(entry).await
+    }
+
+    pub async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, AuditError> {
+        self.0.query(filter).await
+    }
+}
+
+/// Appends entries as json lines to a file
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonLinesSink {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl AuditSink for JsonLinesSink {
+    fn record(&self, entry: AuditEntry) -> BoxFuture<'_, Result<(), AuditError>> {
+        Box::pin(async move {
+            let mut line = serde_json::to_vec(&entry)?;
+            line.push(b'\n');
+            let _guard = self.lock.lock().await;
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(&line).await?;
+            file.flush().await?;
+            Ok(())
+        })
+    }
+
+    fn query<'a>(
+        &'a self,
+        filter: &'a AuditFilter,
+    ) -> BoxFuture<'a, Result<Vec<AuditEntry>, AuditError>> {
+        Box::pin(async move {
+            let file = match OpenOptions::new().read(true).open(&self.path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            let mut lines = BufReader::new(file).lines();
+            let mut entries = Vec::new();
+            while let Some(line) = lines.next_line().await? {
+                let Ok(entry) = serde_json::from_str::<AuditEntry>(&line) else {
+                    continue;
+                };
+                if filter.matches(&entry) {
+                    entries.push(entry);
+                }
+            }
+            entries.reverse();
+            entries.truncate(filter.limit);
+            Ok(entries)
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteSink;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use futures::future::BoxFuture;
+    use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+    use super::{AuditEntry, AuditError, AuditFilter, AuditSink};
+
+    /// Keeps entries in the `BoardAudit` table of a sqlite database
+    #[derive(Debug, Clone)]
+    pub struct SqliteSink {
+        pool: SqlitePool,
+    }
+
+    impl SqliteSink {
+        pub fn new(pool: SqlitePool) -> Self {
+            Self { pool }
+        }
+
+        /// Creates the `BoardAudit` table
+        pub async fn setup(&self) -> Result<(), sqlx::Error> {
+            sqlx::query(
+                "CREATE TABLE IF NOT EXISTS BoardAudit (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    actor TEXT NOT NULL,
+                    namespace TEXT NOT NULL,
+                    task_id TEXT,
+                    action TEXT NOT NULL,
+                    before TEXT,
+                    after TEXT,
+                    timestamp INTEGER NOT NULL
+                )",
+            )
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        }
+    }
+
+    type Row = (
+        String,
+        String,
+        Option<String>,
+        String,
+        Option<String>,
+        Option<String>,
+        i64,
+    );
+
+    impl AuditSink for SqliteSink {
+        fn record(&self, entry: AuditEntry) -> BoxFuture<'_, Result<(), AuditError>> {
+            Box::pin(async move {
+                let before = entry
+                    .before
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?;
+                let after = entry
+                    .after
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?;
+                sqlx::query(
+                    "INSERT INTO BoardAudit (actor, namespace, task_id, action, before, after, timestamp) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(&entry.actor)
+                .bind(&entry.namespace)
+                .bind(&entry.task_id)
+                .bind(&entry.action)
+                .bind(before)
+                .bind(after)
+                .bind(entry.timestamp as i64)
+                .execute(&self.pool)
+                .await?;
+                Ok(())
+            })
+        }
+
+        fn query<'a>(
+            &'a self,
+            filter: &'a AuditFilter,
+        ) -> BoxFuture<'a, Result<Vec<AuditEntry>, AuditError>> {
+            Box::pin(async move {
+                let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(
+                    "SELECT actor, namespace, task_id, action, before, after, timestamp FROM BoardAudit WHERE 1 = 1",
+                );
+                for (column, value) in [
+                    ("actor", &filter.actor),
+                    ("namespace", &filter.namespace),
+                    ("task_id", &filter.task_id),
+                    ("action", &filter.action),
+                ] {
+                    if let Some(value) = value {
+                        query.push(format!(" AND {column} = ")).push_bind(value);
+                    }
+                }
+                if let Some(since) = filter.since {
+                    query.push(" AND timestamp >= ").push_bind(since as i64);
+                }
+                if let Some(until) = filter.until {
+                    query.push(" AND timestamp <= ").push_bind(until as i64);
+                }
+                if let Some(namespaces) = &filter.namespaces {
+                    if namespaces.is_empty() {
+                        return Ok(Vec::new());
+                    }
+                    query.push(" AND namespace IN (");
+                    let mut separated = query.separated(", ");
+                    for namespace in namespaces {
+                        separated.push_bind(namespace);
+                    }
+                    separated.push_unseparated(")");
+                }
+                query
+                    .push(" ORDER BY id DESC LIMIT ")
+                    .push_bind(filter.limit as i64);
+                let rows: Vec<Row> = query.build_query_as().fetch_all(&self.pool).await?;
+                rows.into_iter()
+                    .map(|(actor, namespace, task_id, action, before, after, timestamp)| -> Result<_, AuditError> {
+                        Ok(AuditEntry {
+                            actor,
+                            namespace,
+                            task_id,
+                            action,
+                            before: before.as_deref().map(serde_json::from_str).transpose()?,
+                            after: after.as_deref().map(serde_json::from_str).transpose()?,
+                            timestamp: timestamp as u64,
+                        })
+                    })
+                    .collect()
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+
+    fn sink(name: &str) -> JsonLinesSink {
+        let path =
+            std::env::temp_dir().join(format!("board-audit-{}-{name}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        JsonLinesSink::new(path)
+    }
+
+    #[actix_web::test]
+    async fn the_limit_applies_after_the_namespaces() {
+        let sink = sink("limit");
+        sink.record(AuditEntry::new("alice", "emails", "push"))
+            .await
+            .unwrap();
+        for _ in 0..3 {
+            sink.record(AuditEntry::new("bob", "invoices", "push"))
+                .await
+                .unwrap();
+        }
+        let filter = AuditFilter {
+            limit: 2,
+            namespaces: Some(BTreeSet::from(["emails".to_string()])),
+            ..AuditFilter::default()
+        };
+        let entries = sink.query(&filter).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].namespace, "emails");
+
+        let entries = sink
+            .query(&AuditFilter {
+                limit: 2,
+                ..AuditFilter::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry.namespace == "invoices"));
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::{HashMap, HashSet},
+    future::ready,
+    sync::Arc,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// What an identity is allowed to do, higher roles include the lower ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Role {
+    /// Can list queues, jobs and workers
+    Viewer,
+    /// Can also push and modify jobs
+    Operator,
+    /// Can also see payloads without their [`Redaction`](crate::redact::Redaction)
+    Admin,
+}
+
+/// Someone verified by [`Auth`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub role: Role,
+    /// The namespaces this identity can access, `None` for all of them
+    pub namespaces: Option<HashSet<String>>,
+}
+
+impl Identity {
+    pub fn new(name: &str, role: Role) -> Self {
+        Self {
+            name: name.to_string(),
+            role,
+            namespaces: None,
+        }
+    }
+
+    pub fn viewer(name: &str) -> Self {
+        Self::new(name, Role::Viewer)
+    }
+
+    pub fn operator(name: &str) -> Self {
+        Self::new(name, Role::Operator)
+    }
+
+    /// Restrict the identity to some namespaces
+    pub fn only<I: IntoIterator<Item = S>, S: Into<String>>(mut self, namespaces: I) -> Self {
+        self.namespaces = Some(namespaces.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn admin(name: &str) -> Self {
+        Self::new(name, Role::Admin)
+    }
+
+    /// Used when the board has no [`Auth`], nothing is protected so it can do anything
+    pub fn anonymous() -> Self {
+        Self::admin("anonymous")
+    }
+
+    pub fn can_access(&self, namespace: &str) -> bool {
+        match &self.namespaces {
+            Some(namespaces) => namespaces.contains(namespace),
+            None => true,
+        }
+    }
+
+    pub fn allows(&self, role: Role, namespace: &str) -> bool {
+        self.role >= role && self.can_access(namespace)
+    }
+}
+
+/// The headers of a request, whatever http framework is serving the api.
+/// Names are lowercase.
+#[derive(Debug, Clone, Default)]
+pub struct Headers(HashMap<String, String>);
+
+impl Headers {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(&name.to_ascii_lowercase()).map(String::as_str)
+    }
+
+    /// The token of an `Authorization: Bearer <token>` header
+    pub fn bearer(&self) -> Option<&str> {
+        let (scheme, token) = self.get("authorization")?.split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+    }
+
+    /// The user and password of an `Authorization: Basic <credentials>` header
+    pub fn basic(&self) -> Option<(String, String)> {
+        let (scheme, credentials) = self.get("authorization")?.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = STANDARD.decode(credentials.trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (user, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+        Some((user.to_string(), password.to_string()))
+    }
+}
+
+impl<K: AsRef<str>, V: Into<String>> FromIterator<(K, V)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(name, value)| (name.as_ref().to_ascii_lowercase(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Checks the credentials of a request.
+///
+/// ```rust,ignore
+/// struct ApiKeys(Database);
+///
+/// impl Verifier for ApiKeys {
+///     fn verify<'a>(&'a self, headers: &'a Headers) -> BoxFuture<'a, Option<Identity>> {
+///         Box::pin(async move {
+///             let key = headers.get("x-api-key")?;
+///             self.0.find_identity(key).await
+///         })
+///     }
+/// }
+/// ```
+pub trait Verifier: Send + Sync + 'static {
+    /// Returns `None` if the request doesn't carry credentials this verifier accepts
+    fn verify<'a>(&'a self, headers: &'a Headers) -> BoxFuture<'a, Option<Identity>>;
+}
+
+/// Accepts `Authorization: Bearer <token>` for a fixed set of tokens, kept as digests
+#[derive(Debug, Clone, Default)]
+pub struct StaticTokens {
+    tokens: HashMap<Secret, Identity>,
+}
+
+impl StaticTokens {
+    pub fn insert(&mut self, token: &str, identity: Identity) {
+        self.tokens.insert(digest(token), identity);
+    }
+}
+
+impl Verifier for StaticTokens {
+    fn verify<'a>(&'a self, headers: &'a Headers) -> BoxFuture<'a, Option<Identity>> {
+        let identity = headers.bearer().and_then(|token| {
+            let given = digest(token);
+            // Every token is compared so the time taken doesn't tell which one came closest
+            self.tokens
+                .iter()
+                .fold(None, |found, (known, identity)| {
+                    if constant_time_eq(known, &given) {
+                        Some(identity)
+                    } else {
+                        found
+                    }
+                })
+                .cloned()
+        });
+        Box::pin(ready(identity))
+    }
+}
+
+/// Accepts HTTP basic authentication for a fixed set of users
+#[derive(Debug, Clone, Default)]
+pub struct BasicUsers {
+    users: HashMap<String, (Secret, Identity)>,
+}
+
+impl BasicUsers {
+    pub fn insert(&mut self, user: &str, password: &str, identity: Identity) {
+        self.users
+            .insert(user.to_string(), (digest(password), identity));
+    }
+}
+
+impl Verifier for BasicUsers {
+    fn verify<'a>(&'a self, headers: &'a Headers) -> BoxFuture<'a, Option<Identity>> {
+        let identity = headers.basic().and_then(|(user, given)| {
+            let (password, identity) = self.users.get(&user)?;
+            constant_time_eq(password, &digest(&given)).then(|| identity.clone())
+        });
+        Box::pin(ready(identity))
+    }
+}
+
+/// The SHA-256 digest of a token or password, compared instead of the secret so its length doesn't show
+type Secret = [u8; 32];
+
+fn digest(secret: &str) -> Secret {
+    Sha256::digest(secret).into()
+}
+
+fn constant_time_eq(a: &Secret, b: &Secret) -> bool {
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Authentication for the api served by a [`Board`](crate::service::Board).
+///
+/// Verifiers are tried in order and the first identity found wins.
+/// Reads need [`Role::Viewer`] and everything else needs [`Role::Operator`] on the namespace.
+///
+/// ```rust,ignore
+/// let auth = Auth::new()
+///     .bearer("s3cr3t", Identity::operator("deploy-bot"))
+///     .basic("alice", "hunter2", Identity::viewer("alice").only(["emails"]));
+/// Board::new().add_storage(&storage, "emails").with_auth(auth)
+/// ```
+#[derive(Clone, Default)]
+pub struct Auth {
+    tokens: StaticTokens,
+    users: BasicUsers,
+    verifiers: Vec<Arc<dyn Verifier>>,
+}
+
+impl Auth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bearer(mut self, token: &str, identity: Identity) -> Self {
+        self.tokens.insert(token, identity);
+        self
+    }
+
+    pub fn basic(mut self, user: &str, password: &str, identity: Identity) -> Self {
+        self.users.insert(user, password, identity);
+        self
+    }
+
+    /// Add a custom verifier, tried after the static tokens and users
+    pub fn verifier<V: Verifier>(mut self, verifier: V) -> Self {
+        self.verifiers.push(Arc::new(verifier));
+        self
+    }
+
+    pub async fn identify(&self, headers: &Headers) -> Option<Identity> {
+        if let Some(identity) = self.tokens.verify(headers).await {
+            return Some(identity);
+        }
+        if let Some(identity) = self.users.verify(headers).await {
+            return Some(identity);
+        }
+        for verifier in &self.verifiers {
+            if let Some(identity) = verifier.verify(headers).await {
+                return Some(identity);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bearer(token: &str) -> Headers {
+        [("Authorization", format!("Bearer {token}"))]
+            .into_iter()
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn tokens_are_matched_whatever_their_length() {
+        let auth = Auth::new()
+            .bearer("s3cr3t", Identity::operator("deploy-bot"))
+            .bearer("a-much-longer-token", Identity::viewer("grafana"));
+        let identity = auth.identify(&bearer("s3cr3t")).await;
+        assert_eq!(identity, Some(Identity::operator("deploy-bot")));
+        let identity = auth.identify(&bearer("a-much-longer-token")).await;
+        assert_eq!(identity, Some(Identity::viewer("grafana")));
+        assert_eq!(auth.identify(&bearer("s3cr3")).await, None);
+        assert_eq!(auth.identify(&bearer("s3cr3tt")).await, None);
+        assert_eq!(auth.identify(&Headers::default()).await, None);
+    }
+
+    #[actix_web::test]
+    async fn basic_users_need_their_password() {
+        let auth = Auth::new().basic("alice", "hunter2", Identity::viewer("alice"));
+        let basic = |credentials: &str| -> Headers {
+            [(
+                "authorization",
+                format!("Basic {}", STANDARD.encode(credentials)),
+            )]
+            .into_iter()
+            .collect()
+        };
+        let identity = auth.identify(&basic("alice:hunter2")).await;
+        assert_eq!(identity, Some(Identity::viewer("alice")));
+        assert_eq!(auth.identify(&basic("alice:hunter")).await, None);
+        assert_eq!(auth.identify(&basic("bob:hunter2")).await, None);
+    }
+}
@@ -0,0 +1,610 @@
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use ::axum::{
+    extract::{DefaultBodyLimit, MatchedPath, OriginalUri, Path, Query, Request, State},
+    http::{
+        header::{CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderMap, HeaderValue, Method, StatusCode,
+    },
+    middleware::{from_fn, from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Extension, Json, Router,
+};
+use actix_web::web::Data;
+use apalis_core::{storage::Storage, task::task_id::TaskId};
+use futures::StreamExt;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use shared::{BackendExt, Filter, Namespace, RequestExt};
+
+#[cfg(feature = "federation")]
+use crate::federation::Federation;
+use crate::{
+    audit::{Audit, AuditFilter},
+    auth::{Auth, Headers, Identity},
+    export::{Export, ExportQuery},
+    import::{ImportOptions, MAX_IMPORT_SIZE},
+    metrics::Metrics,
+    redact::{RawQuery, Redaction},
+    retention::Retention,
+    schema::JobSchema,
+    service::{etag_matches, Board, Body, Enqueue, NamespaceError, Reply, IDEMPOTENCY_KEY},
+    sse::Broadcaster,
+};
+
+/// Serves a [`Board`] with axum, the counterpart of [`ApiBuilder`](crate::api::ApiBuilder).
+///
+/// ```rust,ignore
+/// let app = Router::new().nest(
+///     "/api/v1",
+///     RouterBuilder::new().add_storage(&storage, "emails").build(),
+/// );
+/// ```
+pub struct RouterBuilder {
+    board: Board,
+    events: Option<Data<Mutex<Broadcaster>>>,
+}
+
+impl RouterBuilder {
+    pub fn new() -> Self {
+        Self {
+            board: Board::new(),
+            events: None,
+        }
+    }
+
+    pub fn add_storage<J, S>(self, storage: &S, namespace: impl Into<Namespace>) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
+        Self {
+            board: self.board.add_storage(storage, namespace),
+            ..self
+        }
+    }
+
+    /// Serve `storage` with the schema of its jobs, pushed jobs that don't match it are rejected with a 422
+    ///
+    /// ```rust,ignore
+    /// RouterBuilder::new().add_storage_with_schema(&storage, "emails", JobSchema::of::<Email>())
+    /// ```
+    pub fn add_storage_with_schema<J, S>(
+        self,
+        storage: &S,
+        namespace: impl Into<Namespace>,
+        schema: JobSchema,
+    ) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
+        Self {
+            board: self
+                .board
+                .add_storage_with_schema(storage, namespace, schema),
+            ..self
+        }
+    }
+
+    /// Serve the namespaces of the remote boards of `federation` as `{source}:{namespace}`
+    #[cfg(feature = "federation")]
+    pub fn federate(self, federation: &Federation) -> Self {
+        Self {
+            board: self.board.federate(federation),
+            ..self
+        }
+    }
+
+    /// Keep the stats of the storages added after this for `ttl`, a second by default
+    pub fn cache_stats_for(self, ttl: Duration) -> Self {
+        Self {
+            board: self.board.cache_stats_for(ttl),
+            ..self
+        }
+    }
+
+    /// Require every request to be authenticated by `auth`
+    pub fn with_auth(self, auth: Auth) -> Self {
+        Self {
+            board: self.board.with_auth(auth),
+            ..self
+        }
+    }
+
+    /// Describe the jobs of `namespace`, pushed jobs that don't match the schema are rejected with a 422
+    pub fn job_schema(self, namespace: &str, schema: JobSchema) -> Self {
+        Self {
+            board: self.board.job_schema(namespace, schema),
+            ..self
+        }
+    }
+
+    /// List the jobs of `namespace` with payloads of up to `bytes` of json, larger ones are cut to a preview
+    /// and only `GET /backend/{namespace}/job/{job_id}` serves them in full. 4 KiB by default.
+    pub fn preview_limit(self, namespace: &str, bytes: usize) -> Self {
+        Self {
+            board: self.board.preview_limit(namespace, bytes),
+            ..self
+        }
+    }
+
+    /// Record every mutating call in `audit`, readable at `GET /backend/audit`
+    pub fn with_audit(self, audit: Audit) -> Self {
+        Self {
+            board: self.board.with_audit(audit),
+            ..self
+        }
+    }
+
+    /// Serve `metrics` at `GET /backend/metrics` and count every request of the api in it
+    pub fn with_metrics(self, metrics: Metrics) -> Self {
+        Self {
+            board: self.board.with_metrics(metrics),
+            ..self
+        }
+    }
+
+    /// Remove finished jobs per the rules of `retention`, its runs are readable at `GET /backend/retention`
+    pub fn with_retention(self, retention: Retention) -> Self {
+        Self {
+            board: self.board.with_retention(retention),
+            ..self
+        }
+    }
+
+    /// Redact the payloads of jobs before they leave the api, admins can ask for them with `?raw=true`
+    pub fn with_redaction(self, redaction: Redaction) -> Self {
+        Self {
+            board: self.board.with_redaction(redaction),
+            ..self
+        }
+    }
+
+    /// Remember the jobs pushed with an `Idempotency-Key` header for `ttl`, a day by default
+    pub fn remember_keys_for(self, ttl: Duration) -> Self {
+        Self {
+            board: self.board.remember_keys_for(ttl),
+            ..self
+        }
+    }
+
+    /// Serve the events sent to `broadcaster` at `GET /backend/events`, redacted like the payloads of the board
+    pub fn events(mut self, broadcaster: &Data<Mutex<Broadcaster>>) -> Self {
+        self.events = Some(broadcaster.clone());
+        self.board = self.board.serving_events();
+        self
+    }
+
+    /// The routes of the board under `/backend`, to be nested wherever the api lives.
+    /// Panics on a [`NamespaceError`].
+    pub fn build(self) -> Router {
+        self.try_build().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Builds the routes unless two namespaces collide or a route of the board shadows one
+    pub fn try_build(self) -> Result<Router, NamespaceError> {
+        self.board.check()?;
+        self.board.start_retention();
+        let router = Router::new()
+            .route("/backend", get(list_namespaces))
+            .route("/backend/groups", get(list_groups))
+            .route("/backend/openapi.json", get(get_openapi))
+            .route("/backend/healthz", get(healthz))
+            .route("/backend/readyz", get(readyz));
+        // Registered only when used, `Board::check` reserves their names either way
+        let router = match self.events {
+            Some(broadcaster) => {
+                if let Ok(mut broadcaster) = broadcaster.lock() {
+                    broadcaster.set_redaction(self.board.redaction().clone());
+                }
+                router.route("/backend/events", get(events).layer(Extension(broadcaster)))
+            }
+            None => router,
+        };
+        let router = if self.board.has_audit() {
+            router.route("/backend/audit", get(list_audit))
+        } else {
+            router
+        };
+        let router = if self.board.has_retention() {
+            router.route("/backend/retention", get(list_retention))
+        } else {
+            router
+        };
+        let router = router
+            .route("/backend/:namespace", get(list_jobs))
+            .route("/backend/:namespace/workers", get(list_workers))
+            .route("/backend/:namespace/export", get(export_jobs))
+            .route("/backend/:namespace/job", put(push_job))
+            .route("/backend/:namespace/jobs", put(push_jobs))
+            .route(
+                "/backend/:namespace/import",
+                put(import_jobs).layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+            )
+            .route("/backend/:namespace/job/:job_id", get(get_job))
+            .route("/backend/:namespace/schema", get(get_schema));
+        let router = match self.board.metrics().cloned() {
+            Some(metrics) => router
+                .route("/backend/metrics", get(get_metrics))
+                .route_layer(from_fn_with_state(metrics, track)),
+            None => router,
+        };
+        Ok(router
+            .layer(from_fn(not_modified))
+            .with_state(Arc::new(self.board)))
+    }
+}
+
+impl Default for RouterBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Board> for RouterBuilder {
+    fn from(board: Board) -> Self {
+        Self {
+            board,
+            events: None,
+        }
+    }
+}
+
+/// Serves the embedded frontend, meant to be the fallback of the app
+/// so every path the api doesn't handle reaches it.
+///
+/// ```rust,ignore
+/// let app = Router::new()
+///     .nest("/api/v1", RouterBuilder::new().add_storage(&storage, "emails").build())
+///     .fallback(ui);
+/// ```
+#[cfg(feature = "ui")]
+pub async fn ui(uri: ::axum::http::Uri) -> Response {
+    use ::axum::http::header::CACHE_CONTROL;
+
+    let Some(asset) = crate::ui::resolve(uri.path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let cache_control = asset.cache_control();
+    (
+        [
+            (CONTENT_TYPE, asset.mime),
+            (CACHE_CONTROL, cache_control.to_string()),
+        ],
+        asset.body,
+    )
+        .into_response()
+}
+
+impl IntoResponse for Reply {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let etag = self.etag();
+        let mut res = match self.body {
+            Body::Empty => status.into_response(),
+            Body::Text(text) => (status, text).into_response(),
+            Body::Json(value) => (status, Json(value)).into_response(),
+            Body::Raw { content_type, body } => {
+                (status, [(CONTENT_TYPE, content_type)], body).into_response()
+            }
+        };
+        if let Some(etag) = etag.and_then(|etag| HeaderValue::from_str(&etag).ok()) {
+            // Revalidated every time, `not_modified` answers an unchanged listing with a 304
+            res.headers_mut().insert(ETAG, etag);
+            res.headers_mut()
+                .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        }
+        res
+    }
+}
+
+impl IntoResponse for Export {
+    fn into_response(self) -> Response {
+        let disposition = format!(
+            "attachment; filename=\"{}\"",
+            self.filename.replace(['"', '\\'], "_")
+        );
+        let chunks = self
+            .chunks
+            .map(|chunk| chunk.map_err(std::io::Error::other));
+        (
+            [
+                (CONTENT_TYPE, self.content_type.to_string()),
+                (CONTENT_DISPOSITION, disposition),
+            ],
+            ::axum::body::Body::from_stream(chunks),
+        )
+            .into_response()
+    }
+}
+
+type Shared = State<Arc<Board>>;
+
+async fn identify(board: &Board, headers: &HeaderMap) -> Result<Identity, Response> {
+    let headers: Headers = headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect();
+    board
+        .identify(&headers)
+        .await
+        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "unauthorized").into_response())
+}
+
+/// Counts the requests of the api
+async fn track(State(metrics): State<Metrics>, req: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let res = next.run(req).await;
+    metrics.observe(&method, &route, res.status().as_u16(), start.elapsed());
+    res
+}
+
+/// Answers a `GET` whose `If-None-Match` matches the `ETag` of its reply with a 304
+async fn not_modified(req: Request, next: Next) -> Response {
+    let if_none_match = (req.method() == Method::GET)
+        .then(|| {
+            req.headers()
+                .get(IF_NONE_MATCH)?
+                .to_str()
+                .ok()
+                .map(str::to_string)
+        })
+        .flatten();
+    let res = next.run(req).await;
+    let etag = res.headers().get(ETAG).cloned();
+    match (if_none_match, etag) {
+        (Some(tags), Some(etag))
+            if res.status() == StatusCode::OK
+                && etag.to_str().is_ok_and(|etag| etag_matches(&tags, etag)) =>
+        {
+            (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response()
+        }
+        _ => res,
+    }
+}
+
+async fn events(
+    State(board): Shared,
+    Extension(broadcaster): Extension<Data<Mutex<Broadcaster>>>,
+    headers: HeaderMap,
+) -> Result<Response, Response> {
+    let identity = identify(&board, &headers).await?;
+    let Ok(mut broadcaster) = broadcaster.lock() else {
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+    };
+    let client = broadcaster.new_client_for(&identity);
+    Ok((
+        [
+            (CONTENT_TYPE, "text/event-stream"),
+            (CACHE_CONTROL, "no-cache"),
+        ],
+        ::axum::body::Body::from_stream(client),
+    )
+        .into_response())
+}
+
+async fn healthz(State(board): Shared) -> Reply {
+    board.healthz()
+}
+
+async fn readyz(State(board): Shared, headers: HeaderMap) -> Reply {
+    let identity = identify(&board, &headers).await.ok();
+    board.readyz(identity.as_ref()).await
+}
+
+async fn get_metrics(State(board): Shared, headers: HeaderMap) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.render_metrics(&identity).await)
+}
+
+async fn list_namespaces(State(board): Shared, headers: HeaderMap) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.list_namespaces(&identity).await)
+}
+
+async fn list_groups(State(board): Shared, headers: HeaderMap) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.list_groups(&identity).await)
+}
+
+async fn get_openapi(
+    State(board): Shared,
+    headers: HeaderMap,
+    OriginalUri(uri): OriginalUri,
+) -> Result<Json<Value>, Response> {
+    let identity = identify(&board, &headers).await?;
+    // The document is relative to wherever the router was nested
+    let server = uri
+        .path()
+        .strip_suffix("/backend/openapi.json")
+        .unwrap_or_default();
+    let mut doc = board.openapi(&identity);
+    doc["servers"] = json!([{ "url": if server.is_empty() { "/" } else { server } }]);
+    Ok(Json(doc))
+}
+
+async fn list_jobs(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+    Query(filter): Query<Filter>,
+    Query(query): Query<RawQuery>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board
+        .list_jobs(&identity, &namespace, &filter, query.raw)
+        .await)
+}
+
+async fn list_workers(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.list_workers(&identity, &namespace).await)
+}
+
+async fn export_jobs(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> Result<Export, Response> {
+    let identity = identify(&board, &headers).await?;
+    board
+        .export_jobs(&identity, &namespace, &query)
+        .map_err(IntoResponse::into_response)
+}
+
+async fn push_job(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+    Json(payload): Json<Value>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    let key = headers.get(IDEMPOTENCY_KEY).and_then(|v| v.to_str().ok());
+    Ok(board.push_job(&identity, &namespace, payload, key).await)
+}
+
+async fn push_jobs(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+    Json(batch): Json<Vec<Enqueue>>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    let key = headers.get(IDEMPOTENCY_KEY).and_then(|v| v.to_str().ok());
+    Ok(board.push_jobs(&identity, &namespace, batch, key).await)
+}
+
+async fn import_jobs(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+    Query(options): Query<ImportOptions>,
+    ndjson: String,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board
+        .import_jobs(&identity, &namespace, &ndjson, &options)
+        .await)
+}
+
+async fn get_job(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path((namespace, job_id)): Path<(String, TaskId)>,
+    Query(query): Query<RawQuery>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board
+        .get_job(&identity, &namespace, &job_id, query.raw)
+        .await)
+}
+
+async fn get_schema(
+    State(board): Shared,
+    headers: HeaderMap,
+    Path(namespace): Path<String>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.get_schema(&identity, &namespace))
+}
+
+async fn list_retention(State(board): Shared, headers: HeaderMap) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.list_retention(&identity))
+}
+
+async fn list_audit(
+    State(board): Shared,
+    headers: HeaderMap,
+    Query(filter): Query<AuditFilter>,
+) -> Result<Reply, Response> {
+    let identity = identify(&board, &headers).await?;
+    Ok(board.list_audit(&identity, &filter).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::axum::{body::Body as HttpBody, http::Request as HttpRequest};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::testing::Fake;
+
+    fn get(uri: &str, token: Option<&str>) -> HttpRequest<HttpBody> {
+        let req = HttpRequest::get(uri);
+        let req = match token {
+            Some(token) => req.header("authorization", format!("Bearer {token}")),
+            None => req,
+        };
+        req.body(HttpBody::empty()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn events_are_served_to_authenticated_clients() {
+        let broadcaster = Broadcaster::create();
+        let auth = Auth::new().bearer("s3cr3t", Identity::viewer("alice").only(["emails"]));
+        let board = Board::new()
+            .add_fake("emails", Arc::new(Fake::default()))
+            .with_auth(auth);
+        let router = RouterBuilder::from(board).events(&broadcaster).build();
+
+        let res = router
+            .clone()
+            .oneshot(get("/backend/events", None))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = router
+            .clone()
+            .oneshot(get("/backend/events", Some("s3cr3t")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[CONTENT_TYPE], "text/event-stream");
+        let mut body = res.into_body().into_data_stream();
+        let connected = body.next().await.unwrap().unwrap();
+        assert_eq!(&connected[..], b"data: connected\n\n");
+
+        broadcaster
+            .lock()
+            .unwrap()
+            .send_event(&shared::BoardEvent::QueueUpdated {
+                namespace: "emails".to_string(),
+                state: shared::JobState::Pending,
+            });
+        let event = body.next().await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&event).contains("queue_updated"));
+
+        let res = router
+            .oneshot(get("/backend/emails", Some("s3cr3t")))
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
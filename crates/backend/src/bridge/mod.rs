@@ -0,0 +1,9 @@
+//! Background tasks that feed changes made by workers in other processes into the [`Broadcaster`](crate::sse::Broadcaster)
+
+pub mod poll;
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+#[cfg(feature = "redis")]
+pub mod redis;
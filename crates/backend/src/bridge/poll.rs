@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use actix_web::web::Data;
+use apalis_core::task::task_id::TaskId;
+use shared::{BackendExt, BoardEvent, JobState, RequestExt, Stat};
+use tokio::task::JoinHandle;
+
+use crate::sse::Broadcaster;
+
+/// States that can be listed from a backend, `Scheduled` jobs are listed as pending
+const STATES: [JobState; 5] = [
+    JobState::Pending,
+    JobState::Running,
+    JobState::Failed,
+    JobState::Dead,
+    JobState::Success,
+];
+
+fn count(stat: &Stat, state: &JobState) -> usize {
+    match state {
+        JobState::Pending | JobState::Scheduled => stat.pending,
+        JobState::Running => stat.running,
+        JobState::Dead => stat.dead,
+        JobState::Failed => stat.failed,
+        JobState::Success => stat.success,
+    }
+}
+
+/// Synthesizes board events for backends without push notifications.
+///
+/// Every `interval` it compares [`BackendExt::stats`] and the first `pages` of
+/// [`BackendExt::list_jobs`] for each state with the previous run and emits
+/// [`BoardEvent::JobUpdated`] for listed jobs that moved to another state and
+/// [`BoardEvent::QueueUpdated`] for states whose counts changed, new jobs included.
+/// The progress persisted for jobs it sees finish is cleared.
+///
+/// Create it once and share clones of it, like [`Retention`](crate::retention::Retention),
+/// so a single task polls the storage however many workers the server has.
+#[derive(Debug)]
+pub struct Watcher<J, S> {
+    storage: S,
+    interval: Duration,
+    pages: i32,
+    started: Arc<AtomicBool>,
+    _job: PhantomData<fn() -> J>,
+}
+
+impl<J, S: Clone> Clone for Watcher<J, S> {
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            interval: self.interval,
+            pages: self.pages,
+            started: self.started.clone(),
+            _job: PhantomData,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Snapshot {
+    stats: Stat,
+    states: HashMap<String, JobState>,
+}
+
+impl<J, S> Watcher<J, S>
+where
+    S: BackendExt<J> + Send + Sync + 'static,
+    S::Request: RequestExt + Send,
+{
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            interval: Duration::from_secs(5),
+            pages: 1,
+            started: Arc::default(),
+            _job: PhantomData,
+        }
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// How often to poll the backend, defaults to 5 seconds
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How many pages of each state to compare, defaults to 1
+    pub fn pages(mut self, pages: i32) -> Self {
+        self.pages = pages.max(1);
+        self
+    }
+
+    /// Polls the backend forever, sending its events as those of `namespace`
+    pub async fn run(self, namespace: String, broadcaster: Data<Mutex<Broadcaster>>) {
+        let mut interval = tokio::time::interval(self.interval);
+        let mut previous: Option<Snapshot> = None;
+        loop {
+            interval.tick().await;
+            let Some(current) = self.snapshot().await else {
+                continue;
+            };
+            if let Some(previous) = &previous {
+                let events = diff(&namespace, previous, &current);
+                if let Ok(broadcaster) = broadcaster.lock() {
+                    for event in &events {
+                        broadcaster.send_event(event);
+                    }
+                }
+                let finished: Vec<TaskId> = events
+                    .iter()
+                    .filter_map(|event| match event {
+                        BoardEvent::JobUpdated { task_id, to, .. } if to.is_finished() => {
+                            task_id.parse().ok()
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                // Progress is best effort, it is cleared again when the job is purged
+                let _ = self.storage.clear_progress(&finished).await;
+            }
+            previous = Some(current);
+        }
+    }
+
+    /// Runs the watcher in the background, only the first call of all the clones does
+    pub fn spawn(
+        &self,
+        namespace: &str,
+        broadcaster: Data<Mutex<Broadcaster>>,
+    ) -> Option<JoinHandle<()>>
+    where
+        S: Clone,
+    {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return None;
+        }
+        Some(tokio::spawn(
+            self.clone().run(namespace.to_string(), broadcaster),
+        ))
+    }
+
+    /// Reads the backend, `None` if any query failed so a flaky backend doesn't look like a mass change
+    async fn snapshot(&self) -> Option<Snapshot> {
+        let stats = self.storage.stats().await.ok()?;
+        let mut states = HashMap::new();
+        for state in STATES {
+            for page in 1..=self.pages {
+                let jobs = self.storage.list_jobs(&state, page).await.ok()?;
+                if jobs.is_empty() {
+                    break;
+                }
+                for job in jobs {
+                    states.insert(job.task_id().to_string(), state);
+                }
+            }
+        }
+        Some(Snapshot { stats, states })
+    }
+}
+
+/// Jobs seen in both snapshots in different states, and states whose counts changed.
+/// A job missing from the previous snapshot may as well have scrolled onto the listed pages,
+/// so only the count of its state tells it apart.
+fn diff(namespace: &str, previous: &Snapshot, current: &Snapshot) -> Vec<BoardEvent> {
+    let mut events: Vec<BoardEvent> = current
+        .states
+        .iter()
+        .filter_map(|(task_id, to)| {
+            let from = *previous.states.get(task_id)?;
+            (from != *to).then(|| BoardEvent::JobUpdated {
+                namespace: namespace.to_string(),
+                task_id: task_id.clone(),
+                from: Some(from),
+                to: *to,
+            })
+        })
+        .collect();
+    events.extend(
+        STATES
+            .iter()
+            .filter(|state| count(&previous.stats, state) != count(&current.stats, state))
+            .map(|state| BoardEvent::QueueUpdated {
+                namespace: namespace.to_string(),
+                state: *state,
+            }),
+    );
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn snapshot(pending: usize, running: usize, states: &[(&str, JobState)]) -> Snapshot {
+        Snapshot {
+            stats: Stat {
+                pending,
+                running,
+                ..Stat::default()
+            },
+            states: states
+                .iter()
+                .map(|(task_id, state)| (task_id.to_string(), *state))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn only_jobs_that_moved_are_updated() {
+        let previous = snapshot(11, 0, &[("a", JobState::Pending), ("b", JobState::Pending)]);
+        // `a` started running, `c` scrolled onto the first page of pending jobs
+        let current = snapshot(
+            10,
+            1,
+            &[
+                ("a", JobState::Running),
+                ("b", JobState::Pending),
+                ("c", JobState::Pending),
+            ],
+        );
+        let events = serde_json::to_value(diff("emails", &previous, &current)).unwrap();
+        assert_eq!(
+            events,
+            json!([
+                { "event": "job_updated", "namespace": "emails", "task_id": "a", "from": "Pending", "to": "Running" },
+                { "event": "queue_updated", "namespace": "emails", "state": "Pending" },
+                { "event": "queue_updated", "namespace": "emails", "state": "Running" },
+            ])
+        );
+    }
+
+    #[test]
+    fn new_jobs_only_update_their_queue() {
+        let previous = snapshot(0, 0, &[]);
+        let current = snapshot(1, 0, &[("a", JobState::Pending)]);
+        let events = serde_json::to_value(diff("emails", &previous, &current)).unwrap();
+        assert_eq!(
+            events,
+            json!([{ "event": "queue_updated", "namespace": "emails", "state": "Pending" }])
+        );
+    }
+
+    #[actix_web::test]
+    async fn sqlite_storages_are_watched_through_the_api() {
+        use actix_web::{test, App};
+        use apalis_sql::sqlite::{SqlitePool, SqliteStorage};
+        use futures::StreamExt;
+        use serde_json::Value;
+
+        use crate::api::ApiBuilder;
+
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        SqliteStorage::setup(&pool).await.unwrap();
+        shared::sqlite::setup(&pool).await.unwrap();
+        let storage: SqliteStorage<Value> = SqliteStorage::new(pool.clone());
+        let interval = Duration::from_millis(50);
+        let watcher = Watcher::new(storage).interval(interval);
+        let broadcaster = Broadcaster::create();
+        let mut client = broadcaster.lock().unwrap().new_client();
+        let app = test::init_service(
+            App::new().service(
+                ApiBuilder::new()
+                    .add_storage_watched(&watcher, "emails", &broadcaster)
+                    .build(),
+            ),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/backend/emails/job")
+            .set_json(json!({ "to": "test@example.com" }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 200);
+        // Seen pending by a few polls before it starts
+        tokio::time::sleep(interval * 4).await;
+        let (task_id,): (String,) = sqlx::query_as("SELECT id FROM Jobs")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        sqlx::query("UPDATE Jobs SET status = 'Running' WHERE id = ?")
+            .bind(&task_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let updated = async {
+            while let Some(Ok(msg)) = client.next().await {
+                let msg = String::from_utf8_lossy(&msg);
+                let data = msg.trim_end().trim_start_matches("data: ");
+                if let Ok(event @ BoardEvent::JobUpdated { .. }) =
+                    serde_json::from_str::<BoardEvent>(data)
+                {
+                    return Some(event);
+                }
+            }
+            None
+        };
+        let updated = tokio::time::timeout(Duration::from_secs(5), updated)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(updated).unwrap(),
+            json!({ "event": "job_updated", "namespace": "emails", "task_id": task_id, "from": "Pending", "to": "Running" })
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use actix_web::web::Data;
+use serde::Deserialize;
+use shared::{BoardEvent, JobState};
+use sqlx::postgres::{PgListener, PgPool};
+use tokio::task::JoinHandle;
+
+use crate::sse::Broadcaster;
+
+/// The channel the board's trigger notifies on
+const CHANNEL: &str = "apalis::board";
+
+const SETUP: &str = r#"
+CREATE OR REPLACE FUNCTION apalis.board_notify_job() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' OR NEW.status IS DISTINCT FROM OLD.status THEN
+        PERFORM pg_notify('apalis::board', json_build_object(
+            'id', NEW.id,
+            'job_type', NEW.job_type,
+            'old_status', CASE WHEN TG_OP = 'UPDATE' THEN OLD.status ELSE NULL END,
+            'status', NEW.status
+        )::text);
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS board_notify_job ON apalis.jobs;
+
+CREATE TRIGGER board_notify_job
+    AFTER INSERT OR UPDATE OF status ON apalis.jobs
+    FOR EACH ROW EXECUTE FUNCTION apalis.board_notify_job();
+"#;
+
+#[derive(Debug, Deserialize)]
+struct Notification {
+    id: String,
+    job_type: String,
+    old_status: Option<String>,
+    status: String,
+}
+
+/// Turns status changes on `apalis.jobs` into [`BoardEvent::JobUpdated`] events.
+///
+/// ```rust,ignore
+/// let bridge = PostgresBridge::new(pool).alias("email::Email", "emails");
+/// bridge.setup().await?;
+/// bridge.spawn(broadcaster.clone());
+/// ```
+#[derive(Debug, Clone)]
+pub struct PostgresBridge {
+    pool: PgPool,
+    aliases: HashMap<String, String>,
+}
+
+impl PostgresBridge {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            aliases: HashMap::new(),
+        }
+    }
+
+    /// Report jobs of `job_type` under the namespace they were registered with in `ApiBuilder`.
+    /// Without an alias the job type is used as the namespace.
+    pub fn alias(mut self, job_type: &str, namespace: &str) -> Self {
+        self.aliases
+            .insert(job_type.to_string(), namespace.to_string());
+        self
+    }
+
+    /// Installs the trigger that notifies the board about changes to `apalis.jobs`.
+    /// Should run after `PostgresStorage::setup`.
+    pub async fn setup(&self) -> Result<(), sqlx::Error> {
+        sqlx::raw_sql(SETUP).execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Listens for notifications until the connection fails
+    pub async fn run(self, broadcaster: Data<Mutex<Broadcaster>>) -> Result<(), sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANNEL).await?;
+        loop {
+            let notification = listener.recv().await?;
+            let Ok(change) = serde_json::from_str::<Notification>(notification.payload()) else {
+                continue;
+            };
+            if let Some(event) = self.to_event(change) {
+                if let Ok(broadcaster) = broadcaster.lock() {
+                    broadcaster.send_event(&event);
+                }
+            }
+        }
+    }
+
+    /// Runs the bridge in the background
+    pub fn spawn(
+        self,
+        broadcaster: Data<Mutex<Broadcaster>>,
+    ) -> JoinHandle<Result<(), sqlx::Error>> {
+        tokio::spawn(self.run(broadcaster))
+    }
+
+    fn to_event(&self, change: Notification) -> Option<BoardEvent> {
+        let to = JobState::from_sql_status(&change.status)?;
+        let from = change
+            .old_status
+            .as_deref()
+            .and_then(JobState::from_sql_status);
+        let namespace = self
+            .aliases
+            .get(&change.job_type)
+            .cloned()
+            .unwrap_or(change.job_type);
+        Some(BoardEvent::JobUpdated {
+            namespace,
+            task_id: change.id,
+            from,
+            to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn notifications_become_job_updates() {
+        let pool = PgPool::connect_lazy("postgres://localhost/apalis").unwrap();
+        let bridge = PostgresBridge::new(pool).alias("email::Email", "emails");
+        let event = |payload: serde_json::Value| {
+            let change: Notification = serde_json::from_value(payload).unwrap();
+            serde_json::to_value(bridge.to_event(change)).unwrap()
+        };
+
+        let started = json!({ "id": "1", "job_type": "email::Email", "old_status": "Pending", "status": "Running" });
+        assert_eq!(
+            event(started),
+            json!({ "event": "job_updated", "namespace": "emails", "task_id": "1", "from": "Pending", "to": "Running" })
+        );
+        let inserted =
+            json!({ "id": "2", "job_type": "sms::Sms", "old_status": null, "status": "Pending" });
+        assert_eq!(
+            event(inserted),
+            json!({ "event": "job_updated", "namespace": "sms::Sms", "task_id": "2", "from": null, "to": "Pending" })
+        );
+        let unknown =
+            json!({ "id": "3", "job_type": "sms::Sms", "old_status": null, "status": "Archived" });
+        assert_eq!(event(unknown), serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{pin::pin, sync::Mutex, time::Duration};
+
+use actix_web::web::Data;
+use apalis_redis::RedisStorage;
+use futures::{
+    future::{select, Either},
+    StreamExt,
+};
+use redis::{aio::ConnectionLike, Client, RedisError};
+use shared::{BoardEvent, JobState};
+use tokio::task::JoinHandle;
+
+use crate::sse::Broadcaster;
+
+/// The keyspace events the bridge needs: keyspace (K), generic (g), list (l) and sorted set (z)
+const KEYSPACE_EVENTS: &str = "Kglz";
+
+#[derive(Debug, Clone)]
+struct Watched {
+    namespace: String,
+    /// Prefix shared by all the keys of the queue
+    prefix: String,
+    channel: String,
+    keys: Vec<(String, JobState)>,
+}
+
+impl Watched {
+    fn state_of(&self, key: &str) -> Option<JobState> {
+        self.keys
+            .iter()
+            .find(|(k, _)| key == k || key.starts_with(&format!("{k}:")))
+            .map(|(_, state)| *state)
+    }
+}
+
+/// Queue updates waiting to be sent, each namespace and state once
+#[derive(Debug, Default)]
+struct Coalesced(Vec<(String, JobState)>);
+
+impl Coalesced {
+    fn add(&mut self, namespace: &str, state: JobState) {
+        if !self.0.iter().any(|(n, s)| n == namespace && *s == state) {
+            self.0.push((namespace.to_string(), state));
+        }
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = BoardEvent> + '_ {
+        self.0
+            .drain(..)
+            .map(|(namespace, state)| BoardEvent::QueueUpdated { namespace, state })
+    }
+}
+
+/// Turns changes to the sets and lists of [`RedisStorage`] queues into board events.
+///
+/// Keyspace notifications only say which set changed, so they become [`BoardEvent::QueueUpdated`].
+/// Every push notifies, so they are coalesced and each changed state of a namespace is sent once per window.
+/// Services that know more can [`publish`] any [`BoardEvent`] to the queue's board channel, those are sent right away.
+///
+/// ```rust,ignore
+/// RedisBridge::new(client)
+///     .watch(&storage, "apalis::redis")
+///     .spawn(broadcaster.clone());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RedisBridge {
+    client: Client,
+    watched: Vec<Watched>,
+    window: Duration,
+}
+
+impl RedisBridge {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            watched: Vec::new(),
+            window: Duration::from_millis(500),
+        }
+    }
+
+    /// How long keyspace notifications are coalesced for, half a second by default
+    pub fn coalesce(mut self, window: Duration) -> Self {
+        self.window = window.max(Duration::from_millis(1));
+        self
+    }
+
+    /// Watch the queue of `storage` and report it under `namespace`
+    pub fn watch<T>(mut self, storage: &RedisStorage<T>, namespace: &str) -> Self {
+        let config = storage.get_config();
+        self.watched.push(Watched {
+            namespace: namespace.to_string(),
+            prefix: format!("{}:", config.get_namespace()),
+            channel: board_channel(storage),
+            keys: vec![
+                (config.active_jobs_list(), JobState::Pending),
+                (config.scheduled_jobs_set(), JobState::Scheduled),
+                (config.inflight_jobs_set(), JobState::Running),
+                (config.done_jobs_set(), JobState::Success),
+                (config.failed_jobs_set(), JobState::Failed),
+                (config.dead_jobs_set(), JobState::Dead),
+            ],
+        });
+        self
+    }
+
+    /// Enables the keyspace notifications the bridge relies on, keeping any already configured
+    pub async fn setup(&self) -> Result<(), RedisError> {
+        let mut conn = self.client.get_multiplexed_async_connection().await?;
+        let (_, current): (String, String) = redis::cmd("CONFIG")
+            .arg("GET")
+            .arg("notify-keyspace-events")
+            .query_async(&mut conn)
+            .await?;
+        let mut events = current;
+        for flag in KEYSPACE_EVENTS.chars() {
+            if !events.contains(flag) && !(events.contains('A') && flag != 'K') {
+                events.push(flag);
+            }
+        }
+        redis::cmd("CONFIG")
+            .arg("SET")
+            .arg("notify-keyspace-events")
+            .arg(events)
+            .query_async(&mut conn)
+            .await
+    }
+
+    /// Forwards notifications until the connection fails
+    pub async fn run(self, broadcaster: Data<Mutex<Broadcaster>>) -> Result<(), RedisError> {
+        let mut pubsub = self.client.get_async_pubsub().await?;
+        for watched in &self.watched {
+            pubsub
+                .psubscribe(format!("__keyspace@*__:{}*", watched.prefix))
+                .await?;
+            pubsub.subscribe(&watched.channel).await?;
+        }
+        let mut messages = pubsub.on_message();
+        let mut flush = tokio::time::interval(self.window);
+        let mut coalesced = Coalesced::default();
+        loop {
+            let msg = match select(messages.next(), pin!(flush.tick())).await {
+                Either::Left((Some(msg), _)) => msg,
+                Either::Left((None, _)) => break,
+                Either::Right(_) => {
+                    send(&broadcaster, coalesced.drain());
+                    continue;
+                }
+            };
+            let channel = msg.get_channel_name();
+            if self.watched.iter().any(|w| w.channel == channel) {
+                let event = msg
+                    .get_payload::<String>()
+                    .ok()
+                    .and_then(|payload| serde_json::from_str::<BoardEvent>(&payload).ok());
+                send(&broadcaster, event);
+            } else if let Some((namespace, state)) = self.keyspace_change(channel) {
+                coalesced.add(namespace, state);
+            }
+        }
+        send(&broadcaster, coalesced.drain());
+        Ok(())
+    }
+
+    /// Runs the bridge in the background
+    pub fn spawn(
+        self,
+        broadcaster: Data<Mutex<Broadcaster>>,
+    ) -> JoinHandle<Result<(), RedisError>> {
+        tokio::spawn(self.run(broadcaster))
+    }
+
+    /// The namespace and state of the key a keyspace notification is about
+    fn keyspace_change(&self, channel: &str) -> Option<(&str, JobState)> {
+        let (_, key) = channel.split_once("__:")?;
+        self.watched
+            .iter()
+            .filter(|w| key.starts_with(&w.prefix))
+            .find_map(|w| w.state_of(key).map(|state| (w.namespace.as_str(), state)))
+    }
+}
+
+fn send(broadcaster: &Mutex<Broadcaster>, events: impl IntoIterator<Item = BoardEvent>) {
+    if let Ok(broadcaster) = broadcaster.lock() {
+        for event in events {
+            broadcaster.send_event(&event);
+        }
+    }
+}
+
+/// The pub/sub channel the bridge listens on for events about the queue of `storage`
+pub fn board_channel<T>(storage: &RedisStorage<T>) -> String {
+    format!("{}:board", storage.get_config().get_namespace())
+}
+
+/// Publish an event for the boards bridging the queue of `storage`
+pub async fn publish<T, C>(
+    conn: &mut C,
+    storage: &RedisStorage<T>,
+    event: &BoardEvent,
+) -> Result<(), RedisError>
+where
+    C: ConnectionLike + Send,
+{
+    let payload = serde_json::to_string(event).map_err(|e| {
+        RedisError::from((redis::ErrorKind::IoError, "Encode error", e.to_string()))
+    })?;
+    redis::cmd("PUBLISH")
+        .arg(board_channel(storage))
+        .arg(payload)
+        .query_async(conn)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_are_sent_once_per_namespace_and_state() {
+        let mut coalesced = Coalesced::default();
+        for _ in 0..100 {
+            coalesced.add("emails", JobState::Pending);
+        }
+        coalesced.add("emails", JobState::Running);
+        coalesced.add("invoices", JobState::Pending);
+
+        let events = serde_json::to_value(coalesced.drain().collect::<Vec<_>>()).unwrap();
+        assert_eq!(
+            events,
+            serde_json::json!([
+                { "event": "queue_updated", "namespace": "emails", "state": "Pending" },
+                { "event": "queue_updated", "namespace": "emails", "state": "Running" },
+                { "event": "queue_updated", "namespace": "invoices", "state": "Pending" },
+            ])
+        );
+        assert_eq!(coalesced.drain().count(), 0);
+    }
+
+    #[test]
+    fn keyspace_notifications_name_their_queue() {
+        let bridge = RedisBridge {
+            client: Client::open("redis://127.0.0.1").unwrap(),
+            watched: vec![Watched {
+                namespace: "emails".to_string(),
+                prefix: "emails:".to_string(),
+                channel: "emails:board".to_string(),
+                keys: vec![
+                    ("emails:active".to_string(), JobState::Pending),
+                    ("emails:inflight".to_string(), JobState::Running),
+                ],
+            }],
+            window: Duration::from_millis(500),
+        };
+        assert_eq!(
+            bridge.keyspace_change("__keyspace@0__:emails:inflight:worker-1"),
+            Some(("emails", JobState::Running))
+        );
+        assert_eq!(
+            bridge.keyspace_change("__keyspace@0__:emails:active"),
+            Some(("emails", JobState::Pending))
+        );
+        assert_eq!(
+            bridge.keyspace_change("__keyspace@0__:invoices:active"),
+            None
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use std::sync::Arc;
+
+use futures::stream::{self, BoxStream, StreamExt};
+use serde_json::{Map, Value};
+use shared::JobState;
+pub use shared::{ExportQuery, Format};
+
+use crate::{redact::Redaction, service::Queue};
+
+/// Jobs being exported, each item is a chunk of the response.
+///
+/// The status is sent before the first page is read, so a storage failing midway ends the stream with an error
+/// and the adapters abort the response instead of answering with a truncated file.
+pub struct Export {
+    pub content_type: &'static str,
+    /// The file name suggested in `Content-Disposition`
+    pub filename: String,
+    pub chunks: BoxStream<'static, Result<String, String>>,
+}
+
+/// CSV exports need columns, these are used when none are asked for
+const DEFAULT_COLUMNS: [&str; 2] = ["task_id", "args"];
+
+/// Jobs per page of the storage listings, a shorter page is the last one
+const PAGE_SIZE: usize = 10;
+
+impl Export {
+    pub(crate) fn new(
+        queue: Arc<dyn Queue>,
+        namespace: &str,
+        query: &ExportQuery,
+        redaction: Option<Redaction>,
+    ) -> Self {
+        let format = query.format;
+        let columns: Option<Vec<String>> = match &query.columns {
+            Some(columns) => Some(
+                columns
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|column| !column.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+            None if format == Format::Csv => {
+                Some(DEFAULT_COLUMNS.iter().map(|c| c.to_string()).collect())
+            }
+            None => None,
+        };
+        let header = match (&columns, format) {
+            (Some(columns), Format::Csv) => Some(Ok(csv_row(columns.iter().map(|c| c.as_str())))),
+            _ => None,
+        };
+        let status = query.status;
+        // One page at a time, the storage is only read when the previous chunk was sent.
+        // Some listings ignore the page (the running jobs of redis), so a page starting
+        // like the previous one ends the export as well as a short one
+        let pages = stream::try_unfold(Some((1, None)), move |next| {
+            let queue = queue.clone();
+            async move {
+                let Some((page, last)) = next else {
+                    return Ok(None);
+                };
+                let jobs = queue.page(status, page).await?;
+                let first = jobs.first().map(|(task_id, _)| task_id.clone());
+                if first.is_none() || first == last {
+                    return Ok(None);
+                }
+                let next = (jobs.len() == PAGE_SIZE).then(|| (page + 1, first));
+                Ok(Some((jobs, next)))
+            }
+        });
+        let owner = namespace.to_string();
+        let rows = pages.map(move |jobs| {
+            jobs.map(|jobs| {
+                let mut chunk = String::new();
+                for (task_id, mut job) in jobs {
+                    if let (Some(redaction), Some(payload)) = (&redaction, job.get_mut("args")) {
+                        redaction.payload(&owner, payload);
+                    }
+                    match (&columns, format) {
+                        (None, _) => {
+                            chunk.push_str(&job.to_string());
+                            chunk.push('\n');
+                        }
+                        (Some(columns), Format::Ndjson) => {
+                            let row: Map<String, Value> = columns
+                                .iter()
+                                .map(|column| {
+                                    let value = extract(&task_id, &job, column);
+                                    (column.clone(), value.unwrap_or(Value::Null))
+                                })
+                                .collect();
+                            chunk.push_str(&Value::Object(row).to_string());
+                            chunk.push('\n');
+                        }
+                        (Some(columns), Format::Csv) => {
+                            let cells: Vec<String> = columns
+                                .iter()
+                                .map(|column| match extract(&task_id, &job, column) {
+                                    None | Some(Value::Null) => String::new(),
+                                    Some(Value::String(text)) => text,
+                                    Some(value) => value.to_string(),
+                                })
+                                .collect();
+                            chunk.push_str(&csv_row(cells.iter().map(|c| c.as_str())));
+                        }
+                    }
+                }
+                chunk
+            })
+        });
+        Self {
+            content_type: format.content_type(),
+            filename: format!("{namespace}-{status}.{}", format.extension()),
+            chunks: stream::iter(header).chain(rows).boxed(),
+        }
+    }
+}
+
+/// Resolves a dotted path like `args.user.email` in a serialized job, indices of arrays included
+fn extract(task_id: &str, job: &Value, column: &str) -> Option<Value> {
+    if column == "task_id" {
+        return Some(Value::String(task_id.to_string()));
+    }
+    let pointer: String = column
+        .split('.')
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect();
+    job.pointer(&pointer).cloned()
+}
+
+fn csv_row<'a>(cells: impl Iterator<Item = &'a str>) -> String {
+    let mut row = cells.map(csv_cell).collect::<Vec<_>>().join(",");
+    row.push_str("\r\n");
+    row
+}
+
+fn csv_cell(cell: &str) -> String {
+    if cell.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::Fake;
+
+    async fn collect(export: Export) -> Result<String, String> {
+        export
+            .chunks
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map(|chunks| chunks.concat())
+    }
+
+    fn queue() -> Arc<Fake> {
+        let fake = Fake::default();
+        for (id, args) in [
+            (
+                "1",
+                json!({ "name": "alice", "note": "says \"hi\", twice" }),
+            ),
+            ("2", json!({ "name": "bob", "tags": ["a", "b"] })),
+        ] {
+            fake.jobs
+                .lock()
+                .unwrap()
+                .push((id.to_string(), json!({ "task_id": id, "args": args })));
+        }
+        Arc::new(fake)
+    }
+
+    #[actix_web::test]
+    async fn csv_rows_pick_and_quote_columns() {
+        let query = ExportQuery {
+            format: Format::Csv,
+            columns: Some("task_id, args.name,args.note,args.tags.1".to_string()),
+            ..ExportQuery::default()
+        };
+        let export = Export::new(queue(), "emails", &query, None);
+        assert_eq!(export.filename, "emails-Pending.csv");
+        assert_eq!(
+            collect(export).await.unwrap(),
+            "task_id,args.name,args.note,args.tags.1\r\n\
+             1,alice,\"says \"\"hi\"\", twice\",\r\n\
+             2,bob,,b\r\n"
+        );
+    }
+
+    #[actix_web::test]
+    async fn ndjson_lines_are_jobs_or_their_columns() {
+        let export = Export::new(queue(), "emails", &ExportQuery::default(), None);
+        let lines: Vec<Value> = collect(export)
+            .await
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["args"]["name"], "bob");
+
+        let query = ExportQuery {
+            columns: Some("task_id,args.missing".to_string()),
+            ..ExportQuery::default()
+        };
+        let export = Export::new(queue(), "emails", &query, None);
+        let first = collect(export).await.unwrap();
+        let first: Value = serde_json::from_str(first.lines().next().unwrap()).unwrap();
+        assert_eq!(first, json!({ "task_id": "1", "args.missing": null }));
+    }
+
+    #[actix_web::test]
+    async fn listings_ignoring_the_page_are_exported_once() {
+        let query = ExportQuery {
+            status: JobState::Running,
+            ..ExportQuery::default()
+        };
+        // A full page is followed by the same page, a short one is the last
+        for count in [PAGE_SIZE, 2] {
+            let fake = Fake {
+                every_page: true,
+                ..Fake::default()
+            };
+            for id in 0..count {
+                let id = id.to_string();
+                fake.jobs
+                    .lock()
+                    .unwrap()
+                    .push((id.clone(), json!({ "task_id": id, "args": {} })));
+            }
+            let export = Export::new(Arc::new(fake), "emails", &query, None);
+            assert_eq!(collect(export).await.unwrap().lines().count(), count);
+        }
+    }
+
+    #[actix_web::test]
+    async fn a_failing_storage_ends_the_stream_with_an_error() {
+        let query = ExportQuery::default();
+        let export = Export::new(Arc::new(Fake::down("gone")), "emails", &query, None);
+        assert_eq!(collect(export).await, Err("gone".to_string()));
+    }
+}
@@ -0,0 +1,657 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use actix_web::web::Data;
+use apalis_core::task::task_id::TaskId;
+use futures::{future::BoxFuture, StreamExt};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use shared::{
+    BoardEvent, Filter, JobState, Namespace, NamespaceInfo, Readiness, ReservedKey, Stat,
+};
+
+use crate::{
+    service::{PushError, Queue, Reply},
+    sse::Broadcaster,
+};
+
+/// How long a call to a remote board may take, event streams aside
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long [`Federation::relay`] waits before reconnecting to an event stream
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// How long the namespaces listed by a remote are reused for the stats of each of them
+const LISTING_TTL: Duration = Duration::from_secs(1);
+
+/// The namespaces listed by a remote, shared by its queues so a board listing fetches them once
+type Listing = Arc<tokio::sync::Mutex<Option<(Instant, Vec<NamespaceInfo>)>>>;
+
+/// A board served by another service, the url being where its [`ApiBuilder`](crate::api::ApiBuilder) is mounted.
+///
+/// ```rust,ignore
+/// Remote::new("billing", "http://billing:8000/api/v1").token("s3cr3t")
+/// ```
+#[derive(Debug, Clone)]
+pub struct Remote {
+    source: String,
+    url: String,
+    token: Option<String>,
+    events: Option<String>,
+}
+
+impl Remote {
+    /// The namespaces of the board are served as `{source}:{namespace}`
+    pub fn new(source: &str, url: &str) -> Self {
+        Self {
+            source: source.to_string(),
+            url: url.trim_end_matches('/').to_string(),
+            token: None,
+            events: None,
+        }
+    }
+
+    /// Bearer token sent with every call, the remote board checks it like any other
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Url of the remote's event stream, `{url}/backend/events` by default
+    pub fn events(mut self, url: &str) -> Self {
+        self.events = Some(url.to_string());
+        self
+    }
+
+    fn backend(&self, path: &str) -> String {
+        format!("{}/backend{path}", self.url)
+    }
+
+    fn events_url(&self) -> String {
+        self.events
+            .clone()
+            .unwrap_or_else(|| self.backend("/events"))
+    }
+
+    fn call(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FederationError {
+    #[error("listing the namespaces of {remote} failed: {error}")]
+    Connect {
+        remote: String,
+        error: reqwest::Error,
+    },
+}
+
+/// Serves the namespaces of several remote boards as one, each prefixed with the source of its [`Remote`].
+/// Job, worker and stat queries are forwarded to the remote, events are relayed with [`Federation::relay`].
+///
+/// The namespaces are listed once by [`Federation::connect`], connect again to pick up new ones.
+/// Audit, retention and idempotency are left to the remote boards,
+/// pushes with an `Idempotency-Key` are answered with a 501 and should go to the remote board itself.
+///
+/// ```rust,ignore
+/// let federation = Federation::new()
+///     .remote(Remote::new("billing", "http://billing:8000/api/v1"))
+///     .remote(Remote::new("mailer", "http://mailer:8000/api/v1"))
+///     .connect()
+///     .await?;
+/// federation.relay(broadcaster.clone());
+/// HttpServer::new(move || {
+///     let scope = ApiBuilder::new()
+///         .federate(&federation)
+///         .events(&broadcaster)
+///         .build();
+///     App::new().service(web::scope("/api/v1").service(scope))
+/// })
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Federation {
+    client: Client,
+    remotes: Vec<Arc<Remote>>,
+    namespaces: Vec<(Namespace, RemoteQueue)>,
+}
+
+impl Federation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn remote(mut self, remote: Remote) -> Self {
+        self.remotes.push(Arc::new(remote));
+        self
+    }
+
+    /// Lists the namespaces of every remote, failing if one can't be reached
+    pub async fn connect(mut self) -> Result<Self, FederationError> {
+        let mut namespaces = Vec::new();
+        for remote in &self.remotes {
+            let listing = Listing::default();
+            let listed: Vec<NamespaceInfo> = async {
+                remote
+                    .call(self.client.get(remote.backend("")))
+                    .timeout(REQUEST_TIMEOUT)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+            }
+            .await
+            .map_err(|error| FederationError::Connect {
+                remote: remote.source.clone(),
+                error,
+            })?;
+            for info in listed {
+                let key = info.namespace.key();
+                let namespace = Namespace {
+                    name: key.clone(),
+                    qualifier: Some(remote.source.clone()),
+                    ..info.namespace
+                };
+                let queue = RemoteQueue {
+                    client: self.client.clone(),
+                    remote: remote.clone(),
+                    namespace: key,
+                    listing: listing.clone(),
+                };
+                namespaces.push((namespace, queue));
+            }
+        }
+        self.namespaces = namespaces;
+        Ok(self)
+    }
+
+    /// The namespaces listed by the last [`Federation::connect`], with their source as qualifier
+    pub fn namespaces(&self) -> impl Iterator<Item = &Namespace> {
+        self.namespaces.iter().map(|(namespace, _)| namespace)
+    }
+
+    pub(crate) fn queues(&self) -> impl Iterator<Item = (Namespace, Arc<dyn Queue>)> + '_ {
+        self.namespaces.iter().map(|(namespace, queue)| {
+            let queue: Arc<dyn Queue> = Arc::new(queue.clone());
+            (namespace.clone(), queue)
+        })
+    }
+
+    /// Relays the events of every remote to `broadcaster`, with their namespaces prefixed like the board serves them.
+    /// Call it once, not per worker, streams that end or fail are opened again.
+    pub fn relay(&self, broadcaster: Data<Mutex<Broadcaster>>) {
+        for remote in &self.remotes {
+            let (client, remote, broadcaster) =
+                (self.client.clone(), remote.clone(), broadcaster.clone());
+            tokio::spawn(async move {
+                loop {
+                    // Ended or failed alike, the remote may just be restarting
+                    let _ = relay_once(&client, &remote, &broadcaster).await;
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            });
+        }
+    }
+}
+
+async fn relay_once(
+    client: &Client,
+    remote: &Remote,
+    broadcaster: &Mutex<Broadcaster>,
+) -> Result<(), reqwest::Error> {
+    let res = remote
+        .call(client.get(remote.events_url()))
+        .send()
+        .await?
+        .error_for_status()?;
+    let mut stream = res.bytes_stream();
+    let mut buffer = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        buffer.extend_from_slice(&chunk?);
+        while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+            let message: Vec<u8> = buffer.drain(..end + 2).collect();
+            let message = String::from_utf8_lossy(&message);
+            for data in message
+                .lines()
+                .filter_map(|line| line.strip_prefix("data: "))
+            {
+                forward(&remote.source, data, broadcaster);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn forward(source: &str, data: &str, broadcaster: &Mutex<Broadcaster>) {
+    // The remote's keepalives, the broadcaster sends its own
+    if data == "ping" || data == "connected" {
+        return;
+    }
+    let Ok(broadcaster) = broadcaster.lock() else {
+        return;
+    };
+    match serde_json::from_str::<BoardEvent>(data) {
+        Ok(mut event) => {
+            let (BoardEvent::Progress { namespace, .. }
+            | BoardEvent::JobUpdated { namespace, .. }
+            | BoardEvent::QueueUpdated { namespace, .. }) = &mut event;
+            *namespace = format!("{source}:{namespace}");
+            broadcaster.send_event(&event);
+        }
+        Err(_) => broadcaster.send_line(data),
+    }
+}
+
+/// A namespace of a remote board
+#[derive(Debug, Clone)]
+struct RemoteQueue {
+    client: Client,
+    remote: Arc<Remote>,
+    /// The namespace as the remote board serves it
+    namespace: String,
+    listing: Listing,
+}
+
+#[derive(Deserialize)]
+struct Pushed {
+    task_id: TaskId,
+}
+
+#[derive(Deserialize)]
+struct Rejected {
+    index: usize,
+    message: String,
+}
+
+impl RemoteQueue {
+    fn get(&self, path: &str) -> RequestBuilder {
+        let url = self.remote.backend(&format!("/{}{path}", self.namespace));
+        self.remote
+            .call(self.client.get(url))
+            .timeout(REQUEST_TIMEOUT)
+    }
+
+    fn list(&self, status: JobState, page: i32) -> RequestBuilder {
+        let status = serde_json::to_value(status).unwrap_or_default();
+        self.get("").query(&[
+            ("status", status.as_str().unwrap_or_default()),
+            ("page", &page.to_string()),
+        ])
+    }
+
+    async fn fetch(&self, request: RequestBuilder) -> Result<Value, String> {
+        let res = request.send().await.map_err(|e| e.to_string())?;
+        let res = res.error_for_status().map_err(|e| e.to_string())?;
+        res.json().await.map_err(|e| e.to_string())
+    }
+
+    /// What the remote board said, passed through as is
+    async fn forward(&self, request: RequestBuilder) -> Reply {
+        match request.send().await {
+            Ok(res) => reply(res).await,
+            Err(e) => Reply::text(502, format!("{} didn't answer: {e}", self.remote.source)),
+        }
+    }
+}
+
+async fn reply(res: Response) -> Reply {
+    let status = res.status().as_u16();
+    let json = res
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    let Ok(text) = res.text().await else {
+        return Reply::empty(502);
+    };
+    if text.is_empty() {
+        return Reply::empty(status);
+    }
+    match serde_json::from_str::<Value>(&text) {
+        Ok(value) if json => Reply::json(status, &value),
+        _ => Reply::text(status, text),
+    }
+}
+
+/// The task id of a listed job, wherever its storage put it
+fn task_id(job: &Value) -> Option<&str> {
+    job.pointer("/parts/context/task_id")
+        .or_else(|| job.pointer("/parts/task_id"))
+        .and_then(Value::as_str)
+}
+
+impl Queue for RemoteQueue {
+    fn jobs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Reply> {
+        Box::pin(self.forward(self.list(filter.status, filter.page)))
+    }
+
+    fn page(
+        &self,
+        status: JobState,
+        page: i32,
+    ) -> BoxFuture<'_, Result<Vec<(String, Value)>, String>> {
+        Box::pin(async move {
+            let mut listed = self.fetch(self.list(status, page)).await?;
+            let jobs = listed
+                .get_mut("jobs")
+                .and_then(Value::as_array_mut)
+                .map(std::mem::take)
+                .unwrap_or_default();
+            let mut page = Vec::with_capacity(jobs.len());
+            for mut job in jobs {
+                let Some(id) = task_id(&job).map(str::to_string) else {
+                    continue;
+                };
+                // Large payloads are listed as previews, the whole job is fetched instead
+                if job.get("preview").is_some() {
+                    job = self.fetch(self.get(&format!("/job/{id}"))).await?;
+                }
+                if let Some(job) = job.as_object_mut() {
+                    job.remove("size");
+                }
+                page.push((id, job));
+            }
+            Ok(page)
+        })
+    }
+
+    fn workers(&self) -> BoxFuture<'_, Reply> {
+        Box::pin(self.forward(self.get("/workers")))
+    }
+
+    fn push(
+        &self,
+        jobs: Vec<(Value, Option<i64>)>,
+    ) -> BoxFuture<'_, Result<Vec<TaskId>, PushError>> {
+        Box::pin(async move {
+            let batch: Vec<Value> = jobs
+                .into_iter()
+                .map(|(job, run_at)| json!({ "job": job, "run_at": run_at }))
+                .collect();
+            let url = self.remote.backend(&format!("/{}/jobs", self.namespace));
+            let request = self.remote.call(self.client.put(url)).json(&batch);
+            let res = request
+                .timeout(REQUEST_TIMEOUT)
+                .send()
+                .await
+                .map_err(|e| PushError::Failed(Vec::new(), e.to_string()))?;
+            let status = res.status().as_u16();
+            let body: Value = res.json().await.unwrap_or_default();
+            let pushed: Vec<Pushed> = body
+                .get("jobs")
+                .and_then(|jobs| serde_json::from_value(jobs.clone()).ok())
+                .unwrap_or_default();
+            let task_ids = pushed.into_iter().map(|pushed| pushed.task_id).collect();
+            if status == 200 {
+                return Ok(task_ids);
+            }
+            let rejected: Option<Rejected> = body
+                .pointer("/errors/0")
+                .and_then(|error| serde_json::from_value(error.clone()).ok());
+            Err(match rejected {
+                Some(rejected) if (400..500).contains(&status) => {
+                    PushError::Invalid(rejected.index, rejected.message)
+                }
+                _ => {
+                    let error = body.get("error").and_then(Value::as_str);
+                    let message = error
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("{} answered {status}", self.remote.source));
+                    PushError::Failed(task_ids, message)
+                }
+            })
+        })
+    }
+
+    fn job<'a>(&'a self, task_id: &'a TaskId) -> BoxFuture<'a, Reply> {
+        Box::pin(self.forward(self.get(&format!("/job/{task_id}"))))
+    }
+
+    fn validate(&self, _job: &Value) -> Result<(), String> {
+        // Only the remote knows the job type, it rejects what doesn't deserialize
+        Ok(())
+    }
+
+    fn snapshot(&self) -> BoxFuture<'_, Option<(Stat, usize)>> {
+        Box::pin(async move {
+            // Held while fetching so the other namespaces of the remote wait for this listing
+            let mut listing = self.listing.lock().await;
+            let fresh = matches!(listing.as_ref(), Some((at, _)) if at.elapsed() < LISTING_TTL);
+            if !fresh {
+                let request = self.remote.call(self.client.get(self.remote.backend("")));
+                let listed = self.fetch(request.timeout(REQUEST_TIMEOUT)).await.ok()?;
+                let listed: Vec<NamespaceInfo> = serde_json::from_value(listed).ok()?;
+                *listing = Some((Instant::now(), listed));
+            }
+            let (_, listed) = listing.as_ref()?;
+            let info = listed
+                .iter()
+                .find(|info| info.namespace.key() == self.namespace)?;
+            Some((info.stats.clone()?, info.workers.unwrap_or_default()))
+        })
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            // Answered with 503 when any namespace of the remote is down, so the status isn't checked.
+            // Namespaces are only detailed to admin tokens, otherwise the whole remote has to be ready.
+            let request = self
+                .remote
+                .call(self.client.get(self.remote.backend("/readyz")));
+            let res = request.timeout(REQUEST_TIMEOUT).send().await;
+            let readiness: Readiness = res
+                .map_err(|e| e.to_string())?
+                .json()
+                .await
+                .map_err(|e| e.to_string())?;
+            let up = match &readiness.namespaces {
+                Some(namespaces) => namespaces
+                    .get(&self.namespace)
+                    .is_some_and(|health| health.up),
+                None => readiness.status == "ok",
+            };
+            if up {
+                Ok(())
+            } else {
+                Err(format!("{} isn't ready", self.namespace))
+            }
+        })
+    }
+
+    fn purge_before(
+        &self,
+        _state: JobState,
+        _before: i64,
+        _limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        // Retention is enforced by the remote board
+        Box::pin(async { Ok(0) })
+    }
+
+    fn purge_beyond(
+        &self,
+        _state: JobState,
+        _keep: usize,
+        _limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Box::pin(async { Ok(0) })
+    }
+
+    fn reserve_key<'a>(
+        &'a self,
+        _key: &'a str,
+        _fingerprint: &'a str,
+        _lease: Duration,
+    ) -> BoxFuture<'a, Result<Option<ReservedKey>, String>> {
+        // Never called, pushes with a key are refused since the remote wouldn't see it
+        Box::pin(async { Err("idempotency keys aren't forwarded to remote boards".to_string()) })
+    }
+
+    fn settle_key<'a>(
+        &'a self,
+        _key: &'a str,
+        _fingerprint: &'a str,
+        _outcome: Option<String>,
+        _ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remembers_keys(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use actix_web::{dev::Service, test, App, HttpServer};
+    use shared::JobState;
+
+    use super::*;
+    use crate::{api::ApiBuilder, service::Board, testing::Fake};
+
+    /// Serves `board` on a free port, answering with its url
+    fn serve(board: Board, broadcaster: Data<Mutex<Broadcaster>>) -> String {
+        let server = HttpServer::new(move || {
+            App::new().service(ApiBuilder::from(board.clone()).events(&broadcaster).build())
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    #[actix_web::test]
+    async fn remote_boards_are_served_as_one() {
+        let invoices = Arc::new(Fake::default());
+        invoices.jobs.lock().unwrap().push((
+            "1".to_string(),
+            json!({ "task_id": "1", "args": { "amount": 3 } }),
+        ));
+        let billing_events = Broadcaster::create();
+        let billing = serve(
+            Board::new().add_fake("invoices", invoices),
+            billing_events.clone(),
+        );
+        let mailer = serve(
+            Board::new().add_fake("emails", Arc::new(Fake::default())),
+            Broadcaster::create(),
+        );
+
+        let federation = Federation::new()
+            .remote(Remote::new("billing", &billing))
+            .remote(Remote::new("mailer", &mailer))
+            .connect()
+            .await
+            .unwrap();
+        let keys: Vec<String> = federation.namespaces().map(Namespace::key).collect();
+        assert_eq!(keys, ["billing:invoices", "mailer:emails"]);
+
+        let app =
+            test::init_service(App::new().service(ApiBuilder::new().federate(&federation).build()))
+                .await;
+        let req = test::TestRequest::get().uri("/backend").to_request();
+        let listed: Vec<NamespaceInfo> = test::call_and_read_body_json(&app, req).await;
+        let listed: Vec<String> = listed.iter().map(|info| info.namespace.key()).collect();
+        assert_eq!(listed, ["billing:invoices", "mailer:emails"]);
+        let req = test::TestRequest::get()
+            .uri("/backend/billing:invoices")
+            .to_request();
+        let jobs: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(jobs["jobs"][0]["args"]["amount"], 3);
+
+        let local = Data::new(Mutex::new(Broadcaster::new()));
+        let mut client = local.lock().unwrap().new_client();
+        federation.relay(local.clone());
+        let event = BoardEvent::QueueUpdated {
+            namespace: "invoices".to_string(),
+            state: JobState::Pending,
+        };
+        // Sent until the relay has connected and passes it on
+        let relayed = async {
+            loop {
+                billing_events.lock().unwrap().send_event(&event);
+                let next = tokio::time::timeout(Duration::from_millis(100), client.next()).await;
+                let Ok(Some(Ok(msg))) = next else {
+                    continue;
+                };
+                let msg = String::from_utf8_lossy(&msg);
+                let data = msg.trim_end().trim_start_matches("data: ");
+                if let Ok(event) = serde_json::from_str::<Value>(data) {
+                    return event;
+                }
+            }
+        };
+        let relayed = tokio::time::timeout(Duration::from_secs(10), relayed)
+            .await
+            .unwrap();
+        assert_eq!(
+            relayed,
+            json!({ "event": "queue_updated", "namespace": "billing:invoices", "state": "Pending" })
+        );
+    }
+
+    #[actix_web::test]
+    async fn namespaces_of_a_remote_share_its_listing_and_refuse_keys() {
+        let emails = Arc::new(Fake::default());
+        let board = Board::new()
+            .add_fake("emails", emails.clone())
+            .add_fake("sms", Arc::new(Fake::default()))
+            .add_fake("push", Arc::new(Fake::default()));
+        let listings = Arc::new(AtomicUsize::new(0));
+        let counted = listings.clone();
+        let server = HttpServer::new(move || {
+            let counted = counted.clone();
+            App::new()
+                .wrap_fn(move |req, srv| {
+                    if req.path() == "/backend" {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                    }
+                    srv.call(req)
+                })
+                .service(ApiBuilder::from(board.clone()).build())
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+
+        let federation = Federation::new()
+            .remote(Remote::new("mailer", &url))
+            .connect()
+            .await
+            .unwrap();
+        let app =
+            test::init_service(App::new().service(ApiBuilder::new().federate(&federation).build()))
+                .await;
+        let req = test::TestRequest::get().uri("/backend").to_request();
+        let listed: Vec<NamespaceInfo> = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(listed.len(), 3);
+        assert!(listed.iter().all(|info| info.stats.is_some()));
+        // Once to connect, once for the stats of the three namespaces
+        assert_eq!(listings.load(Ordering::SeqCst), 2);
+
+        let req = test::TestRequest::put()
+            .uri("/backend/mailer:emails/job")
+            .insert_header(("Idempotency-Key", "key-1"))
+            .set_json(json!({ "to": "test@example.com" }))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), 501);
+        assert!(emails.jobs.lock().unwrap().is_empty());
+    }
+}
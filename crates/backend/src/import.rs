@@ -0,0 +1,220 @@
+use std::fmt::Display;
+
+use apalis_core::storage::Storage;
+use chrono::DateTime;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use shared::Queued;
+pub use shared::{ImportOptions, ImportReport, LineError};
+
+/// Largest NDJSON body the import endpoint reads, the frameworks' defaults are meant for single jobs
+pub const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// A job read from a line of NDJSON
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// 1-based, blank lines are counted
+    pub line: usize,
+    pub job: Value,
+    pub run_at: Option<i64>,
+}
+
+/// Reads NDJSON where each line is either a job's payload or a full job as exported from
+/// `GET /backend/{namespace}/export`, recognized by its `args` and `parts`.
+/// `run_at` is only taken from exported jobs, and only with `keep_run_at`.
+pub fn parse(ndjson: &str, keep_run_at: bool) -> (Vec<Line>, Vec<LineError>) {
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    for (index, text) in ndjson.lines().enumerate() {
+        let line = index + 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let value: Value = match serde_json::from_str(text) {
+            Ok(value) => value,
+            Err(e) => {
+                errors.push(LineError {
+                    line,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let (job, run_at) = match value {
+            Value::Object(mut exported)
+                if exported.contains_key("args") && exported.contains_key("parts") =>
+            {
+                let run_at = if keep_run_at {
+                    run_at(&exported["parts"])
+                } else {
+                    None
+                };
+                (exported.remove("args").unwrap_or_default(), run_at)
+            }
+            job => (job, None),
+        };
+        lines.push(Line { line, job, run_at });
+    }
+    (lines, errors)
+}
+
+/// The `run_at` of exported parts, a unix timestamp or RFC 3339 depending on the storage
+fn run_at(parts: &Value) -> Option<i64> {
+    let run_at = parts
+        .pointer("/context/run_at")
+        .or_else(|| parts.pointer("/run_at"))?;
+    match run_at {
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .ok()
+            .map(|at| at.timestamp()),
+        _ => None,
+    }
+}
+
+/// Queues the jobs of `ndjson` in `storage`, the same way the import endpoint does minus the job schema
+/// and the audit trail. Useful to move jobs between storages from a script.
+pub async fn import<J, S>(storage: &mut S, ndjson: &str, options: &ImportOptions) -> ImportReport
+where
+    J: DeserializeOwned,
+    S: Storage<Job = J>,
+    S::Error: Display,
+{
+    let (lines, mut errors) = parse(ndjson, options.keep_run_at);
+    let mut jobs = Vec::with_capacity(lines.len());
+    for line in lines {
+        match serde_json::from_value::<J>(line.job) {
+            Ok(job) => jobs.push((line.line, job, line.run_at)),
+            Err(e) => errors.push(LineError {
+                line: line.line,
+                message: e.to_string(),
+            }),
+        }
+    }
+    let mut report = ImportReport {
+        dry_run: options.dry_run,
+        valid: jobs.len(),
+        ..Default::default()
+    };
+    if !errors.is_empty() || options.dry_run {
+        errors.sort_by_key(|error| error.line);
+        report.errors = errors;
+        return report;
+    }
+    for (line, job, run_at) in jobs {
+        let res = match run_at {
+            Some(on) => storage.schedule(job, on).await,
+            None => storage.push(job).await,
+        };
+        match res {
+            Ok(parts) => report.jobs.push(Queued {
+                task_id: parts.task_id.to_string(),
+                run_at,
+            }),
+            Err(e) => {
+                report.errors.push(LineError {
+                    line,
+                    message: e.to_string(),
+                });
+                break;
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        auth::Identity,
+        service::{Board, Body},
+        testing::Fake,
+    };
+
+    const NDJSON: &str = r#"{"to":"a@example.com"}
+
+{"args":{"to":"b@example.com"},"parts":{"context":{"run_at":1700000000}}}
+{"args":{"to":"c@example.com"},"parts":{"run_at":"2023-11-14T22:13:20Z"}}
+"#;
+
+    #[test]
+    fn lines_are_payloads_or_exported_jobs() {
+        let (lines, errors) = parse(NDJSON, true);
+        assert!(errors.is_empty());
+        let read: Vec<(usize, Value, Option<i64>)> = lines
+            .into_iter()
+            .map(|line| (line.line, line.job, line.run_at))
+            .collect();
+        assert_eq!(
+            read,
+            vec![
+                (1, json!({ "to": "a@example.com" }), None),
+                (3, json!({ "to": "b@example.com" }), Some(1_700_000_000)),
+                (4, json!({ "to": "c@example.com" }), Some(1_700_000_000)),
+            ]
+        );
+
+        let (lines, _) = parse(NDJSON, false);
+        assert!(lines.iter().all(|line| line.run_at.is_none()));
+
+        let (lines, errors) = parse("{\"to\":\"a\"}\n{not json\n", false);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 2);
+    }
+
+    fn report(reply: crate::service::Reply) -> (u16, ImportReport) {
+        let Body::Json(body) = reply.body else {
+            panic!("expected json");
+        };
+        (reply.status, serde_json::from_value(body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn nothing_is_queued_on_a_dry_run_or_an_invalid_line() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new().add_fake("emails", queue.clone());
+        let identity = Identity::anonymous();
+
+        let dry_run = ImportOptions {
+            dry_run: true,
+            ..ImportOptions::default()
+        };
+        let (status, checked) = report(
+            board
+                .import_jobs(&identity, "emails", NDJSON, &dry_run)
+                .await,
+        );
+        assert_eq!(status, 200);
+        assert!(checked.dry_run);
+        assert_eq!(checked.valid, 3);
+        assert!(checked.jobs.is_empty());
+        assert!(queue.jobs.lock().unwrap().is_empty());
+
+        let invalid = format!("{NDJSON}\"not an object\"\n");
+        let options = ImportOptions::default();
+        let (status, rejected) = report(
+            board
+                .import_jobs(&identity, "emails", &invalid, &options)
+                .await,
+        );
+        assert_eq!(status, 422);
+        assert_eq!(rejected.valid, 3);
+        assert_eq!(rejected.errors[0].line, 5);
+        assert!(queue.jobs.lock().unwrap().is_empty());
+
+        let (status, imported) = report(
+            board
+                .import_jobs(&identity, "emails", NDJSON, &options)
+                .await,
+        );
+        assert_eq!(status, 200);
+        assert_eq!(imported.jobs.len(), 3);
+        assert_eq!(queue.jobs.lock().unwrap().len(), 3);
+    }
+}
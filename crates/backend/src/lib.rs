@@ -1,2 +1,22 @@
 pub mod api;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "axum")]
+pub mod axum;
+pub mod bridge;
+pub mod export;
+#[cfg(feature = "federation")]
+pub mod federation;
+pub mod import;
+pub mod metrics;
+pub mod openapi;
+pub mod progress;
+pub mod redact;
+pub mod retention;
+pub mod schema;
+pub mod service;
 pub mod sse;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "ui")]
+pub mod ui;
@@ -0,0 +1,241 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use shared::Stat;
+
+/// Upper bounds of the request latency histogram, in seconds
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+#[derive(Debug, Default)]
+struct Series {
+    count: u64,
+    sum: f64,
+    buckets: [u64; BUCKETS.len()],
+}
+
+/// Prometheus metrics of the board, served at `GET /backend/metrics`.
+///
+/// Create it once and share clones of it, like [`Audit`](crate::audit::Audit), so the counters
+/// cover every worker of the server. Storages are queried at most once per [`Metrics::cache_for`],
+/// scrapes in between get the previous numbers.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    ttl: Duration,
+    requests: Arc<Mutex<BTreeMap<(String, String, u16), Series>>>,
+    storages: Arc<tokio::sync::Mutex<Option<(Instant, String)>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(10),
+            requests: Default::default(),
+            storages: Default::default(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long storage gauges are reused between scrapes, defaults to 10 seconds
+    pub fn cache_for(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Count a request handled by the api, `route` is the matched pattern not the path
+    pub fn observe(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let Ok(mut requests) = self.requests.lock() else {
+            return;
+        };
+        let series = requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default();
+        let seconds = elapsed.as_secs_f64();
+        series.count += 1;
+        series.sum += seconds;
+        for (bucket, le) in series.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= le {
+                *bucket += 1;
+            }
+        }
+    }
+
+    /// Renders the text exposition, `snapshot` is only called when the cached storage gauges expired.
+    /// Concurrent scrapes wait for the one refreshing them.
+    pub async fn render<F, Fut>(&self, snapshot: F) -> String
+    where
+        F: FnOnce() -> Fut,
+        Fut: std::future::Future<Output = Vec<(String, Option<(Stat, usize)>)>>,
+    {
+        let storages = {
+            let mut cached = self.storages.lock().await;
+            match &*cached {
+                Some((at, text)) if at.elapsed() < self.ttl => text.clone(),
+                _ => {
+                    let text = render_storages(&snapshot().await);
+                    *cached = Some((Instant::now(), text.clone()));
+                    text
+                }
+            }
+        };
+        let mut out = storages;
+        self.render_requests(&mut out);
+        out
+    }
+
+    fn render_requests(&self, out: &mut String) {
+        let Ok(requests) = self.requests.lock() else {
+            return;
+        };
+        out.push_str("# HELP apalis_board_http_requests_total Requests handled by the board api\n");
+        out.push_str("# TYPE apalis_board_http_requests_total counter\n");
+        for ((method, route, status), series) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "apalis_board_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{status}\"}} {}",
+                escape(method),
+                escape(route),
+                series.count
+            );
+        }
+        out.push_str(
+            "# HELP apalis_board_http_request_duration_seconds Latency of the board api\n",
+        );
+        out.push_str("# TYPE apalis_board_http_request_duration_seconds histogram\n");
+        for ((method, route, status), series) in requests.iter() {
+            let labels = format!(
+                "method=\"{}\",route=\"{}\",status=\"{status}\"",
+                escape(method),
+                escape(route)
+            );
+            for (count, le) in series.buckets.iter().zip(BUCKETS) {
+                let _ = writeln!(
+                    out,
+                    "apalis_board_http_request_duration_seconds_bucket{{{labels},le=\"{le}\"}} {count}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "apalis_board_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                series.count
+            );
+            let _ = writeln!(
+                out,
+                "apalis_board_http_request_duration_seconds_sum{{{labels}}} {}",
+                series.sum
+            );
+            let _ = writeln!(
+                out,
+                "apalis_board_http_request_duration_seconds_count{{{labels}}} {}",
+                series.count
+            );
+        }
+    }
+}
+
+fn render_storages(snapshots: &[(String, Option<(Stat, usize)>)]) -> String {
+    let mut out = String::new();
+    out.push_str("# HELP apalis_board_storage_up Whether the last query of a storage succeeded\n");
+    out.push_str("# TYPE apalis_board_storage_up gauge\n");
+    for (namespace, snapshot) in snapshots {
+        let _ = writeln!(
+            out,
+            "apalis_board_storage_up{{namespace=\"{}\"}} {}",
+            escape(namespace),
+            u8::from(snapshot.is_some())
+        );
+    }
+    out.push_str("# HELP apalis_board_jobs Jobs in a storage by state\n");
+    out.push_str("# TYPE apalis_board_jobs gauge\n");
+    for (namespace, snapshot) in snapshots {
+        let Some((stat, _)) = snapshot else { continue };
+        for (state, count) in [
+            ("pending", stat.pending),
+            ("running", stat.running),
+            ("failed", stat.failed),
+            ("dead", stat.dead),
+            ("success", stat.success),
+        ] {
+            let _ = writeln!(
+                out,
+                "apalis_board_jobs{{namespace=\"{}\",state=\"{state}\"}} {count}",
+                escape(namespace)
+            );
+        }
+    }
+    out.push_str("# HELP apalis_board_workers Workers registered on a storage\n");
+    out.push_str("# TYPE apalis_board_workers gauge\n");
+    for (namespace, snapshot) in snapshots {
+        let Some((_, workers)) = snapshot else {
+            continue;
+        };
+        let _ = writeln!(
+            out,
+            "apalis_board_workers{{namespace=\"{}\"}} {workers}",
+            escape(namespace)
+        );
+    }
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn storages_are_queried_once_per_ttl() {
+        let metrics = Metrics::new().cache_for(Duration::from_secs(60));
+        metrics.observe(
+            "GET",
+            "/backend/{namespace}",
+            200,
+            Duration::from_millis(20),
+        );
+        let queried = &AtomicUsize::new(0);
+        let snapshot = || async move {
+            queried.fetch_add(1, Ordering::SeqCst);
+            let stat = Stat {
+                pending: 3,
+                ..Stat::default()
+            };
+            vec![
+                ("emails".to_string(), Some((stat, 2))),
+                ("in\"voices".to_string(), None),
+            ]
+        };
+
+        let first = metrics.render(snapshot).await;
+        let second = metrics.render(snapshot).await;
+        assert_eq!(queried.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+
+        for line in [
+            "apalis_board_storage_up{namespace=\"emails\"} 1",
+            "apalis_board_storage_up{namespace=\"in\\\"voices\"} 0",
+            "apalis_board_jobs{namespace=\"emails\",state=\"pending\"} 3",
+            "apalis_board_workers{namespace=\"emails\"} 2",
+            "apalis_board_http_requests_total{method=\"GET\",route=\"/backend/{namespace}\",status=\"200\"} 1",
+            "apalis_board_http_request_duration_seconds_bucket{method=\"GET\",route=\"/backend/{namespace}\",status=\"200\",le=\"0.01\"} 0",
+            "apalis_board_http_request_duration_seconds_bucket{method=\"GET\",route=\"/backend/{namespace}\",status=\"200\",le=\"0.025\"} 1",
+        ] {
+            assert!(first.lines().any(|l| l == line), "missing {line}");
+        }
+    }
+}
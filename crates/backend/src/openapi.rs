@@ -0,0 +1,674 @@
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Map, Value};
+use shared::{JobProgress, JobState, NamespaceGroup, NamespaceInfo, Stat, Worker};
+
+use crate::{
+    audit::AuditEntry, import::ImportReport, retention::Run, schema::JobSchemas, service::Queued,
+};
+
+/// The routes a [`Board`](crate::service::Board) only serves when configured for them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Routes {
+    pub audit: bool,
+    pub metrics: bool,
+    pub retention: bool,
+    pub events: bool,
+    /// Every route but `healthz` and `readyz` needs credentials
+    pub auth: bool,
+}
+
+/// Generates the OpenAPI 3 document of the api served for a [`Board`](crate::service::Board).
+///
+/// Paths are relative to the scope the api is mounted on, `servers` is filled in per request.
+/// Namespaces without a job schema accept and return any json for their jobs.
+pub fn document(namespaces: &[String], job_schemas: &JobSchemas, routes: Routes) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut refs = Map::new();
+    let mut register = |name: &str, schema: schemars::schema::Schema| {
+        refs.insert(name.to_string(), json!(schema));
+    };
+    register("Stat", gen.subschema_for::<Stat>());
+    register("NamespaceInfo", gen.subschema_for::<NamespaceInfo>());
+    register("NamespaceGroup", gen.subschema_for::<NamespaceGroup>());
+    register("JobState", gen.subschema_for::<JobState>());
+    register("JobProgress", gen.subschema_for::<JobProgress>());
+    register("Worker", gen.subschema_for::<Worker>());
+    register("AuditEntry", gen.subschema_for::<AuditEntry>());
+    register("Queued", gen.subschema_for::<Queued>());
+    register("ImportReport", gen.subschema_for::<ImportReport>());
+    register("Run", gen.subschema_for::<Run>());
+    let mut components: Map<String, Value> = gen
+        .take_definitions()
+        .into_iter()
+        .map(|(name, schema)| (name, json!(schema)))
+        .collect();
+
+    let queued = json!({
+        "type": "object",
+        "required": ["jobs"],
+        "properties": {
+            "jobs": { "type": "array", "items": refs["Queued"] },
+            "error": { "type": "string" }
+        }
+    });
+    let batch_errors = json!({
+        "type": "object",
+        "properties": { "errors": { "type": "array", "items": {
+            "type": "object",
+            "properties": {
+                "index": { "type": "integer" },
+                "path": { "type": "string" },
+                "message": { "type": "string" }
+            }
+        } } }
+    });
+    let idempotency_key = json!({
+        "name": "Idempotency-Key",
+        "in": "header",
+        "description": "Retrying with the same key answers like the first push instead of queueing again, reusing it for another payload is a 422",
+        "schema": { "type": "string" }
+    });
+    let raw = json!({
+        "name": "raw",
+        "in": "query",
+        "required": false,
+        "description": "Skip the redaction of payloads, only for admins",
+        "schema": { "type": "boolean", "default": false }
+    });
+
+    let mut paths = Map::new();
+    paths.insert(
+        "/backend".to_string(),
+        json!({
+            "get": {
+                "summary": "List the namespaces of all registered storages with their metadata and stats",
+                "operationId": "listNamespaces",
+                "responses": {
+                    "200": ok(json!({ "type": "array", "items": refs["NamespaceInfo"] }))
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/backend/groups".to_string(),
+        json!({
+            "get": {
+                "summary": "List the namespaces by logical group, the ungrouped ones first",
+                "operationId": "listGroups",
+                "responses": {
+                    "200": ok(json!({ "type": "array", "items": refs["NamespaceGroup"] }))
+                }
+            }
+        }),
+    );
+    paths.insert(
+        "/backend/healthz".to_string(),
+        json!({
+            "get": {
+                "summary": "Liveness of the api, doesn't need credentials",
+                "operationId": "healthz",
+                "responses": { "200": ok(json!({ "type": "object" })) }
+            }
+        }),
+    );
+    let health = json!({
+        "type": "object",
+        "properties": {
+            "status": { "type": "string", "enum": ["ok", "unavailable"] },
+            "backends": { "type": "object", "additionalProperties": { "type": "boolean" } },
+            "namespaces": {
+                "description": "Only answered to admins that can access every namespace",
+                "type": "object",
+                "additionalProperties": {
+                    "type": "object",
+                    "required": ["up", "latency_ms"],
+                    "properties": {
+                        "up": { "type": "boolean" },
+                        "latency_ms": { "type": "number" },
+                        "error": { "type": "string" }
+                    }
+                }
+            }
+        }
+    });
+    paths.insert(
+        "/backend/readyz".to_string(),
+        json!({
+            "get": {
+                "summary": "Pings every storage, doesn't need credentials but only admins get the health of each namespace",
+                "operationId": "readyz",
+                "responses": {
+                    "200": ok(health.clone()),
+                    "503": {
+                        "description": "A storage is down",
+                        "content": { "application/json": { "schema": health } }
+                    }
+                }
+            }
+        }),
+    );
+    if routes.events {
+        paths.insert(
+            "/backend/events".to_string(),
+            json!({
+                "get": {
+                    "summary": "Stream the events of the namespaces the caller can access as server-sent events",
+                    "operationId": "events",
+                    "responses": {
+                        "200": {
+                            "description": "One `data:` line per event, a json `BoardEvent` or a log line",
+                            "content": { "text/event-stream": { "schema": { "type": "string" } } }
+                        }
+                    }
+                }
+            }),
+        );
+    }
+    if routes.audit {
+        paths.insert(
+            "/backend/audit".to_string(),
+            json!({
+                "get": {
+                    "summary": "Query the audit trail, newest first",
+                    "operationId": "listAudit",
+                    "parameters": [
+                        query("actor", json!({ "type": "string" })),
+                        query("namespace", json!({ "type": "string" })),
+                        query("task_id", json!({ "type": "string" })),
+                        query("action", json!({ "type": "string" })),
+                        query("since", json!({ "type": "integer", "format": "uint64" })),
+                        query("until", json!({ "type": "integer", "format": "uint64" })),
+                        query("limit", json!({ "type": "integer", "default": 100 }))
+                    ],
+                    "responses": {
+                        "200": ok(json!({ "type": "array", "items": refs["AuditEntry"] }))
+                    }
+                }
+            }),
+        );
+    }
+    if routes.retention {
+        paths.insert(
+            "/backend/retention".to_string(),
+            json!({
+                "get": {
+                    "summary": "The latest runs of the retention rules, newest first",
+                    "operationId": "listRetention",
+                    "responses": {
+                        "200": ok(json!({ "type": "array", "items": refs["Run"] }))
+                    }
+                }
+            }),
+        );
+    }
+    if routes.metrics {
+        paths.insert(
+            "/backend/metrics".to_string(),
+            json!({
+                "get": {
+                    "summary": "Prometheus metrics of the storages and the api",
+                    "operationId": "getMetrics",
+                    "responses": {
+                        "200": {
+                            "description": "OK",
+                            "content": { "text/plain": { "schema": { "type": "string" } } }
+                        },
+                        "403": { "description": "Only identities with access to every namespace can scrape" }
+                    }
+                }
+            }),
+        );
+    }
+
+    for namespace in namespaces {
+        let job = job_schemas
+            .get(namespace)
+            .map(|job| hoist(namespace, job.schema(), &mut components))
+            .unwrap_or(json!({}));
+        let base = format!("/backend/{namespace}");
+        paths.insert(
+            base.clone(),
+            json!({
+                "get": {
+                    "summary": format!("List jobs of {namespace}"),
+                    "operationId": format!("{namespace}.listJobs"),
+                    "tags": [namespace],
+                    "parameters": [
+                        query("status", refs["JobState"].clone()),
+                        query("page", json!({ "type": "integer", "format": "int32", "default": 1 })),
+                        raw.clone()
+                    ],
+                    "responses": {
+                        "200": ok(json!({
+                            "type": "object",
+                            "required": ["stats", "jobs"],
+                            "properties": {
+                                "stats": refs["Stat"],
+                                "jobs": { "type": "array", "items": listed(&job) },
+                                "progress": {
+                                    "type": "object",
+                                    "additionalProperties": refs["JobProgress"]
+                                }
+                            }
+                        })),
+                        "500": error()
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/workers"),
+            json!({
+                "get": {
+                    "summary": format!("List workers of {namespace}"),
+                    "operationId": format!("{namespace}.listWorkers"),
+                    "tags": [namespace],
+                    "responses": {
+                        "200": ok(json!({ "type": "array", "items": refs["Worker"] })),
+                        "500": error()
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/export"),
+            json!({
+                "get": {
+                    "summary": format!("Stream every job of {namespace} in a state"),
+                    "description": "Without `columns` each line is a job as listed, CSV defaults to `task_id,args`. Columns are dotted paths into the job like `args.email`.",
+                    "operationId": format!("{namespace}.exportJobs"),
+                    "tags": [namespace],
+                    "parameters": [
+                        query("status", refs["JobState"].clone()),
+                        query("format", json!({ "type": "string", "enum": ["ndjson", "csv"], "default": "ndjson" })),
+                        query("columns", json!({ "type": "string" })),
+                        raw.clone()
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "OK",
+                            "content": {
+                                "application/x-ndjson": { "schema": { "type": "string" } },
+                                "text/csv": { "schema": { "type": "string" } }
+                            }
+                        }
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/job"),
+            json!({
+                "put": {
+                    "summary": format!("Push a job to {namespace}"),
+                    "operationId": format!("{namespace}.pushJob"),
+                    "tags": [namespace],
+                    "parameters": [idempotency_key.clone()],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": job } }
+                    },
+                    "responses": {
+                        "200": text(),
+                        "400": error(),
+                        "409": conflict(),
+                        "422": {
+                            "description": "The job doesn't match the namespace's schema",
+                            "content": { "application/json": { "schema": {
+                                "type": "object",
+                                "properties": { "errors": { "type": "array", "items": {
+                                    "type": "object",
+                                    "properties": {
+                                        "path": { "type": "string" },
+                                        "message": { "type": "string" }
+                                    }
+                                } } }
+                            } } }
+                        },
+                        "500": error()
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/jobs"),
+            json!({
+                "put": {
+                    "summary": format!("Push or schedule a batch of jobs to {namespace}"),
+                    "description": "Every job is validated before any is queued. `run_at` is a unix timestamp, `delay` is in seconds, a job can't have both.",
+                    "operationId": format!("{namespace}.pushJobs"),
+                    "tags": [namespace],
+                    "parameters": [idempotency_key.clone()],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["job"],
+                                "properties": {
+                                    "job": job,
+                                    "run_at": { "type": "integer", "format": "int64" },
+                                    "delay": { "type": "integer", "format": "uint64" }
+                                }
+                            }
+                        } } }
+                    },
+                    "responses": {
+                        "200": ok(queued.clone()),
+                        "400": {
+                            "description": "A job doesn't deserialize or has both `run_at` and `delay`, nothing was queued",
+                            "content": { "application/json": { "schema": batch_errors.clone() } }
+                        },
+                        "409": conflict(),
+                        "422": {
+                            "description": "Jobs don't match the namespace's schema, nothing was queued",
+                            "content": { "application/json": { "schema": batch_errors.clone() } }
+                        },
+                        "500": {
+                            "description": "The storage failed, `jobs` lists the ones queued before it did",
+                            "content": { "application/json": { "schema": queued.clone() } }
+                        }
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/import"),
+            json!({
+                "put": {
+                    "summary": format!("Queue the jobs of an NDJSON file in {namespace}"),
+                    "description": "Each line is a job's payload or a job as exported, recognized by its `args` and `parts`. Every line is validated before any is queued, `keep_run_at` schedules exported jobs at their original `run_at`.",
+                    "operationId": format!("{namespace}.importJobs"),
+                    "tags": [namespace],
+                    "parameters": [
+                        query("dry_run", json!({ "type": "boolean", "default": false })),
+                        query("keep_run_at", json!({ "type": "boolean", "default": false }))
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/x-ndjson": { "schema": { "type": "string" } } }
+                    },
+                    "responses": {
+                        "200": ok(refs["ImportReport"].clone()),
+                        "422": {
+                            "description": "Lines are invalid, `errors` lists them and nothing was queued",
+                            "content": { "application/json": { "schema": refs["ImportReport"] } }
+                        },
+                        "500": {
+                            "description": "The storage failed, `jobs` lists the ones queued before it did",
+                            "content": { "application/json": { "schema": refs["ImportReport"] } }
+                        }
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/schema"),
+            json!({
+                "get": {
+                    "summary": format!("The JSON Schema of jobs in {namespace}"),
+                    "operationId": format!("{namespace}.getSchema"),
+                    "tags": [namespace],
+                    "responses": {
+                        "200": ok(json!({ "type": "object" })),
+                        "404": { "description": "No schema was registered" }
+                    }
+                }
+            }),
+        );
+        paths.insert(
+            format!("{base}/job/{{job_id}}"),
+            json!({
+                "get": {
+                    "summary": format!("Fetch a job of {namespace}"),
+                    "operationId": format!("{namespace}.getJob"),
+                    "tags": [namespace],
+                    "parameters": [{
+                        "name": "job_id",
+                        "in": "path",
+                        "required": true,
+                        "schema": { "type": "string" }
+                    }, raw.clone()],
+                    "responses": {
+                        "200": ok(request(&job)),
+                        "404": { "description": "No job with this id" },
+                        "500": error()
+                    }
+                }
+            }),
+        );
+    }
+
+    let mut doc = json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "apalis-board",
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": paths,
+        "components": { "schemas": components }
+    });
+    if routes.auth {
+        secure(&mut doc);
+    }
+    doc
+}
+
+/// Documents the credentials the api takes and how it answers without them.
+/// Readiness takes them only to detail the namespaces, liveness ignores them.
+fn secure(doc: &mut Value) {
+    doc["components"]["securitySchemes"] = json!({
+        "bearerAuth": { "type": "http", "scheme": "bearer" },
+        "basicAuth": { "type": "http", "scheme": "basic" }
+    });
+    doc["security"] = json!([{ "bearerAuth": [] }, { "basicAuth": [] }]);
+    let Some(paths) = doc["paths"].as_object_mut() else {
+        return;
+    };
+    for (path, item) in paths.iter_mut() {
+        let Some(operations) = item.as_object_mut() else {
+            continue;
+        };
+        for operation in operations.values_mut() {
+            match path.as_str() {
+                "/backend/healthz" => operation["security"] = json!([]),
+                "/backend/readyz" => {
+                    operation["security"] = json!([{}, { "bearerAuth": [] }, { "basicAuth": [] }])
+                }
+                _ => {
+                    operation["responses"]["401"] = json!({
+                        "description": "Missing or rejected credentials",
+                        "content": { "text/plain": { "schema": { "type": "string" } } }
+                    });
+                    // Namespaced operations are tagged with their namespace
+                    if operation.get("tags").is_some() {
+                        operation["responses"]["403"] = json!({
+                            "description": "The caller can't access the namespace or lacks the role for the operation",
+                            "content": { "text/plain": { "schema": { "type": "string" } } }
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves the definitions of a job schema to the components, prefixed with the namespace
+fn hoist(namespace: &str, schema: &Value, components: &mut Map<String, Value>) -> Value {
+    let prefix: String = namespace
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    fn rewrite(value: &mut Value, prefix: &str) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(reference)) = map.get_mut("$ref") {
+                    if let Some(name) = reference.strip_prefix("#/definitions/") {
+                        *reference = format!("#/components/schemas/{prefix}.{name}");
+                    }
+                }
+                map.values_mut().for_each(|v| rewrite(v, prefix));
+            }
+            Value::Array(items) => items.iter_mut().for_each(|v| rewrite(v, prefix)),
+            _ => {}
+        }
+    }
+    let mut schema = schema.clone();
+    rewrite(&mut schema, &prefix);
+    if let Value::Object(map) = &mut schema {
+        map.remove("$schema");
+        if let Some(Value::Object(definitions)) = map.remove("definitions") {
+            for (name, definition) in definitions {
+                components.insert(format!("{prefix}.{name}"), definition);
+            }
+        }
+    }
+    schema
+}
+
+/// A job as returned by the backend: the payload in `args` next to its metadata in `parts`
+fn request(job: &Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "args": job,
+            "parts": { "type": "object" }
+        }
+    })
+}
+
+/// A listed job, large payloads are cut to a `preview` in place of `args`
+fn listed(job: &Value) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "args": job,
+            "preview": { "type": "string", "description": "The first bytes of the payload's json" },
+            "size": { "type": "integer", "description": "Bytes of the payload's json" },
+            "parts": { "type": "object" }
+        }
+    })
+}
+
+fn query(name: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "schema": schema })
+}
+
+fn ok(schema: Value) -> Value {
+    json!({
+        "description": "OK",
+        "content": { "application/json": { "schema": schema } }
+    })
+}
+
+fn text() -> Value {
+    json!({
+        "description": "OK",
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    })
+}
+
+fn error() -> Value {
+    json!({
+        "description": "The request failed",
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    })
+}
+
+fn conflict() -> Value {
+    json!({
+        "description": "A push with the same Idempotency-Key is still running",
+        "content": { "text/plain": { "schema": { "type": "string" } } }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{
+        auth::{Auth, Identity},
+        schema::JobSchema,
+        service::Board,
+        testing::Fake,
+    };
+
+    #[test]
+    fn documents_the_namespaces_of_the_caller_with_their_schemas() {
+        let schema = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "type": "object",
+            "properties": { "to": { "$ref": "#/definitions/Address" } },
+            "definitions": { "Address": { "type": "string" } }
+        });
+        let board = Board::new()
+            .add_fake("mailer:emails", Arc::new(Fake::default()))
+            .add_fake("invoices", Arc::new(Fake::default()))
+            .job_schema("mailer:emails", JobSchema::new(schema).unwrap());
+
+        let doc = board.openapi(&Identity::viewer("alice").only(["mailer:emails"]));
+        let paths = doc["paths"].as_object().unwrap();
+        assert!(paths.contains_key("/backend/mailer:emails/job"));
+        assert!(!paths.keys().any(|path| path.contains("invoices")));
+        assert!(!paths.contains_key("/backend/audit"));
+        assert!(!paths.contains_key("/backend/metrics"));
+
+        let pushed = &paths["/backend/mailer:emails/job"]["put"]["requestBody"]["content"]
+            ["application/json"]["schema"];
+        assert_eq!(
+            pushed["properties"]["to"]["$ref"],
+            "#/components/schemas/mailer_emails.Address"
+        );
+        assert!(pushed.get("$schema").is_none());
+        assert_eq!(
+            doc["components"]["schemas"]["mailer_emails.Address"],
+            json!({ "type": "string" })
+        );
+        assert!(doc["components"]["schemas"]["Stat"].is_object());
+    }
+
+    #[test]
+    fn documents_events_and_credentials() {
+        let board = Board::new().add_fake("emails", Arc::new(Fake::default()));
+        let doc = board.openapi(&Identity::anonymous());
+        assert!(doc["paths"].get("/backend/events").is_none());
+        assert!(doc.get("security").is_none());
+        assert!(doc["paths"]["/backend/emails"]["get"]["responses"]
+            .get("401")
+            .is_none());
+
+        let auth = Auth::new().bearer("s3cr3t", Identity::admin("root"));
+        let board = board.with_auth(auth).serving_events();
+        let doc = board.openapi(&Identity::anonymous());
+        let paths = &doc["paths"];
+        assert!(
+            paths["/backend/events"]["get"]["responses"]["200"]["content"]
+                .get("text/event-stream")
+                .is_some()
+        );
+        assert_eq!(
+            doc["components"]["securitySchemes"]["bearerAuth"],
+            json!({ "type": "http", "scheme": "bearer" })
+        );
+        assert_eq!(doc["security"][1], json!({ "basicAuth": [] }));
+
+        let listed = &paths["/backend/emails"]["get"]["responses"];
+        assert!(listed.get("401").is_some());
+        assert!(listed.get("403").is_some());
+        let namespaces = &paths["/backend"]["get"]["responses"];
+        assert!(namespaces.get("401").is_some());
+        assert!(namespaces.get("403").is_none());
+        assert_eq!(paths["/backend/healthz"]["get"]["security"], json!([]));
+        assert!(paths["/backend/readyz"]["get"]["responses"]
+            .get("401")
+            .is_none());
+    }
+}
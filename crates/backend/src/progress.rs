@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::web::Data;
+use apalis_core::task::task_id::TaskId;
+use futures::future::BoxFuture;
+use shared::{BackendExt, BoardEvent, JobProgress};
+
+use crate::sse::Broadcaster;
+
+type PersistFn = Arc<dyn Fn(JobProgress) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// Reports how far along a running job is.
+///
+/// Register it on a worker with `.data(progress)` and take it in a handler as `Data<Progress>`:
+///
+/// ```rust,ignore
+/// async fn import(job: Import, task_id: TaskId, progress: Data<Progress>) {
+///     progress.update(&task_id, 50, "Imported 500 of 1000 rows").await;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Progress {
+    namespace: String,
+    broadcaster: Option<Data<Mutex<Broadcaster>>>,
+    persist: Option<PersistFn>,
+}
+
+impl Progress {
+    pub fn new(namespace: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            broadcaster: None,
+            persist: None,
+        }
+    }
+
+    /// Send every update as a [`BoardEvent::Progress`] to the board's event channel
+    pub fn broadcast_to(mut self, broadcaster: Data<Mutex<Broadcaster>>) -> Self {
+        self.broadcaster = Some(broadcaster);
+        self
+    }
+
+    /// Keep the latest update in the storage so it shows up in the job list
+    pub fn persist_to<J, S>(mut self, storage: &S) -> Self
+    where
+        S: BackendExt<J> + Clone + Send + Sync + 'static,
+    {
+        let storage = storage.clone();
+        self.persist = Some(Arc::new(move |progress| {
+            let storage = storage.clone();
+            Box::pin(async move {
+                // Progress is best effort and should never fail the job
+                let _ = storage.set_progress(&progress).await;
+            })
+        }));
+        self
+    }
+
+    /// Record the progress of a job with a message
+    pub async fn update(&self, task_id: &TaskId, percent: u8, message: impl Into<String>) {
+        self.report(JobProgress::new(task_id, percent, Some(message.into())))
+            .await
+    }
+
+    /// Record the progress of a job without a message
+    pub async fn set_percent(&self, task_id: &TaskId, percent: u8) {
+        self.report(JobProgress::new(task_id, percent, None)).await
+    }
+
+    async fn report(&self, progress: JobProgress) {
+        if let Some(broadcaster) = &self.broadcaster {
+            if let Ok(broadcaster) = broadcaster.lock() {
+                broadcaster.send_event(&BoardEvent::Progress {
+                    namespace: self.namespace.clone(),
+                    progress: progress.clone(),
+                });
+            }
+        }
+        if let Some(persist) = &self.persist {
+            persist(progress).await;
+        }
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("namespace", &self.namespace)
+            .field("broadcast", &self.broadcaster.is_some())
+            .field("persist", &self.persist.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// What [`Action::Mask`] replaces values with
+pub const MASK: &str = "[redacted]";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Replace the value with [`MASK`]
+    Mask,
+    /// Replace the value with its sha256 so equal values can still be matched
+    Hash,
+    /// Remove the value
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Path(Vec<String>),
+    Key(String),
+}
+
+/// What to redact from the payloads of a namespace and how, masked unless said otherwise.
+///
+/// ```rust,ignore
+/// RedactRule::path("customer.address").drop()
+/// RedactRule::key("*token*")
+/// RedactRule::key("email").hash()
+/// ```
+#[derive(Debug, Clone)]
+pub struct RedactRule {
+    target: Target,
+    action: Action,
+}
+
+impl RedactRule {
+    /// The value at a dotted path of the payload, `*` matches any key or index like in `recipients.*.email`
+    pub fn path(path: &str) -> Self {
+        Self {
+            target: Target::Path(path.split('.').map(str::to_string).collect()),
+            action: Action::Mask,
+        }
+    }
+
+    /// The values of keys named like `pattern` anywhere in the payload.
+    /// Case insensitive, `*` matches any characters like in `*token*`.
+    pub fn key(pattern: &str) -> Self {
+        Self {
+            target: Target::Key(pattern.to_ascii_lowercase()),
+            action: Action::Mask,
+        }
+    }
+
+    pub fn mask(self) -> Self {
+        self.action(Action::Mask)
+    }
+
+    pub fn hash(self) -> Self {
+        self.action(Action::Hash)
+    }
+
+    pub fn drop(self) -> Self {
+        self.action(Action::Drop)
+    }
+
+    pub fn action(mut self, action: Action) -> Self {
+        self.action = action;
+        self
+    }
+}
+
+/// The [`RedactRule`]s of every namespace, applied to payloads before they leave the api.
+/// Identities with [`Role::Admin`](crate::auth::Role::Admin) can ask for the raw payloads with `?raw=true`.
+#[derive(Debug, Clone, Default)]
+pub struct Redaction {
+    rules: BTreeMap<String, Vec<RedactRule>>,
+}
+
+impl Redaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, namespace: &str, rule: RedactRule) -> Self {
+        self.rules
+            .entry(namespace.to_string())
+            .or_default()
+            .push(rule);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Redacts a job's payload with the rules of `namespace`
+    pub fn payload(&self, namespace: &str, payload: &mut Value) {
+        for rule in self.rules.get(namespace).into_iter().flatten() {
+            match &rule.target {
+                Target::Path(path) => redact_path(payload, path, rule.action),
+                Target::Key(pattern) => redact_keys(payload, pattern, rule.action),
+            }
+        }
+    }
+
+    /// Redacts a value that isn't a payload, like an event or a log line, with the rules of `namespace`
+    /// or of every namespace if there is none. Key rules apply anywhere in it and path rules to the payloads
+    /// it carries, under `args`, `job` or `job.args`.
+    pub fn message(&self, namespace: Option<&str>, value: &mut Value) {
+        let rules = self
+            .rules
+            .iter()
+            .filter(|(ns, _)| namespace.is_none_or(|namespace| namespace == *ns))
+            .flat_map(|(_, rules)| rules);
+        for rule in rules {
+            match &rule.target {
+                Target::Key(pattern) => redact_keys(value, pattern, rule.action),
+                Target::Path(path) => {
+                    for pointer in PAYLOADS {
+                        if let Some(payload) = value.pointer_mut(pointer) {
+                            redact_path(payload, path, rule.action);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where messages carry payloads, a job's `args` or the job of an audit entry
+const PAYLOADS: [&str; 3] = ["/args", "/job", "/job/args"];
+
+/// Query of the routes returning payloads
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct RawQuery {
+    /// Skip the redaction, only for [`Role::Admin`](crate::auth::Role::Admin)
+    #[serde(default)]
+    pub raw: bool,
+}
+
+fn redact_path(value: &mut Value, path: &[String], action: Action) {
+    let [segment, rest @ ..] = path else {
+        return;
+    };
+    match value {
+        Value::Object(map) => {
+            let keys: Vec<String> = match segment.as_str() {
+                "*" => map.keys().cloned().collect(),
+                key if map.contains_key(key) => vec![key.to_string()],
+                _ => return,
+            };
+            for key in keys {
+                match (rest.is_empty(), action) {
+                    (true, Action::Drop) => {
+                        map.remove(&key);
+                    }
+                    (true, action) => {
+                        if let Some(value) = map.get_mut(&key) {
+                            *value = redacted(value, action);
+                        }
+                    }
+                    (false, _) => {
+                        if let Some(value) = map.get_mut(&key) {
+                            redact_path(value, rest, action);
+                        }
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            let indices: Vec<usize> = match segment.as_str() {
+                "*" => (0..items.len()).collect(),
+                index => match index.parse() {
+                    Ok(index) if index < items.len() => vec![index],
+                    _ => return,
+                },
+            };
+            // Backwards so dropping doesn't shift the indices left to visit
+            for index in indices.into_iter().rev() {
+                match (rest.is_empty(), action) {
+                    (true, Action::Drop) => {
+                        items.remove(index);
+                    }
+                    (true, action) => items[index] = redacted(&items[index], action),
+                    (false, _) => redact_path(&mut items[index], rest, action),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redact_keys(value: &mut Value, pattern: &str, action: Action) {
+    match value {
+        Value::Object(map) => {
+            let keys: Vec<String> = map
+                .keys()
+                .filter(|key| matches(pattern, key))
+                .cloned()
+                .collect();
+            for key in keys {
+                if action == Action::Drop {
+                    map.remove(&key);
+                } else if let Some(value) = map.get_mut(&key) {
+                    *value = redacted(value, action);
+                }
+            }
+            for value in map.values_mut() {
+                redact_keys(value, pattern, action);
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_keys(item, pattern, action);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn redacted(value: &Value, action: Action) -> Value {
+    match action {
+        Action::Hash => {
+            let digest = match value {
+                Value::String(text) => Sha256::digest(text.as_bytes()),
+                value => Sha256::digest(value.to_string().as_bytes()),
+            };
+            Value::String(format!("sha256:{digest:x}"))
+        }
+        Action::Mask | Action::Drop => Value::String(MASK.to_string()),
+    }
+}
+
+/// Matches a lowercase pattern where `*` is any characters, the key is compared case insensitively
+fn matches(pattern: &str, key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = key.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn payloads_are_redacted_by_path_and_key() {
+        let redaction = Redaction::new()
+            .rule("emails", RedactRule::path("customer.address"))
+            .rule("emails", RedactRule::key("*token*").drop());
+        let mut payload = json!({ "customer": { "address": "1 Main St" }, "reset_token": "abc" });
+        redaction.payload("emails", &mut payload);
+        assert_eq!(payload, json!({ "customer": { "address": MASK } }));
+
+        let mut other = json!({ "reset_token": "abc" });
+        redaction.payload("invoices", &mut other);
+        assert_eq!(other, json!({ "reset_token": "abc" }));
+    }
+
+    #[test]
+    fn messages_are_redacted_in_the_payloads_they_carry() {
+        let redaction = Redaction::new()
+            .rule("emails", RedactRule::path("customer.address"))
+            .rule("invoices", RedactRule::key("iban"));
+        let mut event = json!({
+            "namespace": "emails",
+            "job": { "args": { "customer": { "address": "1 Main St" } } },
+            "customer": { "address": "not a payload" },
+            "iban": "DE89"
+        });
+        redaction.message(Some("emails"), &mut event);
+        assert_eq!(event["job"]["args"]["customer"]["address"], MASK);
+        assert_eq!(event["customer"]["address"], "not a payload");
+        assert_eq!(event["iban"], "DE89");
+
+        // Without a namespace every rule applies
+        redaction.message(None, &mut event);
+        assert_eq!(event["iban"], MASK);
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+pub use shared::Run;
+use shared::{AuditEntry, JobState};
+
+use crate::{audit::Audit, service::Queue};
+
+/// How many runs [`Retention::runs`] remembers
+const RUNS: usize = 100;
+
+/// How long the finished jobs of a namespace are kept.
+///
+/// ```rust,ignore
+/// Rule::new()
+///     .keep(JobState::Success, Duration::from_secs(7 * 24 * 60 * 60))
+///     .keep(JobState::Dead, Duration::from_secs(30 * 24 * 60 * 60))
+///     .max(100_000)
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Rule {
+    ages: Vec<(JobState, Duration)>,
+    max: Option<usize>,
+}
+
+impl Rule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remove jobs in `state` that finished more than `age` ago.
+    /// Only success, failed and dead jobs are ever removed.
+    pub fn keep(mut self, state: JobState, age: Duration) -> Self {
+        self.ages.retain(|(kept, _)| *kept != state);
+        self.ages.push((state, age));
+        self
+    }
+
+    /// Keep at most `count` jobs in each of the success, failed and dead states, the oldest go first
+    pub fn max(mut self, count: usize) -> Self {
+        self.max = Some(count);
+        self
+    }
+}
+
+/// Removes finished jobs per namespace [`Rule`]s in the background, the latest runs are served at
+/// `GET /backend/retention`.
+///
+/// Create it once and share clones of it, like [`Metrics`](crate::metrics::Metrics), so a single task
+/// enforces it however many workers the server has. Each run removes jobs `batch` at a time.
+#[derive(Debug, Clone)]
+pub struct Retention {
+    rules: BTreeMap<String, Rule>,
+    interval: Duration,
+    batch: usize,
+    started: Arc<AtomicBool>,
+    runs: Arc<Mutex<VecDeque<Run>>>,
+}
+
+impl Default for Retention {
+    fn default() -> Self {
+        Self {
+            rules: BTreeMap::new(),
+            interval: Duration::from_secs(60 * 60),
+            batch: 1000,
+            started: Default::default(),
+            runs: Default::default(),
+        }
+    }
+}
+
+impl Retention {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn rule(mut self, namespace: &str, rule: Rule) -> Self {
+        self.rules.insert(namespace.to_string(), rule);
+        self
+    }
+
+    /// How often the rules are enforced, hourly by default
+    pub fn every(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How many jobs are removed per query, 1000 by default
+    pub fn batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    /// The latest runs, newest first
+    pub fn runs(&self) -> Vec<Run> {
+        self.runs
+            .lock()
+            .map(|runs| runs.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Starts enforcing the rules on `queues`, only the first call of all the clones does.
+    /// Runs that removed jobs or failed are recorded in `audit` as the `retention` actor.
+    pub(crate) fn spawn(&self, queues: &BTreeMap<String, Arc<dyn Queue>>, audit: Option<Audit>) {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let queues: Vec<(String, Rule, Arc<dyn Queue>)> = self
+            .rules
+            .iter()
+            .filter_map(|(namespace, rule)| {
+                let queue = queues.get(namespace)?.clone();
+                Some((namespace.clone(), rule.clone(), queue))
+            })
+            .collect();
+        let retention = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(retention.interval);
+            loop {
+                interval.tick().await;
+                for (namespace, rule, queue) in &queues {
+                    let run = retention.enforce(namespace, rule, queue.as_ref()).await;
+                    if let Some(audit) = audit
+                        .as_ref()
+                        .filter(|_| run.removed > 0 || run.error.is_some())
+                    {
+                        let entry =
+                            AuditEntry::new("retention", namespace, "purge").after(json!(run));
+                        // Jobs are already removed, a failing sink shouldn't stop the next runs
+                        let _ = audit.record(entry).await;
+                    }
+                    if let Ok(mut runs) = retention.runs.lock() {
+                        if runs.len() == RUNS {
+                            runs.pop_front();
+                        }
+                        runs.push_back(run);
+                    }
+                }
+            }
+        });
+    }
+
+    async fn enforce(&self, namespace: &str, rule: &Rule, queue: &dyn Queue) -> Run {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut run = Run {
+            namespace: namespace.to_string(),
+            started_at: now.as_secs(),
+            removed: 0,
+            error: None,
+        };
+        let now = i64::try_from(now.as_secs()).unwrap_or(i64::MAX);
+        let mut purges: Vec<Purge> = rule
+            .ages
+            .iter()
+            .map(|(state, age)| {
+                let age = i64::try_from(age.as_secs()).unwrap_or(i64::MAX);
+                Purge::Before(*state, now.saturating_sub(age))
+            })
+            .collect();
+        if let Some(max) = rule.max {
+            purges.extend(
+                [JobState::Success, JobState::Failed, JobState::Dead]
+                    .map(|state| Purge::Beyond(state, max)),
+            );
+        }
+        for purge in purges {
+            loop {
+                let res = match purge {
+                    Purge::Before(state, before) => {
+                        queue.purge_before(state, before, self.batch).await
+                    }
+                    Purge::Beyond(state, keep) => queue.purge_beyond(state, keep, self.batch).await,
+                };
+                match res {
+                    Ok(removed) => {
+                        run.removed += removed;
+                        if removed < self.batch {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        run.error = Some(e);
+                        return run;
+                    }
+                }
+            }
+        }
+        run
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Purge {
+    /// Jobs in the state that finished before the timestamp
+    Before(JobState, i64),
+    /// Jobs in the state beyond the newest ones
+    Beyond(JobState, usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Fake;
+
+    #[actix_web::test]
+    async fn runs_remove_jobs_a_batch_at_a_time() {
+        let retention = Retention::new().batch(10);
+        let rule = Rule::new()
+            .keep(JobState::Success, Duration::from_secs(60))
+            .keep(JobState::Success, Duration::from_secs(3600))
+            .max(100);
+        assert_eq!(rule.ages.len(), 1);
+
+        let queue = Fake::default();
+        *queue.finished.lock().unwrap() = 25;
+        let run = retention.enforce("emails", &rule, &queue).await;
+        assert_eq!(run.namespace, "emails");
+        assert_eq!(run.removed, 25);
+        assert_eq!(run.error, None);
+        assert_eq!(*queue.finished.lock().unwrap(), 0);
+
+        let run = retention
+            .enforce("emails", &rule, &Fake::down("gone"))
+            .await;
+        assert_eq!(run.removed, 0);
+        assert_eq!(run.error.as_deref(), Some("gone"));
+    }
+
+    #[actix_web::test]
+    async fn only_the_first_clone_spawns() {
+        let retention = Retention::new()
+            .rule("emails", Rule::new().max(0))
+            .every(Duration::from_millis(10));
+        let queue = Arc::new(Fake::default());
+        *queue.finished.lock().unwrap() = 3;
+        let queues: BTreeMap<String, Arc<dyn Queue>> =
+            BTreeMap::from([("emails".to_string(), queue.clone() as Arc<dyn Queue>)]);
+
+        let other = Arc::new(Fake::default());
+        *other.finished.lock().unwrap() = 5;
+        let others: BTreeMap<String, Arc<dyn Queue>> =
+            BTreeMap::from([("emails".to_string(), other.clone() as Arc<dyn Queue>)]);
+
+        retention.spawn(&queues, None);
+        retention.clone().spawn(&others, None);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let runs = retention.runs();
+        assert!(!runs.is_empty());
+        assert_eq!(runs.iter().map(|run| run.removed).sum::<usize>(), 3);
+        assert_eq!(*other.finished.lock().unwrap(), 5);
+    }
+}
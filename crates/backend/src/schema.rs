@@ -0,0 +1,142 @@
+use std::{collections::HashMap, sync::Arc};
+
+use jsonschema::Validator;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+
+/// A reason a payload doesn't match the job schema of a namespace
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    /// JSON pointer to the offending value
+    pub path: String,
+    pub message: String,
+}
+
+/// The job schema of a namespace with its compiled validator
+#[derive(Clone)]
+pub struct JobSchema {
+    schema: Value,
+    validator: Arc<Validator>,
+}
+
+impl JobSchema {
+    /// Use a schema supplied as json, fails if it isn't a valid JSON Schema
+    pub fn new(schema: Value) -> Result<Self, String> {
+        let validator = jsonschema::validator_for(&schema).map_err(|e| e.to_string())?;
+        Ok(Self {
+            schema,
+            validator: Arc::new(validator),
+        })
+    }
+
+    /// Derive the schema from the job type
+    pub fn of<J: JsonSchema>() -> Self {
+        let schema = serde_json::to_value(schemars::schema_for!(J))
+            .expect("a derived schema is always valid json");
+        Self::new(schema).expect("a derived schema is always a valid JSON Schema")
+    }
+
+    pub fn schema(&self) -> &Value {
+        &self.schema
+    }
+
+    pub fn validate(&self, payload: &Value) -> Result<(), Vec<Violation>> {
+        let violations: Vec<Violation> = self
+            .validator
+            .iter_errors(payload)
+            .map(|e| Violation {
+                path: e.instance_path.to_string(),
+                message: e.to_string(),
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+impl std::fmt::Debug for JobSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JobSchema")
+            .field("schema", &self.schema)
+            .finish()
+    }
+}
+
+/// Job schemas registered with a [`Board`](crate::service::Board), by namespace
+#[derive(Debug, Clone, Default)]
+pub struct JobSchemas(pub(crate) HashMap<String, JobSchema>);
+
+impl JobSchemas {
+    pub fn get(&self, namespace: &str) -> Option<&JobSchema> {
+        self.0.get(namespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        auth::Identity,
+        service::{Board, Body},
+        testing::Fake,
+    };
+
+    fn email() -> JobSchema {
+        JobSchema::new(json!({
+            "type": "object",
+            "required": ["to"],
+            "properties": { "to": { "type": "string" } }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn violations_point_at_the_offending_value() {
+        assert!(email()
+            .validate(&json!({ "to": "test@example.com" }))
+            .is_ok());
+        let violations = email().validate(&json!({ "to": 42 })).unwrap_err();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "/to");
+        assert!(JobSchema::new(json!({ "type": "no-such-type" })).is_err());
+    }
+
+    #[actix_web::test]
+    async fn jobs_not_matching_the_schema_are_rejected() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new()
+            .add_fake("emails", queue.clone())
+            .add_fake("invoices", Arc::new(Fake::default()))
+            .job_schema("emails", email());
+        let identity = Identity::anonymous();
+
+        let reply = board
+            .push_job(&identity, "emails", json!({ "to": 42 }), None)
+            .await;
+        assert_eq!(reply.status, 422);
+        let Body::Json(body) = reply.body else {
+            panic!("expected json");
+        };
+        assert_eq!(body["errors"][0]["path"], "/to");
+        assert!(queue.jobs.lock().unwrap().is_empty());
+
+        let reply = board
+            .push_job(
+                &identity,
+                "emails",
+                json!({ "to": "test@example.com" }),
+                None,
+            )
+            .await;
+        assert_eq!(reply.status, 200);
+
+        assert_eq!(board.get_schema(&identity, "emails").status, 200);
+        assert_eq!(board.get_schema(&identity, "invoices").status, 404);
+    }
+}
@@ -0,0 +1,1643 @@
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use apalis_core::{storage::Storage, task::task_id::TaskId};
+use futures::future::{join_all, BoxFuture};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use shared::{
+    cache::{self, Cached},
+    BackendExt, Filter, GetJobsResult, JobState, Namespace, NamespaceGroup, NamespaceInfo,
+    Readiness, RequestExt, ReservedKey, Stat,
+};
+pub use shared::{Enqueue, Health, Queued};
+use tokio::{sync::RwLock, time::timeout};
+
+#[cfg(feature = "federation")]
+use crate::federation::Federation;
+use crate::{
+    audit::{Audit, AuditEntry, AuditFilter},
+    auth::{Auth, Headers, Identity, Role},
+    export::{Export, ExportQuery},
+    import::{self, ImportOptions, ImportReport, LineError},
+    metrics::Metrics,
+    openapi,
+    redact::Redaction,
+    retention::{Retention, Run},
+    schema::{JobSchema, JobSchemas},
+};
+
+/// The answer to a board api call, turned into a response by the http framework serving it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub status: u16,
+    pub body: Body,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Body {
+    Empty,
+    Text(String),
+    Json(Value),
+    /// Text in another format, like the Prometheus exposition
+    Raw {
+        content_type: &'static str,
+        body: String,
+    },
+}
+
+impl Reply {
+    pub fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        match serde_json::to_value(value) {
+            Ok(value) => Self {
+                status,
+                body: Body::Json(value),
+            },
+            Err(e) => Self::text(500, e),
+        }
+    }
+
+    pub fn text(status: u16, text: impl Display) -> Self {
+        Self {
+            status,
+            body: Body::Text(text.to_string()),
+        }
+    }
+
+    pub fn empty(status: u16) -> Self {
+        Self {
+            status,
+            body: Body::Empty,
+        }
+    }
+
+    /// The `ETag` of a successful reply with a body, a hash of the body
+    pub fn etag(&self) -> Option<String> {
+        let digest = match (self.status, &self.body) {
+            (200, Body::Text(text)) | (200, Body::Raw { body: text, .. }) => Sha256::digest(text),
+            (200, Body::Json(value)) => Sha256::digest(value.to_string()),
+            _ => return None,
+        };
+        let hex = format!("{digest:x}");
+        Some(format!("\"{}\"", &hex[..32]))
+    }
+}
+
+/// Whether an `If-None-Match` header matches `etag`, weak tags included
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// A storage as seen by the board, with its job type erased
+pub(crate) trait Queue: Send + Sync + 'static {
+    fn jobs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Reply>;
+
+    /// A page of jobs in `status` with their task ids, serialized as they are listed.
+    /// Pages start at 1, an empty one is past the last.
+    fn page(
+        &self,
+        status: JobState,
+        page: i32,
+    ) -> BoxFuture<'_, Result<Vec<(String, Value)>, String>>;
+
+    fn workers(&self) -> BoxFuture<'_, Reply>;
+
+    /// Queues the jobs in order, scheduled at the unix timestamp if there is one.
+    /// Nothing is queued unless every job deserializes.
+    fn push(
+        &self,
+        jobs: Vec<(Value, Option<i64>)>,
+    ) -> BoxFuture<'_, Result<Vec<TaskId>, PushError>>;
+
+    fn job<'a>(&'a self, task_id: &'a TaskId) -> BoxFuture<'a, Reply>;
+
+    /// Whether `job` deserializes to the job type of the storage
+    fn validate(&self, job: &Value) -> Result<(), String>;
+
+    /// The stats and worker count, `None` if the storage can't be queried
+    fn snapshot(&self) -> BoxFuture<'_, Option<(Stat, usize)>>;
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>>;
+
+    fn purge_before(
+        &self,
+        state: JobState,
+        before: i64,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>>;
+
+    fn purge_beyond(
+        &self,
+        state: JobState,
+        keep: usize,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>>;
+
+    fn reserve_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        lease: Duration,
+    ) -> BoxFuture<'a, Result<Option<ReservedKey>, String>>;
+
+    fn settle_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        outcome: Option<String>,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), String>>;
+
+    /// Whether pushes with an idempotency key can be served, the keys being kept by [`Queue::reserve_key`]
+    fn remembers_keys(&self) -> bool {
+        true
+    }
+}
+
+/// Names of the fixed routes of the board, reserved whether or not they are served
+/// so turning on audit or metrics later can't shadow a namespace
+pub const RESERVED: [&str; 8] = [
+    "openapi.json",
+    "healthz",
+    "readyz",
+    "groups",
+    "events",
+    "audit",
+    "metrics",
+    "retention",
+];
+
+/// Why a [`Board`] can't be served, see [`Board::check`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NamespaceError {
+    #[error("namespace {0} was added twice, qualify one of them with `Namespace::qualified`")]
+    Duplicate(String),
+    #[error("namespace {0} is shadowed by the board's route `/backend/{0}`")]
+    Reserved(String),
+    #[error("namespace {0:?} can't be a path segment")]
+    Invalid(String),
+}
+
+/// Why [`Queue::push`] didn't queue every job
+pub(crate) enum PushError {
+    /// The job at this index doesn't deserialize, nothing was queued
+    Invalid(usize, String),
+    /// The storage failed after queueing these
+    Failed(Vec<TaskId>, String),
+}
+
+struct StorageQueue<S> {
+    storage: RwLock<S>,
+    /// A clone of the storage answering the stats
+    stats: Cached<S>,
+}
+
+impl<J, S> Queue for StorageQueue<S>
+where
+    J: Serialize + DeserializeOwned + Send + 'static,
+    S: BackendExt<J> + Storage<Job = J> + Send + Sync + 'static,
+    S::Context: Serialize + Send,
+    S::Request: Serialize + RequestExt + Send,
+    <S as Storage>::Error: Display + Send,
+    <S as BackendExt<J>>::Error: Display + Send,
+{
+    fn jobs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Reply> {
+        Box::pin(async move {
+            let stats = match self.stats.stats().await {
+                Ok(stats) => stats,
+                Err(e) => return Reply::text(500, e),
+            };
+            let storage = self.storage.read().await;
+            let jobs = match storage.list_jobs(&filter.status, filter.page).await {
+                Ok(jobs) => jobs,
+                Err(e) => return Reply::text(500, e),
+            };
+            let task_ids: Vec<_> = jobs.iter().map(|j| j.task_id().clone()).collect();
+            let progress = storage.list_progress(&task_ids).await.unwrap_or_default();
+            Reply::json(
+                200,
+                &GetJobsResult {
+                    stats,
+                    jobs,
+                    progress,
+                },
+            )
+        })
+    }
+
+    fn workers(&self) -> BoxFuture<'_, Reply> {
+        Box::pin(async move {
+            match self.storage.read().await.list_workers().await {
+                Ok(workers) => Reply::json(200, &workers),
+                Err(e) => Reply::text(500, e),
+            }
+        })
+    }
+
+    fn push(
+        &self,
+        jobs: Vec<(Value, Option<i64>)>,
+    ) -> BoxFuture<'_, Result<Vec<TaskId>, PushError>> {
+        Box::pin(async move {
+            let jobs = jobs
+                .into_iter()
+                .enumerate()
+                .map(|(index, (job, run_at))| {
+                    serde_json::from_value::<J>(job)
+                        .map(|job| (job, run_at))
+                        .map_err(|e| PushError::Invalid(index, e.to_string()))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut storage = self.storage.write().await;
+            let mut queued = Vec::with_capacity(jobs.len());
+            for (job, run_at) in jobs {
+                let res = match run_at {
+                    Some(on) => storage.schedule(job, on).await,
+                    None => storage.push(job).await,
+                };
+                match res {
+                    Ok(parts) => queued.push(parts.task_id),
+                    Err(e) => {
+                        self.stats.invalidate().await;
+                        return Err(PushError::Failed(queued, e.to_string()));
+                    }
+                }
+            }
+            self.stats.invalidate().await;
+            Ok(queued)
+        })
+    }
+
+    fn page(
+        &self,
+        status: JobState,
+        page: i32,
+    ) -> BoxFuture<'_, Result<Vec<(String, Value)>, String>> {
+        Box::pin(async move {
+            let jobs = {
+                let storage = self.storage.read().await;
+                storage
+                    .list_jobs(&status, page)
+                    .await
+                    .map_err(|e| e.to_string())?
+            };
+            jobs.into_iter()
+                .map(|job| {
+                    let task_id = job.task_id().to_string();
+                    serde_json::to_value(job)
+                        .map(|job| (task_id, job))
+                        .map_err(|e| e.to_string())
+                })
+                .collect()
+        })
+    }
+
+    fn validate(&self, job: &Value) -> Result<(), String> {
+        J::deserialize(job).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    fn job<'a>(&'a self, task_id: &'a TaskId) -> BoxFuture<'a, Reply> {
+        Box::pin(async move {
+            let res = self.storage.write().await.fetch_by_id(task_id).await;
+            match res {
+                Ok(Some(job)) => Reply::json(200, &job),
+                Ok(None) => Reply::empty(404),
+                Err(e) => Reply::text(500, e),
+            }
+        })
+    }
+
+    fn snapshot(&self) -> BoxFuture<'_, Option<(Stat, usize)>> {
+        Box::pin(async move {
+            let stats = self.stats.stats().await.ok()?;
+            let workers = self.storage.read().await.list_workers().await.ok()?;
+            Some((stats, workers.len()))
+        })
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            let storage = self.storage.read().await;
+            storage.ping().await.map_err(|e| e.to_string())
+        })
+    }
+
+    fn purge_before(
+        &self,
+        state: JobState,
+        before: i64,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Box::pin(async move {
+            let storage = self.storage.read().await;
+            storage
+                .purge_before(&state, before, limit)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn purge_beyond(
+        &self,
+        state: JobState,
+        keep: usize,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Box::pin(async move {
+            let storage = self.storage.read().await;
+            storage
+                .purge_beyond(&state, keep, limit)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn reserve_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        lease: Duration,
+    ) -> BoxFuture<'a, Result<Option<ReservedKey>, String>> {
+        Box::pin(async move {
+            let storage = self.storage.read().await;
+            storage
+                .reserve_key(key, fingerprint, lease)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+
+    fn settle_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        outcome: Option<String>,
+        ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let storage = self.storage.read().await;
+            storage
+                .settle_key(key, fingerprint, outcome.as_deref(), ttl)
+                .await
+                .map_err(|e| e.to_string())
+        })
+    }
+}
+
+/// The kind of a storage from its type, `PostgresStorage<Email>` is `postgres`
+fn backend_kind<S>() -> String {
+    let name = type_name::<S>();
+    let name = name.split('<').next().unwrap_or(name);
+    let name = name.rsplit("::").next().unwrap_or(name);
+    name.strip_suffix("Storage")
+        .unwrap_or(name)
+        .to_ascii_lowercase()
+}
+
+/// Records the size of a listed job's payload and swaps it for a preview of its first `limit` bytes of json
+/// if it is larger, the full payload is left to `get_job`
+fn preview(job: &mut Value, limit: usize) {
+    let Some(job) = job.as_object_mut() else {
+        return;
+    };
+    let Some(payload) = job.get("args") else {
+        return;
+    };
+    let text = payload.to_string();
+    let size = text.len();
+    if size > limit {
+        let mut end = limit;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        job.remove("args");
+        job.insert(
+            "preview".to_string(),
+            Value::String(text[..end].to_string()),
+        );
+    }
+    job.insert("size".to_string(), size.into());
+}
+
+/// The answer to a push of a single job
+fn pushed(queued: Vec<Queued>) -> Reply {
+    match queued.first() {
+        Some(queued) => Reply::text(
+            200,
+            format!("Job with ID [{}] added to queue", queued.task_id),
+        ),
+        None => Reply::empty(500),
+    }
+}
+
+/// The answer to a push of a batch
+fn pushed_batch(queued: Vec<Queued>) -> Reply {
+    Reply::json(200, &json!({ "jobs": queued }))
+}
+
+/// The hash of a pushed body, telling a retry from another push reusing its idempotency key
+fn fingerprint<T: Serialize>(body: &T) -> String {
+    let body = serde_json::to_string(body).unwrap_or_default();
+    format!("{:x}", Sha256::digest(body))
+}
+
+/// The header making pushes idempotent
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+const DEFAULT_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// How long a key is held by a push that hasn't settled it, so one that died doesn't block retries for a day
+const IDEMPOTENCY_LEASE: Duration = Duration::from_secs(60);
+
+/// Largest payload, in bytes of json, listed in full unless [`Board::preview_limit`] says otherwise
+pub const DEFAULT_PREVIEW_LIMIT: usize = 4 * 1024;
+
+/// How long a storage gets to answer a readiness check
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The board api without any http framework.
+///
+/// Authorizes, validates and audits every call before handing it to the storage of the namespace.
+/// [`ApiBuilder`](crate::api::ApiBuilder) serves it with actix-web
+/// and, with the `axum` feature, `axum::RouterBuilder` with axum.
+#[derive(Clone, Default)]
+pub struct Board {
+    queues: BTreeMap<String, Arc<dyn Queue>>,
+    auth: Option<Auth>,
+    audit: Option<Audit>,
+    schemas: JobSchemas,
+    metrics: Option<Metrics>,
+    idempotency_ttl: Option<Duration>,
+    retention: Option<Retention>,
+    redaction: Redaction,
+    preview_limits: BTreeMap<String, usize>,
+    stats_ttl: Option<Duration>,
+    metadata: BTreeMap<String, Namespace>,
+    duplicates: Vec<String>,
+    /// Whether the adapter serves `GET /backend/events`, only for the OpenAPI document
+    events: bool,
+}
+
+impl Board {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve `storage` under a namespace, either just its name or a [`Namespace`] describing it
+    pub fn add_storage<J, S>(self, storage: &S, namespace: impl Into<Namespace>) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
+        let ttl = self.stats_ttl.unwrap_or(cache::DEFAULT_TTL);
+        let queue = StorageQueue {
+            storage: RwLock::new(storage.clone()),
+            stats: Cached::new(storage.clone()).ttl(ttl),
+        };
+        let mut namespace = namespace.into();
+        namespace.backend.get_or_insert_with(backend_kind::<S>);
+        self.add_queue(namespace, Arc::new(queue))
+    }
+
+    /// Serve `storage` like [`Board::add_storage`] with the schema of its jobs, see [`Board::job_schema`]
+    pub fn add_storage_with_schema<J, S>(
+        self,
+        storage: &S,
+        namespace: impl Into<Namespace>,
+        schema: JobSchema,
+    ) -> Self
+    where
+        J: Serialize + DeserializeOwned + Send + 'static,
+        S: BackendExt<J> + Storage<Job = J> + Clone + Send + Sync + 'static,
+        S::Context: Serialize + Send,
+        S::Request: Serialize + RequestExt + Send,
+        <S as Storage>::Error: Display + Send,
+        <S as BackendExt<J>>::Error: Display + Send,
+    {
+        let namespace = namespace.into();
+        let key = namespace.key();
+        self.add_storage(storage, namespace)
+            .job_schema(&key, schema)
+    }
+
+    /// Serve the namespaces of the remote boards of `federation`, see [`Federation`]
+    #[cfg(feature = "federation")]
+    pub fn federate(mut self, federation: &Federation) -> Self {
+        for (namespace, queue) in federation.queues() {
+            self = self.add_queue(namespace, queue);
+        }
+        self
+    }
+
+    pub(crate) fn add_queue(mut self, namespace: Namespace, queue: Arc<dyn Queue>) -> Self {
+        let key = namespace.key();
+        if self.queues.insert(key.clone(), queue).is_some() {
+            self.duplicates.push(key.clone());
+        }
+        self.metadata.insert(key, namespace);
+        self
+    }
+
+    /// Fails if two storages were added under the same namespace, or one can't be reached at `/backend/{namespace}`
+    /// because a route of the board shadows it
+    pub fn check(&self) -> Result<(), NamespaceError> {
+        if let Some(key) = self.duplicates.first() {
+            return Err(NamespaceError::Duplicate(key.clone()));
+        }
+        for key in self.queues.keys() {
+            if key.is_empty() || key.contains(['/', '?', '#']) {
+                return Err(NamespaceError::Invalid(key.clone()));
+            }
+            if RESERVED.contains(&key.as_str()) {
+                return Err(NamespaceError::Reserved(key.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the stats of the storages added after this for `ttl`, a second by default.
+    /// Concurrent listings share one stats query either way.
+    pub fn cache_stats_for(mut self, ttl: Duration) -> Self {
+        self.stats_ttl = Some(ttl);
+        self
+    }
+
+    /// Require every call to be made by an identity verified by `auth`
+    pub fn with_auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Describe the jobs of `namespace`, either derived with [`JobSchema::of`] or supplied with [`JobSchema::new`].
+    /// Pushed jobs that don't match the schema are rejected with a 422.
+    pub fn job_schema(mut self, namespace: &str, schema: JobSchema) -> Self {
+        self.schemas.0.insert(namespace.to_string(), schema);
+        self
+    }
+
+    /// List the jobs of `namespace` with payloads of up to `bytes` of json, larger ones are cut to a preview.
+    /// [`DEFAULT_PREVIEW_LIMIT`] otherwise.
+    pub fn preview_limit(mut self, namespace: &str, bytes: usize) -> Self {
+        self.preview_limits.insert(namespace.to_string(), bytes);
+        self
+    }
+
+    /// Record every mutating call in `audit`, and the runs of the [`Retention`] that removed jobs
+    pub fn with_audit(mut self, audit: Audit) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Serve `metrics` at `GET /backend/metrics`, the adapters also count their requests in it
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Redact the payloads of jobs before they leave the api
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+        self
+    }
+
+    pub fn redaction(&self) -> &Redaction {
+        &self.redaction
+    }
+
+    /// Remove finished jobs per the rules of `retention`, see [`Board::start_retention`]
+    pub fn with_retention(mut self, retention: Retention) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    pub fn has_retention(&self) -> bool {
+        self.retention.is_some()
+    }
+
+    /// Spawns the task enforcing the [`Retention`], once however many boards share it.
+    /// Called by the adapters when they are built, within the runtime of the server.
+    pub fn start_retention(&self) {
+        if let Some(retention) = &self.retention {
+            retention.spawn(&self.queues, self.audit.clone());
+        }
+    }
+
+    /// How long idempotency keys are remembered, a day by default
+    pub fn idempotency_ttl(&self) -> Duration {
+        self.idempotency_ttl.unwrap_or(DEFAULT_IDEMPOTENCY_TTL)
+    }
+
+    /// Remember the jobs pushed with an `Idempotency-Key` for `ttl`
+    pub fn remember_keys_for(mut self, ttl: Duration) -> Self {
+        self.idempotency_ttl = Some(ttl);
+        self
+    }
+
+    pub fn metrics(&self) -> Option<&Metrics> {
+        self.metrics.as_ref()
+    }
+
+    /// All namespaces, sorted
+    pub fn namespaces(&self) -> Vec<String> {
+        self.queues.keys().cloned().collect()
+    }
+
+    pub fn has_audit(&self) -> bool {
+        self.audit.is_some()
+    }
+
+    /// Document `GET /backend/events`, the adapters call this when given a broadcaster
+    pub(crate) fn serving_events(mut self) -> Self {
+        self.events = true;
+        self
+    }
+
+    /// The OpenAPI document of the api as seen by `identity`, without the namespaces it can't access.
+    /// See [`openapi::document`].
+    pub fn openapi(&self, identity: &Identity) -> Value {
+        let namespaces: Vec<String> = self
+            .queues
+            .keys()
+            .filter(|namespace| identity.can_access(namespace))
+            .cloned()
+            .collect();
+        let routes = openapi::Routes {
+            audit: self.has_audit(),
+            metrics: self.metrics.is_some(),
+            retention: self.has_retention(),
+            events: self.events,
+            auth: self.auth.is_some(),
+        };
+        openapi::document(&namespaces, &self.schemas, routes)
+    }
+
+    /// Who is making a call, `None` if [`Auth`] is configured and rejects the credentials
+    pub async fn identify(&self, headers: &Headers) -> Option<Identity> {
+        match &self.auth {
+            None => Some(Identity::anonymous()),
+            Some(auth) => auth.identify(headers).await,
+        }
+    }
+
+    fn queue(&self, identity: &Identity, namespace: &str, role: Role) -> Result<&dyn Queue, Reply> {
+        let queue = self
+            .queues
+            .get(namespace)
+            .ok_or_else(|| Reply::empty(404))?;
+        if !identity.allows(role, namespace) {
+            return Err(Reply::text(403, "forbidden"));
+        }
+        Ok(queue.as_ref())
+    }
+
+    /// Lists the namespaces `identity` can access with their metadata and current stats
+    pub async fn list_namespaces(&self, identity: &Identity) -> Reply {
+        Reply::json(200, &self.namespace_infos(identity).await)
+    }
+
+    /// Lists the namespaces `identity` can access by logical group, the ungrouped ones first
+    pub async fn list_groups(&self, identity: &Identity) -> Reply {
+        let mut groups: BTreeMap<Option<String>, Vec<NamespaceInfo>> = BTreeMap::new();
+        for info in self.namespace_infos(identity).await {
+            groups
+                .entry(info.namespace.group.clone())
+                .or_default()
+                .push(info);
+        }
+        let groups: Vec<NamespaceGroup> = groups
+            .into_iter()
+            .map(|(group, namespaces)| NamespaceGroup { group, namespaces })
+            .collect();
+        Reply::json(200, &groups)
+    }
+
+    async fn namespace_infos(&self, identity: &Identity) -> Vec<NamespaceInfo> {
+        let visible = self
+            .queues
+            .iter()
+            .filter(|(key, _)| identity.can_access(key))
+            .map(|(key, queue)| async move {
+                let snapshot = queue.snapshot().await;
+                NamespaceInfo {
+                    namespace: self
+                        .metadata
+                        .get(key)
+                        .cloned()
+                        .unwrap_or_else(|| Namespace::new(key)),
+                    stats: snapshot.as_ref().map(|(stats, _)| stats.clone()),
+                    workers: snapshot.map(|(_, workers)| workers),
+                }
+            });
+        join_all(visible).await
+    }
+
+    /// Lists jobs with their payloads redacted, unless `raw` is asked by an admin, and cut to a preview when large
+    pub async fn list_jobs(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        filter: &Filter,
+        raw: bool,
+    ) -> Reply {
+        let queue = match self.raw_queue(identity, namespace, raw) {
+            Ok(queue) => queue,
+            Err(reply) => return reply,
+        };
+        let mut reply = queue.jobs(filter).await;
+        if let Body::Json(value) = &mut reply.body {
+            let limit = self
+                .preview_limits
+                .get(namespace)
+                .copied()
+                .unwrap_or(DEFAULT_PREVIEW_LIMIT);
+            let jobs = value.get_mut("jobs").and_then(Value::as_array_mut);
+            for job in jobs.into_iter().flatten() {
+                if !raw {
+                    self.redact_job(namespace, job);
+                }
+                preview(job, limit);
+            }
+        }
+        reply
+    }
+
+    pub async fn list_workers(&self, identity: &Identity, namespace: &str) -> Reply {
+        match self.queue(identity, namespace, Role::Viewer) {
+            Ok(queue) => queue.workers().await,
+            Err(reply) => reply,
+        }
+    }
+
+    /// Fetches a job with its payload redacted, unless `raw` is asked by an admin
+    pub async fn get_job(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        task_id: &TaskId,
+        raw: bool,
+    ) -> Reply {
+        let queue = match self.raw_queue(identity, namespace, raw) {
+            Ok(queue) => queue,
+            Err(reply) => return reply,
+        };
+        let mut reply = queue.job(task_id).await;
+        if let (false, Body::Json(job)) = (raw, &mut reply.body) {
+            self.redact_job(namespace, job);
+        }
+        reply
+    }
+
+    /// The queue of a namespace `identity` can read, raw payloads need [`Role::Admin`]
+    fn raw_queue(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        raw: bool,
+    ) -> Result<&dyn Queue, Reply> {
+        let queue = self.queue(identity, namespace, Role::Viewer)?;
+        if raw && !identity.allows(Role::Admin, namespace) {
+            return Err(Reply::text(403, "raw payloads are only for admins"));
+        }
+        Ok(queue)
+    }
+
+    /// Redacts the payload of a serialized job
+    fn redact_job(&self, namespace: &str, job: &mut Value) {
+        if let Some(payload) = job.get_mut("args") {
+            self.redaction.payload(namespace, payload);
+        }
+    }
+
+    /// Streams every job of `namespace` in the query's status, reading the storage a page at a time
+    pub fn export_jobs(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        query: &ExportQuery,
+    ) -> Result<Export, Reply> {
+        self.raw_queue(identity, namespace, query.raw)?;
+        let queue = self
+            .queues
+            .get(namespace)
+            .cloned()
+            .ok_or_else(|| Reply::empty(404))?;
+        let redaction = (!query.raw).then(|| self.redaction.clone());
+        Ok(Export::new(queue, namespace, query, redaction))
+    }
+
+    /// Queues a job. With an idempotency key, a retry answers like the first push instead of queueing again.
+    pub async fn push_job(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        payload: Value,
+        idempotency_key: Option<&str>,
+    ) -> Reply {
+        let queue = match self.queue(identity, namespace, Role::Operator) {
+            Ok(queue) => queue,
+            Err(reply) => return reply,
+        };
+        if let Some(schema) = self.schemas.get(namespace) {
+            if let Err(errors) = schema.validate(&payload) {
+                return Reply::json(422, &json!({ "errors": errors }));
+            }
+        }
+        let payload_fingerprint = fingerprint(&payload);
+        let key = match self
+            .reserve(queue, idempotency_key, &payload_fingerprint, pushed)
+            .await
+        {
+            Ok(key) => key,
+            Err(reply) => return reply,
+        };
+        let snapshot = self.audit.as_ref().map(|_| payload.clone());
+        let res = queue.push(vec![(payload, None)]).await;
+        let task_id = match res.map(|task_ids| task_ids.into_iter().next()) {
+            Ok(Some(task_id)) => task_id,
+            Ok(None) => return Reply::empty(500),
+            Err(PushError::Invalid(_, e)) => {
+                self.settle(queue, key, &[]).await;
+                return Reply::text(400, e);
+            }
+            Err(PushError::Failed(_, e)) => {
+                self.settle(queue, key, &[]).await;
+                return Reply::text(500, e);
+            }
+        };
+        self.record_push(identity, namespace, &task_id, snapshot, None)
+            .await;
+        let queued = Queued {
+            task_id: task_id.to_string(),
+            run_at: None,
+        };
+        self.settle(queue, key, std::slice::from_ref(&queued)).await;
+        pushed(vec![queued])
+    }
+
+    /// Queues a batch of jobs, all of them are validated before any is queued.
+    /// Answers with the task id of every queued job, including the ones queued before the storage failed.
+    /// With an idempotency key, a retry answers with the jobs the first push queued.
+    pub async fn push_jobs(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        batch: Vec<Enqueue>,
+        idempotency_key: Option<&str>,
+    ) -> Reply {
+        let queue = match self.queue(identity, namespace, Role::Operator) {
+            Ok(queue) => queue,
+            Err(reply) => return reply,
+        };
+        if let Some(schema) = self.schemas.get(namespace) {
+            let errors: Vec<Value> = batch
+                .iter()
+                .enumerate()
+                .flat_map(|(index, enqueue)| {
+                    let violations = schema.validate(&enqueue.job).err().unwrap_or_default();
+                    violations.into_iter().map(move |violation| {
+                        json!({ "index": index, "path": violation.path, "message": violation.message })
+                    })
+                })
+                .collect();
+            if !errors.is_empty() {
+                return Reply::json(422, &json!({ "errors": errors }));
+            }
+        }
+        let batch_fingerprint = fingerprint(&batch);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let mut jobs = Vec::with_capacity(batch.len());
+        for (index, enqueue) in batch.into_iter().enumerate() {
+            let run_at = match (enqueue.run_at, enqueue.delay) {
+                (Some(_), Some(_)) => {
+                    return Reply::json(
+                        400,
+                        &json!({ "errors": [{ "index": index, "message": "set either run_at or delay" }] }),
+                    )
+                }
+                (Some(run_at), None) => Some(run_at),
+                (None, Some(delay)) => {
+                    Some(now.saturating_add(i64::try_from(delay).unwrap_or(i64::MAX)))
+                }
+                (None, None) => None,
+            };
+            jobs.push((enqueue.job, run_at));
+        }
+        let run_ats: Vec<Option<i64>> = jobs.iter().map(|(_, run_at)| *run_at).collect();
+        let snapshots: Vec<Option<Value>> = jobs
+            .iter()
+            .map(|(job, _)| self.audit.as_ref().map(|_| job.clone()))
+            .collect();
+        let key = match self
+            .reserve(queue, idempotency_key, &batch_fingerprint, pushed_batch)
+            .await
+        {
+            Ok(key) => key,
+            Err(reply) => return reply,
+        };
+        let (task_ids, error) = match queue.push(jobs).await {
+            Ok(task_ids) => (task_ids, None),
+            Err(PushError::Invalid(index, message)) => {
+                self.settle(queue, key, &[]).await;
+                return Reply::json(
+                    400,
+                    &json!({ "errors": [{ "index": index, "message": message }] }),
+                );
+            }
+            Err(PushError::Failed(task_ids, message)) => (task_ids, Some(message)),
+        };
+        let mut queued = Vec::with_capacity(task_ids.len());
+        for ((task_id, run_at), snapshot) in task_ids.iter().zip(run_ats).zip(snapshots) {
+            self.record_push(identity, namespace, task_id, snapshot, run_at)
+                .await;
+            queued.push(Queued {
+                task_id: task_id.to_string(),
+                run_at,
+            });
+        }
+        self.settle(queue, key, &queued).await;
+        match error {
+            None => pushed_batch(queued),
+            Some(error) => Reply::json(500, &json!({ "jobs": queued, "error": error })),
+        }
+    }
+
+    /// Queues the jobs of an NDJSON body, see [`import::parse`](crate::import::parse) for the lines it reads.
+    /// Every line is checked against the job schema and the job type first, nothing is queued unless all pass.
+    pub async fn import_jobs(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        ndjson: &str,
+        options: &ImportOptions,
+    ) -> Reply {
+        let queue = match self.queue(identity, namespace, Role::Operator) {
+            Ok(queue) => queue,
+            Err(reply) => return reply,
+        };
+        let (lines, mut errors) = import::parse(ndjson, options.keep_run_at);
+        let schema = self.schemas.get(namespace);
+        let mut valid = 0;
+        for line in &lines {
+            let violations = schema
+                .and_then(|schema| schema.validate(&line.job).err())
+                .unwrap_or_default();
+            if !violations.is_empty() {
+                errors.extend(violations.into_iter().map(|violation| LineError {
+                    line: line.line,
+                    message: format!("{}: {}", violation.path, violation.message),
+                }));
+                continue;
+            }
+            match queue.validate(&line.job) {
+                Ok(()) => valid += 1,
+                Err(message) => errors.push(LineError {
+                    line: line.line,
+                    message,
+                }),
+            }
+        }
+        errors.sort_by_key(|error| error.line);
+        let mut report = ImportReport {
+            dry_run: options.dry_run,
+            valid,
+            ..Default::default()
+        };
+        if !errors.is_empty() {
+            report.errors = errors;
+            return Reply::json(422, &report);
+        }
+        if options.dry_run {
+            return Reply::json(200, &report);
+        }
+        let numbers: Vec<usize> = lines.iter().map(|line| line.line).collect();
+        let run_ats: Vec<Option<i64>> = lines.iter().map(|line| line.run_at).collect();
+        let snapshots: Vec<Option<Value>> = lines
+            .iter()
+            .map(|line| self.audit.as_ref().map(|_| line.job.clone()))
+            .collect();
+        let jobs = lines
+            .into_iter()
+            .map(|line| (line.job, line.run_at))
+            .collect();
+        let (task_ids, error) = match queue.push(jobs).await {
+            Ok(task_ids) => (task_ids, None),
+            Err(PushError::Invalid(index, message)) => (Vec::new(), Some((index, message))),
+            Err(PushError::Failed(task_ids, message)) => {
+                let index = task_ids.len();
+                (task_ids, Some((index, message)))
+            }
+        };
+        for ((task_id, run_at), snapshot) in task_ids.iter().zip(run_ats).zip(snapshots) {
+            self.record_push(identity, namespace, task_id, snapshot, run_at)
+                .await;
+            report.jobs.push(Queued {
+                task_id: task_id.to_string(),
+                run_at,
+            });
+        }
+        match error {
+            None => Reply::json(200, &report),
+            Some((index, message)) => {
+                report.errors.push(LineError {
+                    line: numbers.get(index).copied().unwrap_or_default(),
+                    message,
+                });
+                Reply::json(500, &report)
+            }
+        }
+    }
+
+    /// Reserves the idempotency key of a push of the body hashed to `fingerprint`,
+    /// or answers the way the first push with it did
+    async fn reserve<'k>(
+        &self,
+        queue: &dyn Queue,
+        key: Option<&'k str>,
+        fingerprint: &'k str,
+        answer: fn(Vec<Queued>) -> Reply,
+    ) -> Result<Option<(&'k str, &'k str)>, Reply> {
+        let Some(key) = key else {
+            return Ok(None);
+        };
+        if !queue.remembers_keys() {
+            return Err(Reply::text(
+                501,
+                "Idempotency-Key isn't supported for this namespace",
+            ));
+        }
+        let lease = IDEMPOTENCY_LEASE.min(self.idempotency_ttl());
+        match queue.reserve_key(key, fingerprint, lease).await {
+            Ok(None) => Ok(Some((key, fingerprint))),
+            Ok(Some(reserved)) if reserved.fingerprint != fingerprint => Err(Reply::text(
+                422,
+                "this Idempotency-Key was used with another payload",
+            )),
+            Ok(Some(reserved)) if reserved.outcome.is_empty() => Err(Reply::text(
+                409,
+                "a request with this Idempotency-Key is still running",
+            )),
+            Ok(Some(reserved)) => match serde_json::from_str(&reserved.outcome) {
+                Ok(queued) => Err(answer(queued)),
+                Err(e) => Err(Reply::text(500, e)),
+            },
+            Err(e) => Err(Reply::text(500, e)),
+        }
+    }
+
+    /// Remembers the jobs queued with an idempotency key, frees the key if nothing was queued so a retry can
+    async fn settle(&self, queue: &dyn Queue, key: Option<(&str, &str)>, queued: &[Queued]) {
+        let Some((key, fingerprint)) = key else {
+            return;
+        };
+        let outcome = match queued {
+            [] => None,
+            queued => serde_json::to_string(queued).ok(),
+        };
+        // The jobs are already queued, failing to remember them shouldn't report them as failed
+        let _ = queue
+            .settle_key(key, fingerprint, outcome, self.idempotency_ttl())
+            .await;
+    }
+
+    async fn record_push(
+        &self,
+        identity: &Identity,
+        namespace: &str,
+        task_id: &TaskId,
+        job: Option<Value>,
+        run_at: Option<i64>,
+    ) {
+        let Some(audit) = &self.audit else {
+            return;
+        };
+        let state = match run_at {
+            Some(_) => JobState::Scheduled,
+            None => JobState::Pending,
+        };
+        let entry = AuditEntry::new(&identity.name, namespace, "push")
+            .task_id(task_id)
+            .after(json!({ "state": state, "job": job, "run_at": run_at }));
+        // The job is already queued, a failing sink shouldn't report it as failed
+        let _ = audit.record(entry).await;
+    }
+
+    pub fn get_schema(&self, identity: &Identity, namespace: &str) -> Reply {
+        if let Err(reply) = self.queue(identity, namespace, Role::Viewer) {
+            return reply;
+        }
+        match self.schemas.get(namespace) {
+            Some(schema) => Reply::json(200, schema.schema()),
+            None => Reply::empty(404),
+        }
+    }
+
+    /// Liveness, the api is serving requests
+    pub fn healthz(&self) -> Reply {
+        Reply::json(200, &json!({ "status": "ok" }))
+    }
+
+    /// Readiness, pings every storage at once and answers 503 if any of them is down.
+    /// Like [`Board::healthz`] it doesn't need credentials so load balancers can call it, so only whether each kind
+    /// of storage is up is answered. Admins that can access every namespace also get the health of each one.
+    pub async fn readyz(&self, identity: Option<&Identity>) -> Reply {
+        let checks = join_all(self.queues.iter().map(|(namespace, queue)| async move {
+            let start = Instant::now();
+            let res = timeout(PING_TIMEOUT, queue.ping())
+                .await
+                .unwrap_or_else(|_| Err(format!("no answer within {PING_TIMEOUT:?}")));
+            let health = Health {
+                up: res.is_ok(),
+                latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+                error: res.err(),
+            };
+            (namespace.clone(), health)
+        }))
+        .await;
+        let ready = checks.iter().all(|(_, health)| health.up);
+        let mut backends = BTreeMap::new();
+        for (namespace, health) in &checks {
+            let backend = self
+                .metadata
+                .get(namespace)
+                .and_then(|namespace| namespace.backend.clone())
+                .unwrap_or_else(|| "unknown".to_string());
+            *backends.entry(backend).or_insert(true) &= health.up;
+        }
+        let detailed = identity
+            .is_some_and(|identity| identity.role == Role::Admin && identity.namespaces.is_none());
+        let readiness = Readiness {
+            status: if ready { "ok" } else { "unavailable" }.to_string(),
+            backends,
+            namespaces: detailed.then(|| checks.into_iter().collect()),
+        };
+        Reply::json(if ready { 200 } else { 503 }, &readiness)
+    }
+
+    /// The Prometheus exposition of every storage, only for identities that can access all namespaces
+    pub async fn render_metrics(&self, identity: &Identity) -> Reply {
+        let Some(metrics) = &self.metrics else {
+            return Reply::empty(404);
+        };
+        if identity.namespaces.is_some() {
+            return Reply::text(403, "forbidden");
+        }
+        let body = metrics
+            .render(|| {
+                join_all(self.queues.iter().map(|(namespace, queue)| async move {
+                    (namespace.clone(), queue.snapshot().await)
+                }))
+            })
+            .await;
+        Reply {
+            status: 200,
+            body: Body::Raw {
+                content_type: "text/plain; version=0.0.4; charset=utf-8",
+                body,
+            },
+        }
+    }
+
+    /// The latest retention runs on namespaces `identity` can access
+    pub fn list_retention(&self, identity: &Identity) -> Reply {
+        let Some(retention) = &self.retention else {
+            return Reply::empty(404);
+        };
+        let runs: Vec<Run> = retention
+            .runs()
+            .into_iter()
+            .filter(|run| identity.can_access(&run.namespace))
+            .collect();
+        Reply::json(200, &runs)
+    }
+
+    /// Audit entries matching `filter` in the namespaces `identity` can access
+    pub async fn list_audit(&self, identity: &Identity, filter: &AuditFilter) -> Reply {
+        let Some(audit) = &self.audit else {
+            return Reply::empty(404);
+        };
+        let filter = AuditFilter {
+            namespaces: identity
+                .namespaces
+                .as_ref()
+                .map(|namespaces| namespaces.iter().cloned().collect()),
+            ..filter.clone()
+        };
+        match audit.query(&filter).await {
+            Ok(entries) => Reply::json(
+                200,
+                &entries
+                    .into_iter()
+                    .map(|mut entry| {
+                        // Pushes record the job they queued
+                        for snapshot in [&mut entry.before, &mut entry.after].into_iter().flatten()
+                        {
+                            if let Some(job) = snapshot.get_mut("job") {
+                                self.redaction.payload(&entry.namespace, job);
+                            }
+                        }
+                        entry
+                    })
+                    .collect::<Vec<_>>(),
+            ),
+            Err(e) => Reply::text(500, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::testing::Fake;
+
+    #[test]
+    fn check_rejects_the_names_of_fixed_routes() {
+        for name in RESERVED {
+            let board = Board::new().add_fake(name, Arc::new(Fake::default()));
+            assert_eq!(
+                board.check(),
+                Err(NamespaceError::Reserved(name.to_string()))
+            );
+        }
+        let board = Board::new().add_fake("emails", Arc::new(Fake::default()));
+        assert_eq!(board.check(), Ok(()));
+    }
+
+    fn parse_readiness(reply: Reply) -> Readiness {
+        match reply.body {
+            Body::Json(value) => serde_json::from_value(value).unwrap(),
+            body => panic!("expected json, got {body:?}"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn readyz_only_details_namespaces_to_admins() {
+        let board = Board::new()
+            .add_fake(
+                Namespace::new("emails").backend("postgres"),
+                Arc::new(Fake::default()),
+            )
+            .add_fake(
+                Namespace::new("invoices").backend("redis"),
+                Arc::new(Fake::down("connection refused by 10.0.0.7:6379")),
+            );
+
+        let reply = board.readyz(None).await;
+        assert_eq!(reply.status, 503);
+        let readiness = parse_readiness(reply);
+        assert_eq!(readiness.status, "unavailable");
+        assert_eq!(
+            readiness.backends,
+            BTreeMap::from([("postgres".to_string(), true), ("redis".to_string(), false)])
+        );
+        assert_eq!(readiness.namespaces, None);
+
+        let viewer = Identity::viewer("alice");
+        let readiness = parse_readiness(board.readyz(Some(&viewer)).await);
+        assert_eq!(readiness.namespaces, None);
+
+        let restricted = Identity::admin("ops").only(["emails"]);
+        let readiness = parse_readiness(board.readyz(Some(&restricted)).await);
+        assert_eq!(readiness.namespaces, None);
+
+        let admin = Identity::admin("root");
+        let namespaces = parse_readiness(board.readyz(Some(&admin)).await)
+            .namespaces
+            .unwrap();
+        assert!(namespaces["emails"].up);
+        assert_eq!(
+            namespaces["invoices"].error.as_deref(),
+            Some("connection refused by 10.0.0.7:6379")
+        );
+    }
+
+    #[actix_web::test]
+    async fn idempotency_keys_answer_retries_and_reject_other_payloads() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new().add_fake("emails", queue.clone());
+        let identity = Identity::anonymous();
+        let job = json!({ "to": "test@example.com" });
+
+        let first = board
+            .push_job(&identity, "emails", job.clone(), Some("key-1"))
+            .await;
+        assert_eq!(first.status, 200);
+        let retry = board
+            .push_job(&identity, "emails", job.clone(), Some("key-1"))
+            .await;
+        assert_eq!(retry, first);
+        assert_eq!(queue.jobs.lock().unwrap().len(), 1);
+
+        let other = json!({ "to": "other@example.com" });
+        let reused = board
+            .push_job(&identity, "emails", other, Some("key-1"))
+            .await;
+        assert_eq!(reused.status, 422);
+        assert_eq!(queue.jobs.lock().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn idempotency_keys_are_freed_when_nothing_was_queued() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new().add_fake("emails", queue.clone());
+        let identity = Identity::anonymous();
+
+        let rejected = board
+            .push_job(&identity, "emails", json!("not an object"), Some("key-1"))
+            .await;
+        assert_eq!(rejected.status, 400);
+        assert!(queue.keys.lock().unwrap().is_empty());
+
+        queue.keys.lock().unwrap().insert(
+            "key-2".to_string(),
+            ReservedKey {
+                fingerprint: fingerprint(&json!({ "to": "test@example.com" })),
+                outcome: String::new(),
+            },
+        );
+        let running = board
+            .push_job(
+                &identity,
+                "emails",
+                json!({ "to": "test@example.com" }),
+                Some("key-2"),
+            )
+            .await;
+        assert_eq!(running.status, 409);
+    }
+
+    #[actix_web::test]
+    async fn audit_is_limited_to_the_namespaces_of_the_caller() {
+        let path =
+            std::env::temp_dir().join(format!("board-audit-{}-list.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let board = Board::new()
+            .add_fake("emails", Arc::new(Fake::default()))
+            .add_fake("invoices", Arc::new(Fake::default()))
+            .with_audit(Audit::new(crate::audit::JsonLinesSink::new(path)));
+        let admin = Identity::anonymous();
+        board
+            .push_job(&admin, "emails", json!({ "to": "test@example.com" }), None)
+            .await;
+        for i in 0..3 {
+            board
+                .push_job(&admin, "invoices", json!({ "amount": i }), None)
+                .await;
+        }
+
+        let alice = Identity::viewer("alice").only(["emails"]);
+        let filter = AuditFilter {
+            limit: 1,
+            ..AuditFilter::default()
+        };
+        let reply = board.list_audit(&alice, &filter).await;
+        let Body::Json(entries) = reply.body else {
+            panic!("expected json");
+        };
+        assert_eq!(entries.as_array().map(Vec::len), Some(1));
+        assert_eq!(entries[0]["namespace"], "emails");
+    }
+
+    #[actix_web::test]
+    async fn batches_are_queued_or_scheduled_all_or_nothing() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new().add_fake("emails", queue.clone());
+        let identity = Identity::anonymous();
+
+        let rejected = board
+            .push_jobs(
+                &identity,
+                "emails",
+                vec![
+                    Enqueue::new(json!({ "to": "a@example.com" })),
+                    Enqueue::new(json!({ "to": "b@example.com" }))
+                        .run_at(1_700_000_000)
+                        .delay(60),
+                ],
+                None,
+            )
+            .await;
+        assert_eq!(rejected.status, 400);
+        assert!(queue.jobs.lock().unwrap().is_empty());
+
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let reply = board
+            .push_jobs(
+                &identity,
+                "emails",
+                vec![
+                    Enqueue::new(json!({ "to": "a@example.com" })),
+                    Enqueue::new(json!({ "to": "b@example.com" })).run_at(1_700_000_000),
+                    Enqueue::new(json!({ "to": "c@example.com" })).delay(60),
+                ],
+                None,
+            )
+            .await;
+        assert_eq!(reply.status, 200);
+        let Body::Json(body) = reply.body else {
+            panic!("expected json");
+        };
+        let queued: Vec<Queued> = serde_json::from_value(body["jobs"].clone()).unwrap();
+        assert_eq!(queued.len(), 3);
+        assert_eq!(queued[0].run_at, None);
+        assert_eq!(queued[1].run_at, Some(1_700_000_000));
+        let delayed = queued[2].run_at.unwrap();
+        assert!((before + 60..=before + 61).contains(&delayed));
+        assert_eq!(queue.jobs.lock().unwrap().len(), 3);
+
+        let invalid = board
+            .push_jobs(
+                &identity,
+                "emails",
+                vec![
+                    Enqueue::new(json!({})),
+                    Enqueue::new(json!("not an object")),
+                ],
+                None,
+            )
+            .await;
+        assert_eq!(invalid.status, 400);
+        let Body::Json(body) = invalid.body else {
+            panic!("expected json");
+        };
+        assert_eq!(body["errors"][0]["index"], 1);
+        assert_eq!(queue.jobs.lock().unwrap().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn large_payloads_are_listed_as_previews() {
+        let queue = Arc::new(Fake::default());
+        let board = Board::new()
+            .add_fake("emails", queue.clone())
+            .preview_limit("emails", 16);
+        let identity = Identity::anonymous();
+        board
+            .push_job(&identity, "emails", json!({ "to": "a" }), None)
+            .await;
+        let body = json!({ "body": "héllo wörld, a longer message" });
+        board
+            .push_job(&identity, "emails", body.clone(), None)
+            .await;
+
+        let filter = Filter {
+            status: JobState::Pending,
+            page: 1,
+        };
+        let Body::Json(listed) = board
+            .list_jobs(&identity, "emails", &filter, false)
+            .await
+            .body
+        else {
+            panic!("expected json");
+        };
+        let small = &listed["jobs"][0];
+        assert_eq!(small["args"], json!({ "to": "a" }));
+        assert_eq!(small["size"], 10);
+        let large = &listed["jobs"][1];
+        assert!(large.get("args").is_none());
+        assert_eq!(large["size"], body.to_string().len());
+        let preview = large["preview"].as_str().unwrap();
+        assert!(preview.len() <= 16);
+        assert!(body.to_string().starts_with(preview));
+
+        let task_id: TaskId = serde_json::from_value(large["task_id"].clone()).unwrap();
+        let Body::Json(job) = board
+            .get_job(&identity, "emails", &task_id, false)
+            .await
+            .body
+        else {
+            panic!("expected json");
+        };
+        assert_eq!(job["args"], body);
+    }
+
+    fn parse<T: DeserializeOwned>(reply: Reply) -> T {
+        match reply.body {
+            Body::Json(value) => serde_json::from_value(value).unwrap(),
+            body => panic!("expected json, got {body:?}"),
+        }
+    }
+
+    #[actix_web::test]
+    async fn namespaces_are_listed_with_their_metadata_and_stats() {
+        let emails = Arc::new(Fake::default());
+        emails
+            .jobs
+            .lock()
+            .unwrap()
+            .push(("1".to_string(), json!({ "task_id": "1", "args": {} })));
+        let board = Board::new()
+            .add_fake(
+                Namespace::new("emails")
+                    .description("Transactional emails")
+                    .owner("growth")
+                    .tag("smtp")
+                    .runbook("https://example.com/runbooks/emails"),
+                emails,
+            )
+            .add_fake("invoices", Arc::new(Fake::down("gone")))
+            .add_fake("reports", Arc::new(Fake::default()));
+
+        let alice = Identity::viewer("alice").only(["emails", "invoices"]);
+        let infos: Vec<NamespaceInfo> = parse(board.list_namespaces(&alice).await);
+        assert_eq!(infos.len(), 2);
+        assert_eq!(infos[0].namespace.owner.as_deref(), Some("growth"));
+        assert_eq!(infos[0].namespace.tags, vec!["smtp".to_string()]);
+        assert_eq!(infos[0].stats.as_ref().map(|stats| stats.pending), Some(1));
+        assert_eq!(infos[0].workers, Some(0));
+        assert_eq!(infos[1].namespace, Namespace::new("invoices"));
+        assert_eq!(infos[1].stats, None);
+        assert_eq!(infos[1].workers, None);
+    }
+
+    #[actix_web::test]
+    async fn qualified_namespaces_are_grouped_apart() {
+        let board = Board::new()
+            .add_fake(
+                Namespace::new("emails")
+                    .qualified("pg")
+                    .group("notifications"),
+                Arc::new(Fake::default()),
+            )
+            .add_fake(
+                Namespace::new("emails")
+                    .qualified("redis")
+                    .group("notifications"),
+                Arc::new(Fake::default()),
+            )
+            .add_fake(
+                Namespace::new("invoices").group("billing"),
+                Arc::new(Fake::default()),
+            )
+            .add_fake("reports", Arc::new(Fake::default()));
+        assert_eq!(board.check(), Ok(()));
+        assert_eq!(
+            board.namespaces(),
+            vec!["invoices", "pg:emails", "redis:emails", "reports"]
+        );
+
+        let groups: Vec<NamespaceGroup> = parse(board.list_groups(&Identity::anonymous()).await);
+        let listed: Vec<(Option<&str>, Vec<String>)> = groups
+            .iter()
+            .map(|group| {
+                let keys = group.namespaces.iter().map(|info| info.namespace.key());
+                (group.group.as_deref(), keys.collect())
+            })
+            .collect();
+        assert_eq!(
+            listed,
+            vec![
+                (None, vec!["reports".to_string()]),
+                (Some("billing"), vec!["invoices".to_string()]),
+                (
+                    Some("notifications"),
+                    vec!["pg:emails".to_string(), "redis:emails".to_string()]
+                ),
+            ]
+        );
+
+        let alice = Identity::viewer("alice").only(["redis:emails"]);
+        let groups: Vec<NamespaceGroup> = parse(board.list_groups(&alice).await);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(
+            groups[0].namespaces[0].namespace.qualifier.as_deref(),
+            Some("redis")
+        );
+    }
+
+    #[test]
+    fn check_rejects_duplicates_and_invalid_names() {
+        let board = Board::new()
+            .add_fake("emails", Arc::new(Fake::default()))
+            .add_fake("emails", Arc::new(Fake::default()));
+        assert_eq!(
+            board.check(),
+            Err(NamespaceError::Duplicate("emails".to_string()))
+        );
+        let board = Board::new().add_fake("a/b", Arc::new(Fake::default()));
+        assert_eq!(
+            board.check(),
+            Err(NamespaceError::Invalid("a/b".to_string()))
+        );
+    }
+}
@@ -1,22 +1,42 @@
 use std::{
+    collections::HashSet,
+    convert::Infallible,
     pin::Pin,
     sync::Mutex,
     task::{Context, Poll},
     time::Duration,
 };
 
-use actix_web::{
-    rt::time,
-    web::{Bytes, Data},
-};
+use actix_web::web::{Bytes, Data};
 use futures::{
     channel::mpsc::{channel, Receiver, Sender},
     Stream, StreamExt,
 };
+use shared::BoardEvent;
+
+use crate::{auth::Identity, redact::Redaction};
+
+/// A connected client and the namespaces it may hear about, `None` for all of them
+#[derive(Debug, Clone)]
+struct Subscriber {
+    tx: Sender<Bytes>,
+    namespaces: Option<HashSet<String>>,
+}
+
+impl Subscriber {
+    fn can_access(&self, namespace: Option<&str>) -> bool {
+        match (&self.namespaces, namespace) {
+            (None, _) => true,
+            (Some(namespaces), Some(namespace)) => namespaces.contains(namespace),
+            (Some(_), None) => false,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Broadcaster {
-    clients: Vec<Sender<Bytes>>,
+    clients: Vec<Subscriber>,
+    redaction: Redaction,
 }
 
 impl Default for Broadcaster {
@@ -39,11 +59,12 @@ impl Broadcaster {
     pub fn new() -> Self {
         Broadcaster {
             clients: Vec::new(),
+            redaction: Redaction::default(),
         }
     }
 
     fn spawn_ping(me: Data<Mutex<Self>>) {
-        let mut interval = time::interval(Duration::from_millis(500));
+        let mut interval = tokio::time::interval(Duration::from_millis(500));
         let task = async move {
             loop {
                 interval.tick().await;
@@ -56,7 +77,7 @@ impl Broadcaster {
     fn remove_stale_clients(&mut self) {
         let mut ok_clients = Vec::new();
         for client in self.clients.iter() {
-            let result = client.clone().try_send(Bytes::from("data: ping\n\n"));
+            let result = client.tx.clone().try_send(Bytes::from("data: ping\n\n"));
 
             if let Ok(()) = result {
                 ok_clients.push(client.clone());
@@ -65,34 +86,167 @@ impl Broadcaster {
         self.clients = ok_clients;
     }
 
+    /// A client hearing every event and log line
     pub fn new_client(&mut self) -> Client {
+        self.subscribe(None)
+    }
+
+    /// A client hearing only the events of the namespaces `identity` can access.
+    /// Lines that don't name a namespace only go to identities that can access all of them.
+    pub fn new_client_for(&mut self, identity: &Identity) -> Client {
+        self.subscribe(identity.namespaces.clone())
+    }
+
+    fn subscribe(&mut self, namespaces: Option<HashSet<String>>) -> Client {
         let (tx, rx) = channel(100);
 
         tx.clone()
             .try_send(Bytes::from("data: connected\n\n"))
             .unwrap();
 
-        self.clients.push(tx);
+        self.clients.push(Subscriber { tx, namespaces });
         Client(rx)
     }
 
+    /// Redact events and json logs with `redaction`, see [`Redaction::message`].
+    /// [`ApiBuilder::events`](crate::api::ApiBuilder::events) sets the redaction of the board.
+    pub fn set_redaction(&mut self, redaction: Redaction) {
+        self.redaction = redaction;
+    }
+
     pub fn send(&self, msg: &str) {
-        let msg = unescape::unescape(msg).unwrap();
-        let msg = Bytes::from(["data: ", &msg, "\n\n"].concat());
+        self.send_line(&unescape::unescape(msg).unwrap());
+    }
+
+    /// Sends a message that is already a single line, like one relayed from another board
+    pub(crate) fn send_line(&self, msg: &str) {
+        let mut msg = msg.to_string();
+        let mut namespace = None;
+        if let Ok(mut value) = serde_json::from_str::<serde_json::Value>(&msg) {
+            namespace = value
+                .get("namespace")
+                .and_then(|namespace| namespace.as_str())
+                .map(str::to_string);
+            if !self.redaction.is_empty() {
+                self.redaction.message(namespace.as_deref(), &mut value);
+                msg = value.to_string();
+            }
+        }
+        self.broadcast(namespace.as_deref(), &msg);
+    }
 
-        for client in self.clients.iter().filter(|client| !client.is_closed()) {
-            client.clone().try_send(msg.clone()).unwrap();
+    /// Sends `msg` to the clients that can access `namespace`
+    fn broadcast(&self, namespace: Option<&str>, msg: &str) {
+        let msg = Bytes::from(["data: ", msg, "\n\n"].concat());
+
+        for client in self
+            .clients
+            .iter()
+            .filter(|client| !client.tx.is_closed() && client.can_access(namespace))
+        {
+            // Slow clients miss events instead of blocking the sender
+            let _ = client.tx.clone().try_send(msg.clone());
         }
     }
+
+    /// Send a [`BoardEvent`] to all clients as a json payload
+    pub fn send_event(&self, event: &BoardEvent) {
+        let Ok(mut value) = serde_json::to_value(event) else {
+            return;
+        };
+        let namespace = value
+            .get("namespace")
+            .and_then(|namespace| namespace.as_str())
+            .map(str::to_string);
+        self.redaction.message(namespace.as_deref(), &mut value);
+        self.broadcast(namespace.as_deref(), &value.to_string());
+    }
 }
 
-// wrap Receiver in own type, with correct error type
+/// The events of a connected client, a body both `ApiBuilder` and the axum `RouterBuilder` can stream
 pub struct Client(Receiver<Bytes>);
 
 impl Stream for Client {
-    type Item = Result<Bytes, actix_web::Error>;
+    type Item = Result<Bytes, Infallible>;
 
     fn poll_next(mut self: Pin<&mut Client>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         self.0.poll_next_unpin(cx).map(|c| Ok(c).transpose())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt;
+    use shared::JobState;
+
+    use super::*;
+    use crate::redact::RedactRule;
+
+    /// The messages waiting for `client`, without the one sent on connection
+    fn received(client: &mut Client) -> Vec<String> {
+        let mut messages = Vec::new();
+        while let Some(Some(Ok(msg))) = client.next().now_or_never() {
+            messages.push(String::from_utf8_lossy(&msg).to_string());
+        }
+        messages.retain(|msg| msg != "data: connected\n\n");
+        messages
+    }
+
+    fn updated(namespace: &str) -> BoardEvent {
+        BoardEvent::QueueUpdated {
+            namespace: namespace.to_string(),
+            state: JobState::Pending,
+        }
+    }
+
+    #[test]
+    fn clients_only_hear_about_their_namespaces() {
+        let mut broadcaster = Broadcaster::new();
+        let mut admin = broadcaster.new_client_for(&Identity::admin("root"));
+        let mut alice = broadcaster.new_client_for(&Identity::viewer("alice").only(["emails"]));
+
+        broadcaster.send_event(&updated("emails"));
+        broadcaster.send_event(&updated("invoices"));
+
+        assert_eq!(received(&mut admin).len(), 2);
+        let messages = received(&mut alice);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("\"emails\""));
+    }
+
+    #[test]
+    fn lines_are_redacted_with_every_rule() {
+        let mut broadcaster = Broadcaster::new();
+        broadcaster.set_redaction(
+            Redaction::new()
+                .rule("emails", RedactRule::path("customer.address").drop())
+                .rule("emails", RedactRule::key("*token*")),
+        );
+        let mut client = broadcaster.new_client();
+
+        broadcaster.send(
+            r#"{"namespace":"emails","args":{"customer":{"name":"Ann","address":"1 Main St"}},"api_token":"s3cr3t"}"#,
+        );
+
+        let messages = received(&mut client);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("Ann"));
+        assert!(!messages[0].contains("1 Main St"));
+        assert!(!messages[0].contains("s3cr3t"));
+    }
+
+    #[test]
+    fn lines_without_a_namespace_only_go_to_unrestricted_clients() {
+        let mut broadcaster = Broadcaster::new();
+        let mut admin = broadcaster.new_client();
+        let mut alice = broadcaster.new_client_for(&Identity::viewer("alice").only(["emails"]));
+
+        broadcaster.send(r#"{"level":"INFO","message":"worker started"}"#);
+        broadcaster.send(r#"{"namespace":"emails","message":"sent"}"#);
+
+        assert_eq!(received(&mut admin).len(), 2);
+        let messages = received(&mut alice);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].contains("sent"));
+    }
+}
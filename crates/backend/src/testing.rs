@@ -0,0 +1,225 @@
+//! A storage kept in memory, to test the board without a database
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use apalis_core::task::task_id::TaskId;
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use shared::{Filter, GetJobsResult, JobState, Namespace, ReservedKey, Stat};
+
+use crate::service::{Board, PushError, Queue, Reply};
+
+/// Queues jobs as `{ "task_id", "args" }` objects, all pending, and rejects anything but objects
+#[derive(Default)]
+pub(crate) struct Fake {
+    pub jobs: Mutex<Vec<(String, Value)>>,
+    pub keys: Mutex<HashMap<String, ReservedKey>>,
+    /// Finished jobs the purges remove, at most `limit` per call
+    pub finished: Mutex<usize>,
+    /// Why the storage is unreachable, if it is
+    pub down: Option<String>,
+    /// Answers every page of every status with all the jobs, like listings that ignore the page
+    pub every_page: bool,
+}
+
+impl Fake {
+    pub fn down(reason: &str) -> Self {
+        Self {
+            down: Some(reason.to_string()),
+            ..Self::default()
+        }
+    }
+
+    fn purge(&self, limit: usize) -> Result<usize, String> {
+        if let Some(reason) = &self.down {
+            return Err(reason.clone());
+        }
+        let mut finished = self.finished.lock().unwrap();
+        let removed = limit.min(*finished);
+        *finished -= removed;
+        Ok(removed)
+    }
+
+    fn stats(&self) -> Stat {
+        Stat {
+            pending: self.jobs.lock().unwrap().len(),
+            ..Stat::default()
+        }
+    }
+}
+
+impl Queue for Fake {
+    fn jobs<'a>(&'a self, filter: &'a Filter) -> BoxFuture<'a, Reply> {
+        Box::pin(async move {
+            let jobs = match self.page(filter.status, filter.page).await {
+                Ok(jobs) => jobs.into_iter().map(|(_, job)| job).collect(),
+                Err(e) => return Reply::text(500, e),
+            };
+            Reply::json(
+                200,
+                &GetJobsResult {
+                    stats: self.stats(),
+                    jobs,
+                    progress: HashMap::new(),
+                },
+            )
+        })
+    }
+
+    fn page(
+        &self,
+        status: JobState,
+        page: i32,
+    ) -> BoxFuture<'_, Result<Vec<(String, Value)>, String>> {
+        Box::pin(async move {
+            if let Some(reason) = &self.down {
+                return Err(reason.clone());
+            }
+            if !self.every_page && (status != JobState::Pending || page != 1) {
+                return Ok(Vec::new());
+            }
+            Ok(self.jobs.lock().unwrap().clone())
+        })
+    }
+
+    fn workers(&self) -> BoxFuture<'_, Reply> {
+        Box::pin(async move { Reply::json(200, &Vec::<Value>::new()) })
+    }
+
+    fn push(
+        &self,
+        jobs: Vec<(Value, Option<i64>)>,
+    ) -> BoxFuture<'_, Result<Vec<TaskId>, PushError>> {
+        Box::pin(async move {
+            if let Some((index, _)) = jobs
+                .iter()
+                .enumerate()
+                .find(|(_, (job, _))| !job.is_object())
+            {
+                return Err(PushError::Invalid(index, "expected an object".to_string()));
+            }
+            let mut queued = Vec::with_capacity(jobs.len());
+            for (job, _) in jobs {
+                let task_id = TaskId::new();
+                let id = task_id.to_string();
+                self.jobs
+                    .lock()
+                    .unwrap()
+                    .push((id.clone(), json!({ "task_id": id, "args": job })));
+                queued.push(task_id);
+            }
+            Ok(queued)
+        })
+    }
+
+    fn job<'a>(&'a self, task_id: &'a TaskId) -> BoxFuture<'a, Reply> {
+        Box::pin(async move {
+            let id = task_id.to_string();
+            let jobs = self.jobs.lock().unwrap();
+            match jobs.iter().find(|(task_id, _)| *task_id == id) {
+                Some((_, job)) => Reply::json(200, job),
+                None => Reply::empty(404),
+            }
+        })
+    }
+
+    fn validate(&self, job: &Value) -> Result<(), String> {
+        if job.is_object() {
+            Ok(())
+        } else {
+            Err("expected an object".to_string())
+        }
+    }
+
+    fn snapshot(&self) -> BoxFuture<'_, Option<(Stat, usize)>> {
+        Box::pin(async move { self.down.is_none().then(|| (self.stats(), 0)) })
+    }
+
+    fn ping(&self) -> BoxFuture<'_, Result<(), String>> {
+        Box::pin(async move {
+            match &self.down {
+                Some(reason) => Err(reason.clone()),
+                None => Ok(()),
+            }
+        })
+    }
+
+    fn purge_before(
+        &self,
+        _state: JobState,
+        _before: i64,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Box::pin(async move { self.purge(limit) })
+    }
+
+    fn purge_beyond(
+        &self,
+        _state: JobState,
+        _keep: usize,
+        limit: usize,
+    ) -> BoxFuture<'_, Result<usize, String>> {
+        Box::pin(async move { self.purge(limit) })
+    }
+
+    fn reserve_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        _lease: Duration,
+    ) -> BoxFuture<'a, Result<Option<ReservedKey>, String>> {
+        Box::pin(async move {
+            let mut keys = self.keys.lock().unwrap();
+            match keys.get(key) {
+                Some(reserved) => Ok(Some(reserved.clone())),
+                None => {
+                    let reserved = ReservedKey {
+                        fingerprint: fingerprint.to_string(),
+                        outcome: String::new(),
+                    };
+                    keys.insert(key.to_string(), reserved);
+                    Ok(None)
+                }
+            }
+        })
+    }
+
+    fn settle_key<'a>(
+        &'a self,
+        key: &'a str,
+        fingerprint: &'a str,
+        outcome: Option<String>,
+        _ttl: Duration,
+    ) -> BoxFuture<'a, Result<(), String>> {
+        Box::pin(async move {
+            let mut keys = self.keys.lock().unwrap();
+            if keys
+                .get(key)
+                .is_some_and(|reserved| reserved.fingerprint != fingerprint)
+            {
+                return Ok(());
+            }
+            match outcome {
+                Some(outcome) => {
+                    if let Some(reserved) = keys.get_mut(key) {
+                        reserved.outcome = outcome;
+                    }
+                }
+                None => {
+                    keys.remove(key);
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Board {
+    /// Serve `queue` under `namespace`, sharing it with the test
+    pub(crate) fn add_fake(self, namespace: impl Into<Namespace>, queue: Arc<Fake>) -> Self {
+        self.add_queue(namespace.into(), queue)
+    }
+}
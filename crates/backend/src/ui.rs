@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+
+use rust_embed::RustEmbed;
+
+/// The frontend as built by `trunk build --release` in `crates/frontend`.
+/// Compiling without a build serves a notice instead.
+#[derive(RustEmbed)]
+#[folder = "../frontend/dist"]
+#[allow_missing = true]
+struct Dist;
+
+/// An embedded file of the frontend
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub body: Cow<'static, [u8]>,
+    pub mime: String,
+    /// `index.html` must be revalidated so new builds are picked up, the rest is content hashed
+    pub immutable: bool,
+}
+
+impl Asset {
+    pub fn cache_control(&self) -> &'static str {
+        if self.immutable {
+            "public, max-age=31536000, immutable"
+        } else {
+            "no-cache"
+        }
+    }
+}
+
+const NOT_BUILT: &str =
+    "The board frontend wasn't built, run `trunk build --release` in crates/frontend and rebuild.";
+
+/// Finds the file for a request path.
+///
+/// Paths that aren't a file but a route of the frontend, like `/queue/{namespace}/{status}`,
+/// get `index.html` so the frontend router can take over.
+pub fn resolve(path: &str) -> Option<Asset> {
+    let path = path.trim_start_matches('/');
+    if let Some(file) = Dist::get(path) {
+        return Some(Asset {
+            body: file.data,
+            mime: file.metadata.mimetype().to_string(),
+            immutable: path != "index.html",
+        });
+    }
+    if !is_route(path) {
+        return None;
+    }
+    let body = match Dist::get("index.html") {
+        Some(index) => index.data,
+        None => Cow::Borrowed(NOT_BUILT.as_bytes()),
+    };
+    Some(Asset {
+        body,
+        mime: "text/html; charset=utf-8".to_string(),
+        immutable: false,
+    })
+}
+
+/// Namespaces may contain dots, so anything under `queue/` is a route
+fn is_route(path: &str) -> bool {
+    let last = path.rsplit('/').next().unwrap_or_default();
+    path.starts_with("queue/") || !last.contains('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_of_the_frontend_get_the_index() {
+        for path in ["/", "/queue/emails/Pending", "/queue/app.emails.v2/Failed"] {
+            let asset = resolve(path).unwrap();
+            assert_eq!(asset.mime, "text/html; charset=utf-8");
+            assert_eq!(asset.cache_control(), "no-cache");
+        }
+        assert!(resolve("/no-such-file.js").is_none());
+    }
+}
@@ -0,0 +1,172 @@
+//! Copies the jobs of a namespace from one storage to another.
+//!
+//! ```sh
+//! apalis-migrate --from sqlite://jobs.db --to postgresql://localhost/jobs --namespace emails --checkpoint emails.json
+//! apalis-migrate --from sqlite://jobs.db --to postgresql://localhost/jobs --namespace emails --verify
+//! ```
+//!
+//! Stop the workers of both sides first. Jobs are kept as json so any job type can be migrated.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use apalis::prelude::Request;
+use apalis_redis::RedisStorage;
+use apalis_sql::{
+    mysql::{MySqlPool, MysqlStorage},
+    postgres::{PgPool, PostgresStorage},
+    sqlite::{SqlitePool, SqliteStorage},
+};
+use clap::Parser;
+use serde_json::Value;
+use shared::{
+    migrate::{verify, Checkpoint, Migration, RestoreExt},
+    BackendExt, RequestExt,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about = "Copy the jobs of a namespace between storages")]
+struct Args {
+    /// Url of the storage to copy from, sqlite, postgres, mysql or redis
+    #[arg(long)]
+    from: String,
+    /// Url of the storage to copy to, sqlite, postgres or mysql
+    #[arg(long)]
+    to: String,
+    #[arg(short, long)]
+    namespace: String,
+    /// File the progress is saved to after every page, an interrupted migration resumes from it
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+    /// Only compare the job counts of both sides
+    #[arg(long)]
+    verify: bool,
+}
+
+enum Source {
+    Sqlite(SqliteStorage<Value>),
+    Postgres(PostgresStorage<Value>),
+    Mysql(MysqlStorage<Value>),
+    Redis(RedisStorage<Value>),
+}
+
+enum Target {
+    Sqlite(SqliteStorage<Value>),
+    Postgres(PostgresStorage<Value>),
+    Mysql(MysqlStorage<Value>),
+}
+
+async fn source(url: &str, namespace: &str) -> anyhow::Result<Source> {
+    if url.starts_with("redis://") {
+        let conn = apalis_redis::connect(url).await?;
+        let config = apalis_redis::Config::default().set_namespace(namespace);
+        return Ok(Source::Redis(RedisStorage::new_with_config(conn, config)));
+    }
+    Ok(match target(url, namespace).await? {
+        Target::Sqlite(storage) => Source::Sqlite(storage),
+        Target::Postgres(storage) => Source::Postgres(storage),
+        Target::Mysql(storage) => Source::Mysql(storage),
+    })
+}
+
+async fn target(url: &str, namespace: &str) -> anyhow::Result<Target> {
+    let config = apalis_sql::Config::default().set_namespace(namespace);
+    if url.starts_with("sqlite:") {
+        let pool = SqlitePool::connect(url).await?;
+        SqliteStorage::setup(&pool).await?;
+        return Ok(Target::Sqlite(SqliteStorage::new_with_config(pool, config)));
+    }
+    if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        let pool = PgPool::connect(url).await?;
+        PostgresStorage::setup(&pool).await?;
+        return Ok(Target::Postgres(PostgresStorage::new_with_config(
+            pool, config,
+        )));
+    }
+    if url.starts_with("mysql://") {
+        let pool = MySqlPool::connect(url).await?;
+        MysqlStorage::setup(&pool).await?;
+        return Ok(Target::Mysql(MysqlStorage::new_with_config(pool, config)));
+    }
+    bail!("unsupported storage {url}")
+}
+
+async fn copy<C, S, D>(source: &S, target: &D, args: &Args) -> anyhow::Result<()>
+where
+    S: BackendExt<Value, Request = Request<Value, C>>,
+    Request<Value, C>: RequestExt,
+    D: RestoreExt<Value>,
+    S::Error: Error + Send + Sync + 'static,
+    D::Error: Error + Send + Sync + 'static,
+{
+    if !args.verify {
+        let checkpoint = match &args.checkpoint {
+            Some(path) if path.exists() => {
+                let saved = fs::read_to_string(path)?;
+                serde_json::from_str(&saved)
+                    .with_context(|| format!("{} isn't a checkpoint", path.display()))?
+            }
+            _ => Checkpoint::default(),
+        };
+        let done = Migration::new(source, target)
+            .resume(checkpoint)
+            .run(|checkpoint| {
+                println!(
+                    "{} page {}: {} copied, {} already there",
+                    checkpoint.state, checkpoint.page, checkpoint.copied, checkpoint.skipped
+                );
+                if let Some(path) = &args.checkpoint {
+                    if let Err(e) = save(path, checkpoint) {
+                        eprintln!("couldn't save the checkpoint: {e}");
+                    }
+                }
+            })
+            .await?;
+        println!(
+            "migrated {}: {} copied, {} already there",
+            args.namespace, done.copied, done.skipped
+        );
+    }
+    let verification = verify(source, target).await?;
+    println!("source: {:?}", verification.source);
+    println!("target: {:?}", verification.target);
+    if !verification.matches() {
+        bail!("the job counts of {} differ", args.namespace);
+    }
+    println!("the job counts of {} match", args.namespace);
+    Ok(())
+}
+
+fn save(path: &Path, checkpoint: &Checkpoint) -> anyhow::Result<()> {
+    fs::write(path, serde_json::to_string(checkpoint)?)?;
+    Ok(())
+}
+
+async fn copy_to<C, S>(source: &S, target: Target, args: &Args) -> anyhow::Result<()>
+where
+    S: BackendExt<Value, Request = Request<Value, C>>,
+    Request<Value, C>: RequestExt,
+    S::Error: Error + Send + Sync + 'static,
+{
+    match target {
+        Target::Sqlite(target) => copy(source, &target, args).await,
+        Target::Postgres(target) => copy(source, &target, args).await,
+        Target::Mysql(target) => copy(source, &target, args).await,
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    let from = source(&args.from, &args.namespace).await?;
+    let to = target(&args.to, &args.namespace).await?;
+    match from {
+        Source::Sqlite(source) => copy_to(&source, to, &args).await,
+        Source::Postgres(source) => copy_to(&source, to, &args).await,
+        Source::Mysql(source) => copy_to(&source, to, &args).await,
+        Source::Redis(source) => copy_to(&source, to, &args).await,
+    }
+}
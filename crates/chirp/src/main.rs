@@ -1,5 +1,5 @@
 use actix_cors::Cors;
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use actix_web::{web, App, HttpServer};
 use apalis::layers::catch_panic::CatchPanicLayer;
 use apalis::layers::tracing::TraceLayer;
 use apalis::prelude::{Data, Monitor, WorkerBuilder, WorkerFactoryFn};
@@ -9,6 +9,8 @@ use apalis_sql::mysql::{MySqlPool, MysqlStorage};
 use apalis_sql::postgres::{PgPool, PostgresStorage};
 use apalis_sql::sqlite::{SqlitePool, SqliteStorage};
 use backend::api::ApiBuilder;
+use backend::bridge::poll::Watcher;
+use backend::metrics::Metrics;
 use backend::sse::Broadcaster;
 use chrono::{DateTime, Utc};
 use clap::Parser;
@@ -18,7 +20,6 @@ use futures::io::BufReader;
 use futures::{future, AsyncBufReadExt, StreamExt};
 use processors::docker::run_docker;
 use std::collections::HashMap;
-use std::sync::Mutex;
 use std::time::Duration;
 use std::{process::Stdio, str::FromStr};
 use trace::{Subscriber, TaskSpan};
@@ -40,9 +41,10 @@ enum LaunchJob {
 #[derive(Debug, Clone)]
 enum StorageType {
     Redis(RedisStorage<LaunchJob>),
-    Mysql(MysqlStorage<LaunchJob>),
+    /// Polled for changes since neither can push them
+    Mysql(Watcher<LaunchJob, MysqlStorage<LaunchJob>>),
     Postgres(PostgresStorage<LaunchJob>),
-    Sqlite(SqliteStorage<LaunchJob>),
+    Sqlite(Watcher<LaunchJob, SqliteStorage<LaunchJob>>),
 }
 #[derive(Deserialize, Clone, Debug)]
 enum Source {
@@ -132,20 +134,11 @@ struct Args {
     config: String,
 }
 
-async fn new_client(broadcaster: actix_web::web::Data<Mutex<Broadcaster>>) -> impl Responder {
-    let rx = broadcaster.lock().unwrap().new_client();
-
-    HttpResponse::Ok()
-        .append_header(("content-type", "text/event-stream"))
-        .append_header(("Cache-Control", "no-cache"))
-        .append_header(("Connection", "keep-alive"))
-        .streaming(rx)
-}
-
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
     std::env::set_var("RUST_LOG", "debug,bollard::docker=error,sqlx::query=error");
     let broadcaster = Broadcaster::create();
+    let metrics = Metrics::new();
     let line_sub = Subscriber {
         tx: broadcaster.clone(),
     };
@@ -189,9 +182,15 @@ async fn main() -> std::io::Result<()> {
                     SqliteStorage::setup(&pool)
                         .await
                         .expect("unable to run migrations for sqlite");
+                    shared::sqlite::setup(&pool)
+                        .await
+                        .expect("unable to create the board tables for sqlite");
                     let storage: SqliteStorage<LaunchJob> =
                         SqliteStorage::new_with_config(pool, cfg);
-                    exposed.push((job.clone(), StorageType::Sqlite(storage.clone())));
+                    exposed.push((
+                        job.clone(),
+                        StorageType::Sqlite(Watcher::new(storage.clone())),
+                    ));
                     monitor = monitor.register(
                         WorkerBuilder::new(job)
                             .layer(CatchPanicLayer::new())
@@ -226,8 +225,14 @@ async fn main() -> std::io::Result<()> {
                     MysqlStorage::setup(&pool)
                         .await
                         .expect("unable to run migrations for mysql");
+                    shared::mysql::setup(&pool)
+                        .await
+                        .expect("unable to create the board tables for mysql");
                     let storage: MysqlStorage<LaunchJob> = MysqlStorage::new_with_config(pool, cfg);
-                    exposed.push((job.clone(), StorageType::Mysql(storage.clone())));
+                    exposed.push((
+                        job.clone(),
+                        StorageType::Mysql(Watcher::new(storage.clone())),
+                    ));
                     monitor = monitor.register(
                         WorkerBuilder::new(job)
                             .layer(CatchPanicLayer::new())
@@ -245,6 +250,9 @@ async fn main() -> std::io::Result<()> {
                     PostgresStorage::setup(&pool)
                         .await
                         .expect("unable to run migrations for postgres");
+                    shared::postgres::setup(&pool)
+                        .await
+                        .expect("unable to create the board tables for postgres");
                     let storage: PostgresStorage<LaunchJob> =
                         PostgresStorage::new_with_config(pool, cfg);
                     exposed.push((job.clone(), StorageType::Postgres(storage.clone())));
@@ -265,9 +273,15 @@ async fn main() -> std::io::Result<()> {
                     SqliteStorage::setup(&pool)
                         .await
                         .expect("unable to run migrations for sqlite");
+                    shared::sqlite::setup(&pool)
+                        .await
+                        .expect("unable to create the board tables for sqlite");
                     let storage: SqliteStorage<LaunchJob> =
                         SqliteStorage::new_with_config(pool, cfg);
-                    exposed.push((job.clone(), StorageType::Sqlite(storage.clone())));
+                    exposed.push((
+                        job.clone(),
+                        StorageType::Sqlite(Watcher::new(storage.clone())),
+                    ));
                     monitor = monitor.register(
                         WorkerBuilder::new(job)
                             .layer(CatchPanicLayer::new())
@@ -297,22 +311,28 @@ async fn main() -> std::io::Result<()> {
     }
     let http = async {
         HttpServer::new(move || {
-            let mut api = ApiBuilder::new();
+            let mut api = ApiBuilder::new()
+                .with_metrics(metrics.clone())
+                .events(&broadcaster);
             for (namespace, storage) in exposed.clone() {
                 match storage {
                     StorageType::Redis(redis) => {
                         api = api.add_storage(&redis, &namespace);
                     }
-                    StorageType::Sqlite(sqlite) => api = api.add_storage(&sqlite, &namespace),
-                    StorageType::Mysql(mysql) => api = api.add_storage(&mysql, &namespace),
+                    StorageType::Sqlite(sqlite) => {
+                        api = api.add_storage_watched(&sqlite, &namespace, &broadcaster)
+                    }
+                    StorageType::Mysql(mysql) => {
+                        api = api.add_storage_watched(&mysql, &namespace, &broadcaster)
+                    }
                     StorageType::Postgres(pg) => api = api.add_storage(&pg, &namespace),
                 }
             }
-            let scope = api.build().route("/events", web::get().to(new_client));
-            App::new()
-                .wrap(Cors::permissive())
-                .app_data(broadcaster.clone())
-                .service(web::scope("/api/v1").service(scope))
+            let scope = api.build();
+            let app = App::new().wrap(Cors::permissive());
+            #[cfg(feature = "ui")]
+            let app = app.default_service(backend::api::ui());
+            app.service(web::scope("/api/v1").service(scope))
         })
         .bind("127.0.0.1:8000")?
         .run()
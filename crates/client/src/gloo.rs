@@ -0,0 +1,36 @@
+use gloo_net::http::Request;
+
+use crate::{HttpRequest, HttpResponse, Method, Transport};
+
+/// Sends the calls with the browser's `fetch`, for the frontend and other wasm apps
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gloo;
+
+impl Transport for Gloo {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        let mut builder = match request.method {
+            Method::Get => Request::get(&request.url),
+            Method::Put => Request::put(&request.url),
+        };
+        for (name, value) in &request.headers {
+            builder = builder.header(name, value);
+        }
+        let built = match request.body {
+            Some(body) => builder.body(body),
+            None => builder.build(),
+        };
+        let res = built
+            .map_err(|e| e.to_string())?
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        let status = res.status();
+        let headers = res.headers().entries().collect();
+        let body = res.text().await.map_err(|e| e.to_string())?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
@@ -0,0 +1,355 @@
+//! A typed client for the board api, over any [`Transport`].
+//!
+//! ```rust,ignore
+//! let board = Client::new("http://localhost:8000/api/v1", Native::default()).token("s3cr3t");
+//! let queued = board
+//!     .push_batch("emails", &[Enqueue::new(json!({ "to": "test@example.com" }))], None)
+//!     .await?;
+//! let jobs = board.jobs("emails", &Filter { status: JobState::Pending, page: 1 }).await?;
+//! ```
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use shared::{
+    AuditEntry, AuditFilter, Enqueue, ExportQuery, Filter, GetJobsResult, ImportOptions,
+    ImportReport, NamespaceGroup, NamespaceInfo, Queued, Readiness, Run, Worker,
+};
+
+#[cfg(feature = "gloo")]
+pub mod gloo;
+
+#[cfg(feature = "reqwest")]
+pub mod native;
+
+/// The header making pushes idempotent
+const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Put,
+}
+
+/// A call to the board, built by [`Client`] and sent by a [`Transport`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpRequest {
+    pub method: Method,
+    /// The full url, query included
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// The value of the header `name`, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A body the board tagged, sent again only if it changed
+#[derive(Debug, Clone)]
+struct Cached {
+    etag: String,
+    body: String,
+}
+
+/// Sends the calls of a [`Client`], [`gloo::Gloo`] in the browser and [`native::Native`] elsewhere.
+/// Implement it to reach the board any other way, or to answer the calls in tests.
+pub trait Transport {
+    /// Fails only if no answer came back, whatever its status
+    fn send(&self, request: HttpRequest) -> impl Future<Output = Result<HttpResponse, String>>;
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("the board couldn't be reached: {0}")]
+    Transport(String),
+    #[error("the board answered {status}: {body}")]
+    Status { status: u16, body: String },
+    #[error("serde_json::Error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("serde_urlencoded::ser::Error: {0}")]
+    Query(#[from] serde_urlencoded::ser::Error),
+}
+
+/// Calls the routes of a board mounted at `base`, where its `ApiBuilder` is served.
+///
+/// Listings the board tags with an `ETag` are kept per url and revalidated with `If-None-Match`,
+/// a 304 is answered with the kept body. Clones share what they keep.
+#[derive(Debug, Clone)]
+pub struct Client<T> {
+    base: String,
+    transport: T,
+    token: Option<String>,
+    raw: bool,
+    cache: Arc<Mutex<HashMap<String, Cached>>>,
+}
+
+impl<T: Transport> Client<T> {
+    /// `base` is like `http://localhost:8000/api/v1`, or `/api/v1` from a page served by the board
+    pub fn new(base: &str, transport: T) -> Self {
+        Self {
+            base: base.trim_end_matches('/').to_string(),
+            transport,
+            token: None,
+            raw: false,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Bearer token sent with every call
+    pub fn token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Ask for payloads without redaction when listing and fetching jobs, only admins get them
+    pub fn raw(mut self, raw: bool) -> Self {
+        self.raw = raw;
+        self
+    }
+
+    pub async fn namespaces(&self) -> Result<Vec<NamespaceInfo>, ClientError> {
+        json(self.get("", None).await?)
+    }
+
+    pub async fn groups(&self) -> Result<Vec<NamespaceGroup>, ClientError> {
+        json(self.get("/groups", None).await?)
+    }
+
+    pub async fn healthz(&self) -> Result<(), ClientError> {
+        self.get("/healthz", None).await.map(|_| ())
+    }
+
+    /// The health of every kind of storage, answered whether or not they are all up.
+    /// `namespaces` is only filled in for admin tokens.
+    pub async fn readyz(&self) -> Result<Readiness, ClientError> {
+        let res = self.send(Method::Get, "/readyz", None, None).await?;
+        match res.status {
+            200 | 503 => Ok(serde_json::from_str(&res.body)?),
+            _ => Err(status(res)),
+        }
+    }
+
+    pub async fn openapi(&self) -> Result<Value, ClientError> {
+        json(self.get("/openapi.json", None).await?)
+    }
+
+    /// The Prometheus exposition, when the board serves metrics
+    pub async fn metrics(&self) -> Result<String, ClientError> {
+        Ok(self.get("/metrics", None).await?.body)
+    }
+
+    pub async fn audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, ClientError> {
+        let query = serde_urlencoded::to_string(filter)?;
+        json(self.get("/audit", Some(query)).await?)
+    }
+
+    pub async fn retention(&self) -> Result<Vec<Run>, ClientError> {
+        json(self.get("/retention", None).await?)
+    }
+
+    /// A page of jobs with the stats of the namespace, large payloads are listed as previews
+    pub async fn jobs(
+        &self,
+        namespace: &str,
+        filter: &Filter,
+    ) -> Result<GetJobsResult<Value>, ClientError> {
+        let query = self.with_raw(serde_urlencoded::to_string(filter)?);
+        json(self.get(&format!("/{namespace}"), Some(query)).await?)
+    }
+
+    pub async fn workers(&self, namespace: &str) -> Result<Vec<Worker>, ClientError> {
+        json(self.get(&format!("/{namespace}/workers"), None).await?)
+    }
+
+    pub async fn job(&self, namespace: &str, task_id: &str) -> Result<Value, ClientError> {
+        let query = self.with_raw(String::new());
+        json(
+            self.get(&format!("/{namespace}/job/{task_id}"), Some(query))
+                .await?,
+        )
+    }
+
+    /// The JSON Schema of the jobs the namespace accepts
+    pub async fn schema(&self, namespace: &str) -> Result<Value, ClientError> {
+        json(self.get(&format!("/{namespace}/schema"), None).await?)
+    }
+
+    /// Every job in a state, as NDJSON or CSV
+    pub async fn export(
+        &self,
+        namespace: &str,
+        query: &ExportQuery,
+    ) -> Result<String, ClientError> {
+        let query = serde_urlencoded::to_string(query)?;
+        let path = format!("/{namespace}/export");
+        Ok(self.get(&path, Some(query)).await?.body)
+    }
+
+    /// Queues a job, answering with the board's message.
+    /// Rejected jobs fail with [`ClientError::Status`], its body says why.
+    pub async fn push(
+        &self,
+        namespace: &str,
+        job: &Value,
+        idempotency_key: Option<&str>,
+    ) -> Result<String, ClientError> {
+        let path = format!("/{namespace}/job");
+        let res = self
+            .send(Method::Put, &path, idempotency_key, Some(job.to_string()))
+            .await?;
+        Ok(ok(res)?.body)
+    }
+
+    /// Queues or schedules a batch of jobs, none of them unless all are valid.
+    /// If the storage fails midway the [`ClientError::Status`] body lists the jobs queued before.
+    pub async fn push_batch(
+        &self,
+        namespace: &str,
+        jobs: &[Enqueue],
+        idempotency_key: Option<&str>,
+    ) -> Result<Vec<Queued>, ClientError> {
+        let path = format!("/{namespace}/jobs");
+        let body = serde_json::to_string(jobs)?;
+        let res = self
+            .send(Method::Put, &path, idempotency_key, Some(body))
+            .await?;
+        let mut pushed: Value = json(ok(res)?)?;
+        Ok(serde_json::from_value(pushed["jobs"].take())?)
+    }
+
+    /// Queues the jobs of an NDJSON file. The report is returned even when lines were rejected,
+    /// check its `errors`.
+    pub async fn import(
+        &self,
+        namespace: &str,
+        ndjson: String,
+        options: &ImportOptions,
+    ) -> Result<ImportReport, ClientError> {
+        let query = serde_urlencoded::to_string(options)?;
+        let request = HttpRequest {
+            method: Method::Put,
+            url: self.url(&format!("/{namespace}/import"), Some(query)),
+            headers: self.headers("application/x-ndjson", None),
+            body: Some(ndjson),
+        };
+        let res = self.call(request).await?;
+        serde_json::from_str(&res.body).map_err(|_| status(res))
+    }
+
+    fn url(&self, path: &str, query: Option<String>) -> String {
+        match query.filter(|query| !query.is_empty()) {
+            Some(query) => format!("{}/backend{path}?{query}", self.base),
+            None => format!("{}/backend{path}", self.base),
+        }
+    }
+
+    fn with_raw(&self, query: String) -> String {
+        match (self.raw, query.is_empty()) {
+            (false, _) => query,
+            (true, true) => "raw=true".to_string(),
+            (true, false) => format!("{query}&raw=true"),
+        }
+    }
+
+    fn headers(&self, content_type: &str, idempotency_key: Option<&str>) -> Vec<(String, String)> {
+        let mut headers = vec![("content-type".to_string(), content_type.to_string())];
+        if let Some(token) = &self.token {
+            headers.push(("authorization".to_string(), format!("Bearer {token}")));
+        }
+        if let Some(key) = idempotency_key {
+            headers.push((IDEMPOTENCY_KEY.to_string(), key.to_string()));
+        }
+        headers
+    }
+
+    async fn call(&self, request: HttpRequest) -> Result<HttpResponse, ClientError> {
+        self.transport
+            .send(request)
+            .await
+            .map_err(ClientError::Transport)
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        idempotency_key: Option<&str>,
+        body: Option<String>,
+    ) -> Result<HttpResponse, ClientError> {
+        let request = HttpRequest {
+            method,
+            url: self.url(path, None),
+            headers: self.headers("application/json", idempotency_key),
+            body,
+        };
+        self.call(request).await
+    }
+
+    async fn get(&self, path: &str, query: Option<String>) -> Result<HttpResponse, ClientError> {
+        let url = self.url(path, query);
+        let mut headers = self.headers("application/json", None);
+        let cached = self
+            .cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&url).cloned());
+        if let Some(cached) = &cached {
+            headers.push(("if-none-match".to_string(), cached.etag.clone()));
+        }
+        let request = HttpRequest {
+            method: Method::Get,
+            url: url.clone(),
+            headers,
+            body: None,
+        };
+        let mut res = self.call(request).await?;
+        if let (304, Some(cached)) = (res.status, cached) {
+            res.status = 200;
+            res.body = cached.body;
+            return Ok(res);
+        }
+        let res = ok(res)?;
+        if let (Some(etag), Ok(mut cache)) = (res.header("etag"), self.cache.lock()) {
+            let etag = etag.to_string();
+            let body = res.body.clone();
+            cache.insert(url, Cached { etag, body });
+        }
+        Ok(res)
+    }
+}
+
+fn ok(res: HttpResponse) -> Result<HttpResponse, ClientError> {
+    match res.status {
+        200..=299 => Ok(res),
+        _ => Err(status(res)),
+    }
+}
+
+fn status(res: HttpResponse) -> ClientError {
+    ClientError::Status {
+        status: res.status,
+        body: res.body,
+    }
+}
+
+fn json<R: DeserializeOwned>(res: HttpResponse) -> Result<R, ClientError> {
+    Ok(serde_json::from_str(&res.body)?)
+}
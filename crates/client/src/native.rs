@@ -0,0 +1,172 @@
+use crate::{HttpRequest, HttpResponse, Method, Transport};
+
+/// Sends the calls with reqwest, for CLIs and services
+#[derive(Debug, Clone, Default)]
+pub struct Native {
+    client: reqwest::Client,
+}
+
+impl Native {
+    /// Sends with `client`, to reuse its pool or set timeouts and proxies
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+impl Transport for Native {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+        let method = match request.method {
+            Method::Get => reqwest::Method::GET,
+            Method::Put => reqwest::Method::PUT,
+        };
+        let mut builder = self.client.request(method, &request.url);
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        if let Some(body) = request.body {
+            builder = builder.body(body);
+        }
+        let res = builder.send().await.map_err(|e| e.to_string())?;
+        let status = res.status().as_u16();
+        let headers = res
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = res.text().await.map_err(|e| e.to_string())?;
+        Ok(HttpResponse {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{App, HttpServer};
+    use apalis_sql::sqlite::{SqlitePool, SqliteStorage};
+    use backend::{
+        api::ApiBuilder,
+        auth::{Auth, Identity},
+    };
+    use serde_json::{json, Value};
+    use shared::{Enqueue, Filter, JobState};
+
+    use super::*;
+    use crate::{Client, ClientError};
+
+    /// Serves a board with an `emails` namespace on a free port, answering with its url
+    async fn serve(auth: Option<Auth>) -> String {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        SqliteStorage::setup(&pool).await.unwrap();
+        shared::sqlite::setup(&pool).await.unwrap();
+        let storage: SqliteStorage<Value> = SqliteStorage::new_with_config(
+            pool,
+            apalis_sql::Config::default().set_namespace("emails"),
+        );
+        let server = HttpServer::new(move || {
+            let builder = ApiBuilder::new().add_storage(&storage, "emails");
+            let builder = match auth.clone() {
+                Some(auth) => builder.with_auth(auth),
+                None => builder,
+            };
+            App::new().service(builder.build())
+        })
+        .workers(1)
+        .disable_signals()
+        .bind(("127.0.0.1", 0))
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_web::rt::spawn(server.run());
+        url
+    }
+
+    /// Keeps the status of every answer
+    #[derive(Clone, Default)]
+    struct Recorded {
+        native: Native,
+        statuses: Arc<Mutex<Vec<u16>>>,
+    }
+
+    impl Transport for Recorded {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse, String> {
+            let res = self.native.send(request).await?;
+            self.statuses.lock().unwrap().push(res.status);
+            Ok(res)
+        }
+    }
+
+    #[actix_web::test]
+    async fn pushed_jobs_are_listed() {
+        let board = Client::new(&serve(None).await, Native::default());
+        let namespaces = board.namespaces().await.unwrap();
+        assert_eq!(namespaces[0].namespace.key(), "emails");
+
+        let job = json!({ "to": "test@example.com" });
+        let queued = board
+            .push_batch("emails", &[Enqueue::new(job.clone())], Some("key"))
+            .await
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+        let again = board
+            .push_batch("emails", &[Enqueue::new(job)], Some("key"))
+            .await
+            .unwrap();
+        assert_eq!(again, queued);
+
+        let filter = Filter {
+            status: JobState::Pending,
+            page: 1,
+        };
+        let listed = board.jobs("emails", &filter).await.unwrap();
+        assert_eq!(listed.stats.pending, 1);
+        assert_eq!(listed.jobs.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn statuses_are_mapped_to_errors() {
+        let auth = Auth::new().bearer("s3cr3t", Identity::viewer("alice"));
+        let url = serve(Some(auth)).await;
+
+        let anonymous = Client::new(&url, Native::default());
+        let err = anonymous.namespaces().await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Status { status: 401, .. }),
+            "{err}"
+        );
+
+        let viewer = anonymous.token("s3cr3t");
+        let filter = Filter {
+            status: JobState::Pending,
+            page: 1,
+        };
+        let err = viewer.jobs("missing", &filter).await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Status { status: 404, .. }),
+            "{err}"
+        );
+        // Viewers can't push
+        let err = viewer.push("emails", &json!({}), None).await.unwrap_err();
+        assert!(
+            matches!(err, ClientError::Status { status: 403, .. }),
+            "{err}"
+        );
+
+        let closed = Client::new("http://127.0.0.1:1", Native::default());
+        let err = closed.healthz().await.unwrap_err();
+        assert!(matches!(err, ClientError::Transport(_)), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn unchanged_listings_are_revalidated() {
+        let transport = Recorded::default();
+        let board = Client::new(&serve(None).await, transport.clone());
+        let first = board.namespaces().await.unwrap();
+        let second = board.namespaces().await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(*transport.statuses.lock().unwrap(), [200, 304]);
+    }
+}
@@ -4,6 +4,7 @@ use hirola::{
     prelude::*,
 };
 use serde::Serialize;
+use shared::{JobProgress, NamespaceInfo};
 
 use crate::Link;
 
@@ -25,6 +26,23 @@ pub fn resolve_json<V: Serialize>(val: V) -> String {
     String::from_utf8(buf).unwrap()
 }
 
+pub fn progress_bar(progress: Option<JobProgress>) -> Dom {
+    match progress {
+        Some(progress) => html! {
+            <div class="px-6 pb-4">
+                <div class="flex justify-between text-xs text-gray-500 mb-1">
+                    <span>{progress.message.unwrap_or_default()}</span>
+                    <span>{format!("{}%", progress.percent)}</span>
+                </div>
+                <div class="w-full h-2 bg-gray-800 rounded-full">
+                    <div class="h-2 bg-blue-600 rounded-full" style=format!("width: {}%", progress.percent)></div>
+                </div>
+            </div>
+        },
+        None => html! { <></> },
+    }
+}
+
 #[component]
 fn Main(app: App<State>) -> Dom {
     html! {
@@ -55,13 +73,7 @@ fn Main(app: App<State>) -> Dom {
                 <div class="grid grid-cols-1 gap-4 mt-4 md:grid-cols-3">
                     {app.state().namespaces
                         .signal_vec_cloned()
-                        .map_render(|ns| {
-                            html! {
-                                <a href=format!("/queue/{ns}")>
-                                    {queue_card(&ns, "0 jobs")}
-                                </a>
-                            }
-                        })
+                        .map_render(|info| namespace_card(&info))
                     }
                 </div>
             </section>
@@ -119,39 +131,44 @@ fn overview_card(title: &str, value: &str) -> Dom {
     }
 }
 
-pub fn queue_card(title: &str, info: &str) -> Dom {
+/// A queue with what it is for and how many jobs it has
+pub fn namespace_card(info: &NamespaceInfo) -> Dom {
+    let namespace = &info.namespace;
+    let jobs = match &info.stats {
+        Some(stats) => format!(
+            "{} pending · {} running · {} failed",
+            stats.pending, stats.running, stats.failed
+        ),
+        None => "Unreachable".to_string(),
+    };
+    let details = [
+        namespace.backend.clone(),
+        namespace
+            .owner
+            .as_ref()
+            .map(|owner| format!("owned by {owner}")),
+        info.workers.map(|workers| format!("{workers} workers")),
+    ];
     html! {
         <div class="rounded-sm border text-card-foreground shadow-sm bg-gray-900" data-v0-t="card">
             <div class="flex-col space-y-1.5 p-6 flex justify-between">
-                <h3 class="whitespace-nowrap text-2xl font-semibold leading-none tracking-tight">{title}</h3>
-                <svg
-                    xmlns="http://www.w3.org/2000/svg"
-                    width="24"
-                    height="24"
-                    viewBox="0 0 24 24"
-                    fill="none"
-                    stroke="currentColor"
-                    stroke-width="2"
-                    stroke-linecap="round"
-                    stroke-linejoin="round"
-                    class="w-4 h-4 text-gray-500"
-                >
-                    <path d="M14.5 4h-5L7 7H4a2 2 0 0 0-2 2v9a2 2 0 0 0 2 2h16a2 2 0 0 0 2-2V9a2 2 0 0 0-2-2h-3l-2.5-3z"></path>
-                    <circle cx="12" cy="13" r="3"></circle>
-                </svg>
+                <a href=format!("/queue/{}", namespace.key()) class="whitespace-nowrap text-2xl font-semibold leading-none tracking-tight">{namespace.key()}</a>
+                <p class="text-sm text-gray-500">{namespace.description.clone().unwrap_or_default()}</p>
             </div>
-            <div class="p-6">
-                <div class="text-sm">{info}</div>
-                <div class="mt-2">
-                    // <div class="w-full h-8">
-                    //     <div
-                    //         class="flex aspect-video justify-center text-xs [&amp;_.recharts-cartesian-axis-tick_text]:fill-muted-foreground [&amp;_.recharts-cartesian-grid_line]:stroke-border/50 [&amp;_.recharts-curve.recharts-tooltip-cursor]:stroke-border [&amp;_.recharts-dot[stroke='#fff']]:stroke-transparent [&amp;_.recharts-layer]:outline-none [&amp;_.recharts-polar-grid_[stroke='#ccc']]:stroke-border [&amp;_.recharts-radial-bar-background-sector]:fill-muted [&amp;_.recharts-rectangle.recharts-tooltip-cursor]:fill-muted [&amp;_.recharts-reference-line-line]:stroke-border [&amp;_.recharts-sector[stroke='#fff']]:stroke-transparent [&amp;_.recharts-sector]:outline-none [&amp;_.recharts-surface]:outline-none min-h-[300px]"
-                    //         data-chart="chart-r0"
-                    //     >
-                    //         // Add your recharts or other chart library code here
-                    //     </div>
-                    // </div>
+            <div class="p-6 space-y-2">
+                <div class="text-sm">{jobs}</div>
+                <div class="flex flex-wrap gap-2 text-xs text-gray-500">
+                    {for detail in details.into_iter().flatten() {
+                        html! { <span>{detail}</span> }
+                    }}
+                    {for tag in namespace.tags.clone() {
+                        html! { <span class="rounded-sm bg-gray-800 px-2">{tag}</span> }
+                    }}
                 </div>
+                {match &namespace.runbook {
+                    Some(url) => html! { <a href=url.clone() target="_blank" class="text-xs text-blue-600 hover:underline">"Runbook"</a> },
+                    None => html! { <></> },
+                }}
             </div>
         </div>
     }
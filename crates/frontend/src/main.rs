@@ -1,53 +1,165 @@
-use std::str::FromStr;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
 
-use gloo_net::http::Request;
+use client::{gloo::Gloo, Client, ClientError};
 use hirola::dom::app::router::Router;
 use hirola::dom::app::App;
 use hirola::dom::effects::prelude::*;
 use hirola::dom::Dom;
 use hirola::prelude::{Suspend, *};
-use home::{queue_card, resolve_json};
+use home::{namespace_card, progress_bar, resolve_json};
 use log::Level;
-use shared::{Filter, GetJobsResult, JobState, Worker};
+use push::PushForm;
+use shared::{BoardEvent, Filter, GetJobsResult, JobProgress, JobState, NamespaceInfo, Worker};
 use strum::IntoEnumIterator;
-use web_sys::EventSource;
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{EventSource, MessageEvent};
 mod home;
+mod push;
 
 #[derive(Debug, Clone)]
 pub struct State {
     event_source: EventSource,
-    namespaces: MutableVec<String>,
+    namespaces: MutableVec<NamespaceInfo>,
+    progress: ProgressMap,
 }
 
+/// The latest progress of each job by task id, from the listings and then live from the event stream
+type ProgressMap = Mutable<HashMap<String, JobProgress>>;
+
 impl State {
-    async fn list_namespaces() -> Result<Vec<String>, gloo_net::Error> {
-        let res = Request::get(API_PATH).send().await?;
-        res.json().await
+    async fn list_namespaces() -> Result<Vec<NamespaceInfo>, ClientError> {
+        board().namespaces().await
     }
 
     async fn list_jobs(
         namespace: String,
         filter: Filter,
-    ) -> Result<GetJobsResult<serde_json::Value>, gloo_net::Error> {
-        let res = Request::get(&format!("{API_PATH}/{namespace}"))
-            .query([
-                ("page", filter.page.to_string()),
-                ("status", filter.status.to_string()),
-            ])
-            .send()
-            .await?;
-        res.json().await
+    ) -> Result<GetJobsResult<serde_json::Value>, ClientError> {
+        board().jobs(&namespace, &filter).await
+    }
+
+    async fn get_job(namespace: String, task_id: String) -> Result<serde_json::Value, ClientError> {
+        board().job(&namespace, &task_id).await
+    }
+
+    async fn list_workers(namespace: String) -> Result<Vec<Worker>, ClientError> {
+        board().workers(&namespace).await
+    }
+
+    async fn job_schema(namespace: String) -> Result<serde_json::Value, ClientError> {
+        board().schema(&namespace).await
     }
 
-    async fn list_workers(namespace: String) -> Result<Vec<Worker>, gloo_net::Error> {
-        let res = Request::get(&format!("{API_PATH}/{namespace}/workers"))
-            .send()
-            .await?;
-        res.json().await
+    /// Returns the response text, the task id or the reasons the job was rejected
+    async fn push_job(namespace: String, job: serde_json::Value) -> Result<String, ClientError> {
+        match board().push(&namespace, &job, None).await {
+            Err(ClientError::Status { body, .. }) => Ok(body),
+            res => res,
+        }
+    }
+}
+
+/// Where the board serving this page mounted its api
+const API_BASE: &str = "/api/v1";
+
+fn board() -> Client<Gloo> {
+    Client::new(API_BASE, Gloo)
+}
+
+/// Keeps the progress persisted for the listed jobs, unless the event stream already told of a later one
+fn with_progress(
+    res: GetJobsResult<serde_json::Value>,
+    progress: &ProgressMap,
+) -> Vec<serde_json::Value> {
+    let mut known = progress.lock_mut();
+    for (task_id, persisted) in res.progress {
+        let stale = known
+            .get(&task_id)
+            .is_some_and(|current| current.updated_at >= persisted.updated_at);
+        if !stale {
+            known.insert(task_id, persisted);
+        }
     }
+    res.jobs
 }
 
-const API_PATH: &str = "/api/v1/backend";
+/// Keeps `progress` current with the progress events of the board, forgetting jobs that finished
+fn follow_progress(events: &EventSource, progress: ProgressMap) {
+    let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |message: MessageEvent| {
+        let Some(data) = message.data().as_string() else {
+            return;
+        };
+        match serde_json::from_str::<BoardEvent>(&data) {
+            Ok(BoardEvent::Progress {
+                progress: update, ..
+            }) => {
+                progress.lock_mut().insert(update.task_id.clone(), update);
+            }
+            Ok(BoardEvent::JobUpdated { task_id, to, .. }) if to.is_finished() => {
+                progress.lock_mut().remove(&task_id);
+            }
+            _ => {}
+        }
+    });
+    events.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    // Listens for as long as the page is open
+    on_message.forget();
+}
+
+/// Sql backends nest the task id in the job's context
+fn task_id(task: &serde_json::Value) -> Option<&str> {
+    task.pointer("/parts/context/task_id")
+        .or_else(|| task.pointer("/parts/task_id"))
+        .and_then(|id| id.as_str())
+}
+
+/// A listed job, one with a large payload only has a preview of it until it is loaded in full
+fn job_item(namespace: &str, task: serde_json::Value, progress: &ProgressMap) -> Dom {
+    let text = Mutable::new(resolve_json(&task));
+    let id = task_id(&task).map(str::to_string);
+    let progress = progress
+        .signal_ref(move |all| progress_bar(id.as_ref().and_then(|id| all.get(id).cloned())));
+    let load = match (task.get("preview"), task_id(&task)) {
+        (Some(_), Some(id)) => {
+            let size = task["size"].as_u64().unwrap_or_default();
+            let load = {
+                let text = text.clone();
+                let namespace = namespace.to_string();
+                let id = id.to_string();
+                move |_| {
+                    let text = text.clone();
+                    let namespace = namespace.clone();
+                    let id = id.clone();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        match State::get_job(namespace, id).await {
+                            Ok(job) => text.set(resolve_json(&job)),
+                            Err(e) => text.set(e.to_string()),
+                        }
+                    });
+                }
+            };
+            html! {
+                <button on:click=load class="px-6 pb-2 text-xs text-blue-600 hover:underline">
+                    {format!("Load the full payload ({} KiB)", size.div_ceil(1024))}
+                </button>
+            }
+        }
+        _ => html! { <></> },
+    };
+    html! {
+        <li>
+        <div class="rounded-sm border text-card-foreground focus:outline-none font-mono text-sm shadow-sm p-2 m-1"><div class="p-6 flex items-center space-x-4">
+            {text.signal_cloned()}
+            </div>
+            {load}
+            {progress}
+            </div>
+        </li>
+    }
+}
 
 fn namespace_page(app: &App<State>) -> Dom {
     html! {
@@ -60,31 +172,126 @@ fn namespace_page(app: &App<State>) -> Dom {
 fn namespace_status_page(app: &App<State>) -> Dom {
     html! {
         <>
-            <NamespaceStatusContent router=app.router().clone() />
+            <NamespaceStatusContent router=app.router().clone() progress={app.state().progress.clone()} />
         </>
     }
 }
 
+/// How the queues page lays out the queues
+#[derive(Debug, Clone, Default, PartialEq)]
+struct QueuesView {
+    /// Grouped by tag rather than by logical group
+    by_tag: bool,
+    /// Only the queues with this tag
+    tag: Option<String>,
+}
+
 fn queues_page(app: &App<State>) -> Dom {
-    let namespaces = &app.state().namespaces;
+    let view = Mutable::new(QueuesView::default());
+    let groups = app
+        .state()
+        .namespaces
+        .signal_vec_cloned()
+        .to_signal_cloned()
+        .switch(move |all| {
+            let set = view.clone();
+            view.signal_cloned()
+                .map(move |current| grouped(&all, &current, &set))
+        });
     html! {
         <section class="w-full px-4 pt-2">
             <h2 class="text-xl font-bold">"Queues"</h2>
             <p class="text-gray-500">"These are all the queues you have created on this apalis-board instance."</p>
-            <div class="grid grid-cols-1 gap-4 mt-4 md:grid-cols-3">
-            {namespaces
-                .signal_vec_cloned()
-                .map_render(|ns| {
-                    html! {
-                        <a href=format!("/queue/{ns}")>
-                            {queue_card(&ns, "0 jobs")}
-                        </a>
-                    }
-                })
-            }
+            {groups}
+        </section>
+    }
+}
 
+/// The queues with the selected tag by logical group, or by tag where a queue is in the group of each of its tags
+fn grouped(all: &[NamespaceInfo], view: &QueuesView, set: &Mutable<QueuesView>) -> Dom {
+    let mut tags: Vec<&str> = all
+        .iter()
+        .flat_map(|info| &info.namespace.tags)
+        .map(String::as_str)
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    let mut groups: BTreeMap<Option<&str>, Vec<&NamespaceInfo>> = BTreeMap::new();
+    let shown = all.iter().filter(|info| {
+        view.tag
+            .as_ref()
+            .is_none_or(|tag| info.namespace.tags.contains(tag))
+    });
+    for info in shown {
+        if !view.by_tag {
+            groups
+                .entry(info.namespace.group.as_deref())
+                .or_default()
+                .push(info);
+            continue;
+        }
+        if info.namespace.tags.is_empty() {
+            groups.entry(None).or_default().push(info);
+        }
+        for tag in &info.namespace.tags {
+            groups.entry(Some(tag.as_str())).or_default().push(info);
+        }
+    }
+    let rest = if view.by_tag { "Untagged" } else { "Ungrouped" };
+    let by_group = QueuesView {
+        by_tag: false,
+        tag: view.tag.clone(),
+    };
+    let by_tag = QueuesView {
+        by_tag: true,
+        tag: view.tag.clone(),
+    };
+    let any_tag = QueuesView {
+        by_tag: view.by_tag,
+        tag: None,
+    };
+    html! {
+        <div>
+            <div class="flex flex-wrap gap-2 mt-4">
+                {view_button("By group", by_group, view, set)}
+                {view_button("By tag", by_tag, view, set)}
             </div>
-        </section>
+            <div class="flex flex-wrap gap-2 mt-2">
+                {view_button("All tags", any_tag, view, set)}
+                {for tag in tags {
+                    view_button(tag, QueuesView { by_tag: view.by_tag, tag: Some(tag.to_string()) }, view, set)
+                }}
+            </div>
+            {for (group, infos) in groups {
+                html! {
+                    <div class="mt-6">
+                        <h3 class="text-lg font-semibold">{group.unwrap_or(rest).to_string()}</h3>
+                        <div class="grid grid-cols-1 gap-4 mt-2 md:grid-cols-3">
+                            {for info in infos {
+                                namespace_card(info)
+                            }}
+                        </div>
+                    </div>
+                }
+            }}
+        </div>
+    }
+}
+
+fn view_button(
+    label: &str,
+    target: QueuesView,
+    current: &QueuesView,
+    set: &Mutable<QueuesView>,
+) -> Dom {
+    let class = if target == *current {
+        "rounded-md px-3 py-1 text-sm bg-blue-600 text-white"
+    } else {
+        "rounded-md px-3 py-1 text-sm border border-gray-700 text-gray-500"
+    };
+    let set = set.clone();
+    html! {
+        <button on:click=move |_| set.set(target.clone()) class=class>{label.to_string()}</button>
     }
 }
 
@@ -145,6 +352,7 @@ fn NamespaceContent(router: Router<State>) -> Dom {
         <div class="flex flex-col lg:flex-row w-full">
             <div class="flex-1 px-4">
                 <QueueNav router={router} />
+                <PushForm namespace={namespace.clone()} />
                 <div class="space-y-1">
                 {match State::list_workers(namespace).suspend().await {
                     Loading => html! { <div>"Loading..."</div> },
@@ -206,7 +414,7 @@ fn QueueNav(router: Router<State>) -> Dom {
 }
 
 #[component]
-fn NamespaceStatusContent(router: Router<State>) -> Dom {
+fn NamespaceStatusContent(router: Router<State>, progress: ProgressMap) -> Dom {
     let namespace = router.current_params().get("namespace").unwrap().clone();
     let status = router
         .current_params()
@@ -217,20 +425,14 @@ fn NamespaceStatusContent(router: Router<State>) -> Dom {
                 <div class="flex-1 px-4">
                     <QueueNav router={router} />
                     <div class="space-y-1">
-                    {match State::list_jobs(namespace, Filter { page: 1, status:status.unwrap()}).suspend().await {
+                    {match State::list_jobs(namespace.clone(), Filter { page: 1, status:status.unwrap()}).suspend().await {
                         Loading => html! { <div>"Loading..."</div> },
                         Ready(Ok(res)) => {
                             html! {
                                 <>
                                 <ul class="flex flex-col space-y-4">
-                                    {for task in res.jobs{
-                                        html! {
-                                            <li>
-                                            <div class="rounded-sm border text-card-foreground focus:outline-none font-mono text-sm shadow-sm p-2 m-1"><div class="p-6 flex items-center space-x-4">
-                                                {resolve_json(&task)}
-                                                </div></div>
-                                            </li>
-                                        }
+                                    {for task in with_progress(res, &progress) {
+                                        job_item(&namespace, task, &progress)
                                     }}
                                 </ul>
 
@@ -304,10 +506,13 @@ fn Card<T: AsRef<str>, S: AsRef<str>>(title: T, status: S) -> Dom {
 
 fn main() {
     console_log::init_with_level(Level::Debug).unwrap();
-    let es = EventSource::new(&format!("{API_PATH}/events")).unwrap();
+    let es = EventSource::new(&format!("{API_BASE}/backend/events")).unwrap();
+    let progress = ProgressMap::default();
+    follow_progress(&es, progress.clone());
     let api = State {
         event_source: es,
         namespaces: Default::default(),
+        progress,
     };
     dbg!(&api.event_source);
     let mut app = App::new(api);
@@ -0,0 +1,150 @@
+use hirola::dom::Dom;
+use hirola::prelude::{Suspend, *};
+use serde_json::{Map, Value};
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, HtmlTextAreaElement};
+
+use crate::State;
+
+/// A top level property of a job schema and how it is edited
+#[derive(Debug, Clone)]
+struct Field {
+    name: String,
+    kind: String,
+    required: bool,
+}
+
+impl Field {
+    fn id(&self) -> String {
+        format!("push-field-{}", self.name)
+    }
+}
+
+fn fields(schema: &Value) -> Vec<Field> {
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|r| r.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    schema["properties"]
+        .as_object()
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, property)| Field {
+                    name: name.clone(),
+                    kind: property["type"].as_str().unwrap_or("object").to_string(),
+                    required: required.contains(&name.as_str()),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn input(field: &Field) -> Dom {
+    let class = "flex h-10 w-full rounded-md border border-input bg-black px-3 py-2 text-sm";
+    match field.kind.as_str() {
+        "boolean" => html! { <input id=field.id() type="checkbox" class="h-4 w-4" /> },
+        "integer" | "number" => html! { <input id=field.id() type="number" class=class /> },
+        "string" => html! { <input id=field.id() type="text" class=class /> },
+        _ => html! {
+            <textarea id=field.id() class="w-full rounded-md border border-input bg-black px-3 py-2 text-sm font-mono" placeholder="json"></textarea>
+        },
+    }
+}
+
+/// Reads the form back into a job payload
+fn read(fields: &[Field]) -> Result<Value, String> {
+    let document = web_sys::window()
+        .and_then(|w| w.document())
+        .ok_or("no document")?;
+    let mut job = Map::new();
+    for field in fields {
+        let element = document
+            .get_element_by_id(&field.id())
+            .ok_or(format!("missing field {}", field.name))?;
+        let value = match field.kind.as_str() {
+            "boolean" => Value::Bool(
+                element
+                    .dyn_into::<HtmlInputElement>()
+                    .map(|e| e.checked())
+                    .unwrap_or_default(),
+            ),
+            kind => {
+                let raw = match element.dyn_into::<HtmlInputElement>() {
+                    Ok(input) => input.value(),
+                    Err(element) => element
+                        .dyn_into::<HtmlTextAreaElement>()
+                        .map(|e| e.value())
+                        .unwrap_or_default(),
+                };
+                if raw.is_empty() {
+                    if field.required {
+                        return Err(format!("{} is required", field.name));
+                    }
+                    continue;
+                }
+                match kind {
+                    "string" => Value::String(raw),
+                    _ => serde_json::from_str(&raw)
+                        .map_err(|e| format!("{} is not valid: {e}", field.name))?,
+                }
+            }
+        };
+        job.insert(field.name.clone(), value);
+    }
+    Ok(Value::Object(job))
+}
+
+fn form(namespace: String, schema: Value) -> Dom {
+    let fields = fields(&schema);
+    let status = Mutable::new(String::new());
+    let submit = {
+        let status = status.clone();
+        let fields = fields.clone();
+        move |_| {
+            let status = status.clone();
+            let namespace = namespace.clone();
+            match read(&fields) {
+                Ok(job) => wasm_bindgen_futures::spawn_local(async move {
+                    let message = match State::push_job(namespace, job).await {
+                        Ok(message) => message,
+                        Err(e) => e.to_string(),
+                    };
+                    status.set(message);
+                }),
+                Err(e) => status.set(e),
+            }
+        }
+    };
+    html! {
+        <div class="rounded-sm border text-card-foreground shadow-sm p-4 space-y-3">
+            <h3 class="text-lg font-semibold">"Push a job"</h3>
+            {for field in fields {
+                html! {
+                    <label class="block text-sm space-y-1">
+                        <span class="text-gray-500">{if field.required { format!("{} *", field.name) } else { field.name.clone() }}</span>
+                        {input(&field)}
+                    </label>
+                }
+            }}
+            <button on:click=submit class="inline-flex items-center justify-center rounded-md text-sm font-medium h-10 px-4 py-2 bg-blue-600 text-white">
+                "Push"
+            </button>
+            <p class="text-sm text-gray-500">{status.signal_cloned()}</p>
+        </div>
+    }
+}
+
+/// A form generated from the job schema of a namespace, nothing if it has none
+#[component]
+pub fn PushForm(namespace: String) -> Dom {
+    html! {
+        <div>
+            {match State::job_schema(namespace.clone()).suspend().await {
+                Loading => html! { <div>"Loading..."</div> },
+                Ready(Ok(schema)) => form(namespace, schema),
+                Ready(Err(_)) => html! { <></> }
+            }}
+        </div>
+    }
+}
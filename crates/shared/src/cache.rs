@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use apalis_core::task::task_id::TaskId;
+use futures::lock::Mutex;
+
+use crate::{BackendExt, JobProgress, JobState, ReservedKey, Stat, Worker};
+
+/// How long [`Cached`] keeps the [`Stat`]s unless told otherwise
+pub const DEFAULT_TTL: Duration = Duration::from_secs(1);
+
+/// Keeps the [`Stat`]s of a backend for a ttl instead of aggregating them on every call.
+/// Concurrent calls while they are fetched wait for that one query instead of running their own.
+/// Clones share the cache, everything else goes straight to the backend.
+///
+/// ```rust,ignore
+/// let storage = Cached::new(storage).ttl(Duration::from_secs(5));
+/// let stats = storage.stats().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cached<B> {
+    backend: B,
+    ttl: Duration,
+    stats: Arc<Mutex<Option<(Instant, Stat)>>>,
+}
+
+impl<B> Cached<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            ttl: DEFAULT_TTL,
+            stats: Default::default(),
+        }
+    }
+
+    /// How long the stats are kept, a second by default
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn into_inner(self) -> B {
+        self.backend
+    }
+
+    /// Forget the stats, the next call fetches them again
+    pub async fn invalidate(&self) {
+        *self.stats.lock().await = None;
+    }
+}
+
+impl<T, B> BackendExt<T> for Cached<B>
+where
+    B: BackendExt<T> + Sync,
+{
+    type Request = B::Request;
+    type Error = B::Error;
+
+    fn list_workers(&self) -> impl Future<Output = Result<Vec<Worker>, Self::Error>> + Send {
+        self.backend.list_workers()
+    }
+
+    fn stats(&self) -> impl Future<Output = Result<Stat, Self::Error>> + Send {
+        async move {
+            // Held while fetching so concurrent calls wait for this query
+            let mut cached = self.stats.lock().await;
+            if let Some((at, stats)) = cached.as_ref() {
+                if at.elapsed() < self.ttl {
+                    return Ok(stats.clone());
+                }
+            }
+            let stats = self.backend.stats().await?;
+            *cached = Some((Instant::now(), stats.clone()));
+            Ok(stats)
+        }
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.backend.ping()
+    }
+
+    fn list_jobs(
+        &self,
+        status: &JobState,
+        page: i32,
+    ) -> impl Future<Output = Result<Vec<Self::Request>, Self::Error>> + Send {
+        self.backend.list_jobs(status, page)
+    }
+
+    fn set_progress(
+        &self,
+        progress: &JobProgress,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.backend.set_progress(progress)
+    }
+
+    fn list_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> impl Future<Output = Result<HashMap<String, JobProgress>, Self::Error>> + Send {
+        self.backend.list_progress(task_ids)
+    }
+
+    fn clear_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.backend.clear_progress(task_ids)
+    }
+
+    fn purge_before(
+        &self,
+        state: &JobState,
+        before: i64,
+        limit: usize,
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        self.backend.purge_before(state, before, limit)
+    }
+
+    fn purge_beyond(
+        &self,
+        state: &JobState,
+        keep: usize,
+        limit: usize,
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        self.backend.purge_beyond(state, keep, limit)
+    }
+
+    fn reserve_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lease: Duration,
+    ) -> impl Future<Output = Result<Option<ReservedKey>, Self::Error>> + Send {
+        self.backend.reserve_key(key, fingerprint, lease)
+    }
+
+    fn settle_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        outcome: Option<&str>,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.backend.settle_key(key, fingerprint, outcome, ttl)
+    }
+}
@@ -1,4 +1,15 @@
-use std::{any::type_name, future::Future, num::TryFromIntError, time::Duration};
+use std::{
+    any::type_name,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    future::Future,
+    num::TryFromIntError,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[cfg(feature = "cache")]
+pub mod cache;
+
+pub mod migrate;
 
 #[cfg(feature = "redis")]
 pub mod redis;
@@ -12,13 +23,16 @@ pub mod postgres;
 #[cfg(feature = "mysql")]
 pub mod mysql;
 
-use apalis_core::worker::WorkerId;
+use apalis_core::{request::Request, task::task_id::TaskId, worker::WorkerId};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// A serializable version of a worker.
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Worker {
     /// The Worker's Id
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
     pub worker_id: WorkerId,
     /// Type of task being consumed by the worker, useful for display and filtering
     pub r#type: String,
@@ -40,7 +54,144 @@ impl Worker {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, Default)]
+/// What a namespace is for, listed with it by the board.
+///
+/// Storages on different backends can share a name once they are [`qualified`](Namespace::qualified)
+/// by the backend they are on, the board then serves them under `{backend id}:{name}`.
+///
+/// ```rust,ignore
+/// ApiBuilder::new().add_storage(
+///     &storage,
+///     Namespace::new("emails")
+///         .description("Transactional emails")
+///         .owner("growth")
+///         .tag("email")
+///         .runbook("https://wiki.example.com/runbooks/emails"),
+/// )
+/// .add_storage(&redis, Namespace::new("emails").qualified("redis").group("notifications"))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Namespace {
+    pub name: String,
+    /// Which of the board's backends the namespace is on, when its name alone is ambiguous
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qualifier: Option<String>,
+    /// The logical group the namespace is listed in, like `billing`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The team owning the jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Url of what to do when the queue misbehaves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub runbook: Option<String>,
+    /// The kind of storage like `postgres` or `redis`, guessed from the storage's type if not given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+}
+
+impl Namespace {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Serve the namespace as `{backend_id}:{name}`
+    pub fn qualified(mut self, backend_id: &str) -> Self {
+        self.qualifier = Some(backend_id.to_string());
+        self
+    }
+
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+
+    /// What the board serves the namespace as, its name unless it is qualified
+    pub fn key(&self) -> String {
+        match &self.qualifier {
+            Some(backend_id) => format!("{backend_id}:{}", self.name),
+            None => self.name.clone(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn owner(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        if !self.tags.iter().any(|t| t == tag) {
+            self.tags.push(tag.to_string());
+        }
+        self
+    }
+
+    pub fn runbook(mut self, url: &str) -> Self {
+        self.runbook = Some(url.to_string());
+        self
+    }
+
+    pub fn backend(mut self, kind: &str) -> Self {
+        self.backend = Some(kind.to_string());
+        self
+    }
+}
+
+impl From<&str> for Namespace {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<&String> for Namespace {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Namespace {
+    fn from(name: String) -> Self {
+        Self {
+            name,
+            ..Default::default()
+        }
+    }
+}
+
+/// A namespace as listed by the board, with its current stats
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NamespaceInfo {
+    #[serde(flatten)]
+    pub namespace: Namespace,
+    /// Missing if the storage couldn't be reached
+    pub stats: Option<Stat>,
+    pub workers: Option<usize>,
+}
+
+/// The namespaces of a logical group, `None` gathers the ungrouped ones
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct NamespaceGroup {
+    pub group: Option<String>,
+    pub namespaces: Vec<NamespaceInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, Default)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Stat {
     pub pending: usize,
     pub running: usize,
@@ -50,8 +201,20 @@ pub struct Stat {
 }
 
 #[derive(
-    Debug, Deserialize, Serialize, Default, strum::Display, strum::EnumString, strum::EnumIter,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Deserialize,
+    Serialize,
+    Default,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
 )]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum JobState {
     #[default]
     Pending,
@@ -62,7 +225,46 @@ pub enum JobState {
     Success,
 }
 
-#[derive(Deserialize, Debug)]
+impl JobState {
+    /// Maps a status as persisted by the sql storages to the board's state
+    pub fn from_sql_status(status: &str) -> Option<Self> {
+        match status {
+            "Pending" => Some(JobState::Pending),
+            "Running" => Some(JobState::Running),
+            "Done" => Some(JobState::Success),
+            "Retry" | "Failed" => Some(JobState::Failed),
+            "Killed" => Some(JobState::Dead),
+            _ => None,
+        }
+    }
+
+    /// Whether jobs in this state are done with, the only ones retention removes
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Success | JobState::Failed | JobState::Dead)
+    }
+
+    /// The status persisted by the sql storages for jobs in this state, scheduled jobs are pending ones
+    pub fn to_sql_status(&self) -> &'static str {
+        match self {
+            JobState::Pending | JobState::Scheduled => "Pending",
+            JobState::Running => "Running",
+            JobState::Failed => "Failed",
+            JobState::Dead => "Killed",
+            JobState::Success => "Done",
+        }
+    }
+
+    /// The statuses the sql storages list for this state, failed jobs waiting for a retry included
+    pub fn to_sql_statuses(&self) -> [&'static str; 2] {
+        match self {
+            JobState::Failed => ["Retry", "Failed"],
+            state => [state.to_sql_status(); 2],
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Filter {
     #[serde(default)]
     pub status: JobState,
@@ -74,6 +276,260 @@ fn default_page() -> i32 {
     1
 }
 
+/// A job of a batch pushed to `PUT /backend/{namespace}/jobs`, queued right away unless it has `run_at` or `delay`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Enqueue {
+    pub job: Value,
+    /// Unix timestamp (seconds) to run the job at
+    pub run_at: Option<i64>,
+    /// Seconds to wait before running the job
+    pub delay: Option<u64>,
+}
+
+impl Enqueue {
+    pub fn new(job: Value) -> Self {
+        Self {
+            job,
+            run_at: None,
+            delay: None,
+        }
+    }
+
+    pub fn run_at(mut self, timestamp: i64) -> Self {
+        self.run_at = Some(timestamp);
+        self
+    }
+
+    pub fn delay(mut self, seconds: u64) -> Self {
+        self.delay = Some(seconds);
+        self
+    }
+}
+
+/// A job queued by a push or an import
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Queued {
+    pub task_id: String,
+    /// When the job was scheduled to run, `None` if it was queued right away
+    pub run_at: Option<i64>,
+}
+
+/// How a storage answered the readiness check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Health {
+    pub up: bool,
+    pub latency_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `GET /backend/readyz` answers, `status` is `ok` or `unavailable`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Readiness {
+    pub status: String,
+    /// Whether every storage of a kind, like `postgres`, is up
+    pub backends: BTreeMap<String, bool>,
+    /// The health of every namespace, only answered to admins that can access all of them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespaces: Option<BTreeMap<String, Health>>,
+}
+
+/// What `GET /backend/{namespace}/export` streams
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub status: JobState,
+    #[serde(default)]
+    pub format: Format,
+    /// Comma separated dotted paths into each job, like `task_id,args.email,parts.attempts`.
+    /// `task_id` is the job's task id whatever the storage calls it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub columns: Option<String>,
+    /// Skip the redaction, only for admins
+    #[serde(default)]
+    pub raw: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// One JSON object per line
+    #[default]
+    Ndjson,
+    Csv,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Ndjson => "application/x-ndjson",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Ndjson => "ndjson",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+/// How `PUT /backend/{namespace}/import` treats the lines
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ImportOptions {
+    /// Only validate the lines, nothing is queued
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Schedule exported jobs at the `run_at` they had instead of queueing them now
+    #[serde(default)]
+    pub keep_run_at: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LineError {
+    pub line: usize,
+    pub message: String,
+}
+
+/// The outcome of an import.
+///
+/// Lines are all validated first, when any of them is invalid `errors` lists them and nothing was queued.
+/// If the storage fails midway `jobs` lists the ones queued before and `errors` the line it failed on.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ImportReport {
+    pub dry_run: bool,
+    /// Lines that passed validation
+    pub valid: usize,
+    pub jobs: Vec<Queued>,
+    pub errors: Vec<LineError>,
+}
+
+/// A mutating call made through the api
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AuditEntry {
+    /// Name of the identity that made the call
+    pub actor: String,
+    pub namespace: String,
+    pub task_id: Option<String>,
+    pub action: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+    /// Unix timestamp (seconds)
+    pub timestamp: u64,
+}
+
+impl AuditEntry {
+    pub fn new(actor: &str, namespace: &str, action: &str) -> Self {
+        Self {
+            actor: actor.to_string(),
+            namespace: namespace.to_string(),
+            task_id: None,
+            action: action.to_string(),
+            before: None,
+            after: None,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn task_id(mut self, task_id: impl ToString) -> Self {
+        self.task_id = Some(task_id.to_string());
+        self
+    }
+
+    pub fn before(mut self, before: Value) -> Self {
+        self.before = Some(before);
+        self
+    }
+
+    pub fn after(mut self, after: Value) -> Self {
+        self.after = Some(after);
+        self
+    }
+}
+
+/// Query for `GET /backend/audit`, entries are returned newest first
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AuditFilter {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Only entries at or after this unix timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    /// Only entries at or before this unix timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+    /// Only entries of these namespaces, set by the board to the ones the caller can access
+    /// so the limit applies to what it can see
+    #[serde(skip)]
+    pub namespaces: Option<BTreeSet<String>>,
+}
+
+impl Default for AuditFilter {
+    fn default() -> Self {
+        Self {
+            actor: None,
+            namespace: None,
+            task_id: None,
+            action: None,
+            since: None,
+            until: None,
+            limit: default_limit(),
+            namespaces: None,
+        }
+    }
+}
+
+fn default_limit() -> usize {
+    100
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        fn eq(filter: &Option<String>, value: Option<&str>) -> bool {
+            filter.as_deref().is_none_or(|f| Some(f) == value)
+        }
+        eq(&self.actor, Some(&entry.actor))
+            && eq(&self.namespace, Some(&entry.namespace))
+            && eq(&self.task_id, entry.task_id.as_deref())
+            && eq(&self.action, Some(&entry.action))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+            && self
+                .namespaces
+                .as_ref()
+                .is_none_or(|namespaces| namespaces.contains(&entry.namespace))
+    }
+}
+
+/// What a retention run did to a namespace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Run {
+    pub namespace: String,
+    /// Unix timestamp (seconds) the run started
+    pub started_at: u64,
+    pub removed: usize,
+    /// Set if the storage failed, `removed` counts the jobs removed before it did
+    pub error: Option<String>,
+}
+
 pub trait BackendExt<T>
 where
     Self: Sized,
@@ -86,12 +542,153 @@ where
     /// Returns the counts of jobs in different states
     fn stats(&self) -> impl Future<Output = Result<Stat, Self::Error>> + Send;
 
+    /// Checks the backend is reachable with the cheapest query it supports.
+    /// Defaults to fetching the [`Stat`]s.
+    fn ping(&self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        let stats = self.stats();
+        async move { stats.await.map(|_| ()) }
+    }
+
     /// Fetch jobs persisted in a backend
     fn list_jobs(
         &self,
         status: &JobState,
         page: i32,
     ) -> impl Future<Output = Result<Vec<Self::Request>, Self::Error>> + Send;
+
+    /// Persist the latest progress reported for a job.
+    /// Backends that can't store progress ignore it.
+    fn set_progress(
+        &self,
+        _progress: &JobProgress,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Fetch the latest progress persisted for the given jobs, keyed by task id
+    fn list_progress(
+        &self,
+        _task_ids: &[TaskId],
+    ) -> impl Future<Output = Result<HashMap<String, JobProgress>, Self::Error>> + Send {
+        async { Ok(HashMap::new()) }
+    }
+
+    /// Forget the progress persisted for jobs that finished.
+    /// Purges forget the progress of the jobs they remove on their own.
+    fn clear_progress(
+        &self,
+        _task_ids: &[TaskId],
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Removes up to `limit` jobs in a finished `state` that finished before `before`, a unix timestamp.
+    /// Returns how many were removed, backends that can't remove jobs remove none.
+    fn purge_before(
+        &self,
+        _state: &JobState,
+        _before: i64,
+        _limit: usize,
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        async { Ok(0) }
+    }
+
+    /// Removes up to `limit` jobs in a finished `state`, oldest first, that aren't among the newest `keep`.
+    /// Returns how many were removed, backends that can't remove jobs remove none.
+    fn purge_beyond(
+        &self,
+        _state: &JobState,
+        _keep: usize,
+        _limit: usize,
+    ) -> impl Future<Output = Result<usize, Self::Error>> + Send {
+        async { Ok(0) }
+    }
+
+    /// Reserve an idempotency key of this backend's namespace for a request hashed to `fingerprint`.
+    /// The reservation only lasts `lease` so a request that never settles doesn't hold the key for long.
+    /// Returns `None` if the key was free, otherwise the request that reserved it.
+    /// Backends that can't store keys never find one.
+    fn reserve_key(
+        &self,
+        _key: &str,
+        _fingerprint: &str,
+        _lease: Duration,
+    ) -> impl Future<Output = Result<Option<ReservedKey>, Self::Error>> + Send {
+        async { Ok(None) }
+    }
+
+    /// Store the outcome of the request that reserved `key` with `fingerprint` and keep it for `ttl`, `None` frees the key.
+    /// Nothing changes if the key is now held by another request, its lease having run out.
+    fn settle_key(
+        &self,
+        _key: &str,
+        _fingerprint: &str,
+        _outcome: Option<&str>,
+        _ttl: Duration,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+}
+
+/// An idempotency key found reserved by [`BackendExt::reserve_key`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReservedKey {
+    /// The hash of the request that reserved the key
+    pub fingerprint: String,
+    /// What [`BackendExt::settle_key`] stored, empty while the request is still running
+    pub outcome: String,
+}
+
+/// Gives access to the task id of the requests returned by [`BackendExt::list_jobs`],
+/// and to what a [`Migration`](migrate::Migration) keeps of their history
+pub trait RequestExt {
+    fn task_id(&self) -> &TaskId;
+
+    /// How many times the job was attempted
+    fn attempts(&self) -> usize {
+        0
+    }
+
+    /// Unix timestamp the job is scheduled for, if the backend records it
+    fn run_at(&self) -> Option<i64> {
+        None
+    }
+
+    /// Unix timestamp the job finished at, if the backend records it
+    fn done_at(&self) -> Option<i64> {
+        None
+    }
+
+    /// Whether the job failed and waits to be retried, for backends that tell it apart from failed ones
+    fn retrying(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(any(feature = "sqlite", feature = "postgres", feature = "mysql"))]
+impl<T> RequestExt for Request<T, apalis_core::request::Parts<apalis_sql::context::SqlContext>> {
+    fn task_id(&self) -> &TaskId {
+        &self.parts.context.task_id
+    }
+
+    fn attempts(&self) -> usize {
+        self.parts.context.attempt.current()
+    }
+
+    fn run_at(&self) -> Option<i64> {
+        Some(self.parts.context.context.run_at().timestamp())
+    }
+
+    fn done_at(&self) -> Option<i64> {
+        *self.parts.context.context.done_at()
+    }
+
+    fn retrying(&self) -> bool {
+        matches!(
+            self.parts.context.context.status(),
+            apalis_sql::context::State::Retry
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -107,6 +704,7 @@ pub enum BoardConfig {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum Layer {
     Retry { retries: u64 },
     Timeout { duration: Duration },
@@ -119,9 +717,65 @@ pub enum Layer {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct GetJobsResult<T> {
     pub stats: Stat,
     pub jobs: Vec<T>,
+    /// The latest progress reported for the listed jobs, keyed by task id
+    #[serde(default)]
+    pub progress: HashMap<String, JobProgress>,
+}
+
+/// Progress reported by a handler for a running job
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct JobProgress {
+    pub task_id: String,
+    /// Completion between 0 and 100
+    pub percent: u8,
+    pub message: Option<String>,
+    /// Unix timestamp (seconds) of the report
+    pub updated_at: u64,
+}
+
+impl JobProgress {
+    pub fn new(task_id: &TaskId, percent: u8, message: Option<String>) -> Self {
+        Self {
+            task_id: task_id.to_string(),
+            percent: percent.min(100),
+            message,
+            updated_at: unix_now(),
+        }
+    }
+}
+
+/// Seconds since the unix epoch
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Events pushed to the board through its event channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum BoardEvent {
+    Progress {
+        namespace: String,
+        progress: JobProgress,
+    },
+    /// A job was added or moved to another state.
+    /// `from` is `None` for new jobs.
+    JobUpdated {
+        namespace: String,
+        task_id: String,
+        from: Option<JobState>,
+        to: JobState,
+    },
+    /// Jobs in `state` changed but the backend can't tell which ones
+    QueueUpdated { namespace: String, state: JobState },
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -130,4 +784,6 @@ pub enum SqlError {
     Sqlx(#[from] sqlx::Error),
     #[error("TryFromIntError: {0}")]
     TryFromInt(#[from] TryFromIntError),
+    #[error("serde_json::Error: {0}")]
+    Json(#[from] serde_json::Error),
 }
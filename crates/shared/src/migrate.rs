@@ -0,0 +1,240 @@
+use std::{future::Future, marker::PhantomData};
+
+use apalis_core::{request::Request, task::task_id::TaskId};
+use serde::{Deserialize, Serialize};
+
+use crate::{BackendExt, JobState, RequestExt, Stat};
+
+/// The states copied by a [`Migration`], in order.
+/// Backends that list scheduled jobs with the pending ones have them skipped the second time.
+pub const STATES: [JobState; 6] = [
+    JobState::Pending,
+    JobState::Scheduled,
+    JobState::Running,
+    JobState::Failed,
+    JobState::Dead,
+    JobState::Success,
+];
+
+/// Jobs are listed this many at a time, a shorter page is the last one
+const PAGE_SIZE: usize = 10;
+
+/// What a [`Migration`] keeps of a job besides its payload and state, read with [`RequestExt`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct History {
+    pub attempts: usize,
+    /// Unix timestamp the job is scheduled for, now if the source doesn't record it
+    pub run_at: Option<i64>,
+    /// Unix timestamp the job finished at, now for finished jobs if the source doesn't record it
+    pub done_at: Option<i64>,
+    /// A failed job waiting to be retried, restored as such by the sql backends
+    pub retrying: bool,
+}
+
+impl History {
+    pub fn of<R: RequestExt>(request: &R) -> Self {
+        Self {
+            attempts: request.attempts(),
+            run_at: request.run_at(),
+            done_at: request.done_at(),
+            retrying: request.retrying(),
+        }
+    }
+}
+
+/// Backends a [`Migration`] can write to
+pub trait RestoreExt<T>: BackendExt<T> {
+    /// Inserts a job copied from another backend, keeping its task id, state and [`History`].
+    /// Running jobs are restored as pending since no worker holds them anymore.
+    /// Returns `false` if a job with the same task id is already there, so an interrupted migration can run again.
+    fn restore(
+        &self,
+        task_id: &TaskId,
+        job: &T,
+        state: JobState,
+        history: History,
+    ) -> impl Future<Output = Result<bool, Self::Error>> + Send;
+}
+
+/// Where a [`Migration`] is, save it after every step to resume with [`Migration::resume`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// The state being copied
+    pub state: JobState,
+    /// The next page of `state` to read
+    pub page: i32,
+    /// Jobs written to the target
+    pub copied: usize,
+    /// Jobs the target already had
+    pub skipped: usize,
+    pub finished: bool,
+    /// The first task id of the page read last. Sources that ignore the page, like redis for running jobs,
+    /// answer it again once there are no more.
+    #[serde(default)]
+    pub last: Option<String>,
+}
+
+impl Default for Checkpoint {
+    fn default() -> Self {
+        Self {
+            state: STATES[0],
+            page: 1,
+            copied: 0,
+            skipped: 0,
+            finished: false,
+            last: None,
+        }
+    }
+}
+
+impl Checkpoint {
+    /// Moves to the first page of the next state, or finishes after the last one
+    fn next_state(&mut self) {
+        let next = STATES
+            .iter()
+            .position(|state| *state == self.state)
+            .and_then(|index| STATES.get(index + 1));
+        match next {
+            Some(state) => {
+                self.state = *state;
+                self.page = 1;
+                self.last = None;
+            }
+            None => self.finished = true,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrateError<S, D> {
+    #[error("reading the source failed: {0}")]
+    Source(S),
+    #[error("writing the target failed: {0}")]
+    Target(D),
+}
+
+/// Copies the jobs of a namespace from one backend to another, a page at a time.
+///
+/// Workers of both sides should be stopped so pages don't shift while they are read.
+/// The payload, task id, state and [`History`] of jobs are copied.
+///
+/// ```rust,ignore
+/// let checkpoint = Migration::new(&sqlite, &postgres)
+///     .run(|checkpoint| save(checkpoint))
+///     .await?;
+/// assert!(verify(&sqlite, &postgres).await?.matches());
+/// ```
+pub struct Migration<'a, T, S, D> {
+    source: &'a S,
+    target: &'a D,
+    checkpoint: Checkpoint,
+    job: PhantomData<fn() -> T>,
+}
+
+impl<'a, T, C, S, D> Migration<'a, T, S, D>
+where
+    S: BackendExt<T, Request = Request<T, C>>,
+    Request<T, C>: RequestExt,
+    D: RestoreExt<T>,
+{
+    pub fn new(source: &'a S, target: &'a D) -> Self {
+        Self {
+            source,
+            target,
+            checkpoint: Checkpoint::default(),
+            job: PhantomData,
+        }
+    }
+
+    /// Continue from where a previous migration stopped.
+    /// The page it stopped on is copied again, jobs the target already has are skipped.
+    pub fn resume(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = checkpoint;
+        self
+    }
+
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Copies the next page and moves the checkpoint past it
+    pub async fn step(&mut self) -> Result<&Checkpoint, MigrateError<S::Error, D::Error>> {
+        let checkpoint = &mut self.checkpoint;
+        if checkpoint.finished {
+            return Ok(checkpoint);
+        }
+        let jobs = self
+            .source
+            .list_jobs(&checkpoint.state, checkpoint.page)
+            .await
+            .map_err(MigrateError::Source)?;
+        let first = jobs.first().map(|job| job.task_id().to_string());
+        if first.is_none() || first == checkpoint.last {
+            checkpoint.next_state();
+            return Ok(checkpoint);
+        }
+        for job in &jobs {
+            let restored = self
+                .target
+                .restore(job.task_id(), &job.args, checkpoint.state, History::of(job))
+                .await
+                .map_err(MigrateError::Target)?;
+            if restored {
+                checkpoint.copied += 1;
+            } else {
+                checkpoint.skipped += 1;
+            }
+        }
+        if jobs.len() < PAGE_SIZE {
+            checkpoint.next_state();
+        } else {
+            checkpoint.page += 1;
+            checkpoint.last = first;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Copies every page, calling `on_checkpoint` after each one
+    pub async fn run(
+        mut self,
+        mut on_checkpoint: impl FnMut(&Checkpoint),
+    ) -> Result<Checkpoint, MigrateError<S::Error, D::Error>> {
+        while !self.checkpoint.finished {
+            on_checkpoint(self.step().await?);
+        }
+        Ok(self.checkpoint)
+    }
+}
+
+/// The [`Stat`]s of both sides of a migration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    pub source: Stat,
+    pub target: Stat,
+}
+
+impl Verification {
+    /// Whether the target has as many jobs in each state as the source, counting running jobs as pending
+    pub fn matches(&self) -> bool {
+        let (source, target) = (&self.source, &self.target);
+        source.pending + source.running == target.pending + target.running
+            && source.failed == target.failed
+            && source.dead == target.dead
+            && source.success == target.success
+    }
+}
+
+/// Compares the [`Stat`]s of both sides of a migration
+pub async fn verify<T, S, D>(
+    source: &S,
+    target: &D,
+) -> Result<Verification, MigrateError<S::Error, D::Error>>
+where
+    S: BackendExt<T>,
+    D: BackendExt<T>,
+{
+    Ok(Verification {
+        source: source.stats().await.map_err(MigrateError::Source)?,
+        target: target.stats().await.map_err(MigrateError::Target)?,
+    })
+}
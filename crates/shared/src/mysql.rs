@@ -1,14 +1,53 @@
+use std::{collections::HashMap, time::Duration};
+
 use apalis_core::{
     codec::json::JsonCodec,
     request::{Parts, Request},
+    task::task_id::TaskId,
     worker::WorkerId,
     Codec,
 };
-use apalis_sql::{context::SqlContext, from_row::SqlRequest, mysql::MysqlStorage};
+use apalis_sql::{
+    context::SqlContext,
+    from_row::SqlRequest,
+    mysql::{MySqlPool, MysqlStorage},
+};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
+use sqlx::QueryBuilder;
+
+use crate::{
+    migrate::{History, RestoreExt},
+    unix_now, BackendExt, JobProgress, JobState, ReservedKey, SqlError, Stat, Worker,
+};
 
-use crate::{BackendExt, JobState, SqlError, Stat, Worker};
+/// Creates the tables used by the board next to the ones created by [`MysqlStorage::setup`]
+pub async fn setup(pool: &MySqlPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS board_progress (
+            task_id VARCHAR(64) NOT NULL PRIMARY KEY,
+            job_type VARCHAR(255) NOT NULL,
+            percent INT NOT NULL,
+            message TEXT,
+            updated_at BIGINT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS board_idempotency (
+            namespace VARCHAR(255) NOT NULL,
+            idempotency_key VARCHAR(255) NOT NULL,
+            fingerprint CHAR(64) NOT NULL,
+            outcome MEDIUMTEXT NOT NULL,
+            expires_at BIGINT NOT NULL,
+            PRIMARY KEY (namespace, idempotency_key)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
 
 type MysqlCodec = JsonCodec<Value>;
 
@@ -22,12 +61,11 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
             COUNT(CASE WHEN status = 'Pending' THEN 1 END) AS pending,
             COUNT(CASE WHEN status = 'Running' THEN 1 END) AS running,
             COUNT(CASE WHEN status = 'Done' THEN 1 END) AS done,
-            COUNT(CASE WHEN status = 'Retry' THEN 1 END) AS retry,
-            COUNT(CASE WHEN status = 'Failed' THEN 1 END) AS failed,
+            COUNT(CASE WHEN status IN ('Retry', 'Failed') THEN 1 END) AS failed,
             COUNT(CASE WHEN status = 'Killed' THEN 1 END) AS killed
         FROM jobs WHERE job_type = ?";
 
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(self.get_config().namespace())
             .fetch_one(self.pool())
             .await?;
@@ -46,9 +84,10 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
         status: &JobState,
         page: i32,
     ) -> Result<Vec<Self::Request>, Self::Error> {
-        let status = status.to_string();
-        let fetch_query = "SELECT * FROM jobs WHERE status = ? AND job_type = ? ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET ?";
+        let [retry, status] = status.to_sql_statuses();
+        let fetch_query = "SELECT * FROM jobs WHERE status IN (?, ?) AND job_type = ? ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET ?";
         let res: Vec<SqlRequest<serde_json::Value>> = sqlx::query_as(fetch_query)
+            .bind(retry)
             .bind(status)
             .bind(self.get_config().namespace())
             .bind(((page - 1) * 10).to_string())
@@ -77,4 +116,241 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
             .map(|w| Worker::new::<Self>(WorkerId::new(w.0), w.1))
             .collect())
     }
+
+    async fn ping(&self) -> Result<(), Self::Error> {
+        sqlx::query("SELECT 1").execute(self.pool()).await?;
+        Ok(())
+    }
+
+    async fn purge_before(
+        &self,
+        state: &JobState,
+        before: i64,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE jobs FROM jobs JOIN (SELECT id FROM jobs WHERE job_type = ? AND status = ? AND done_at < FROM_UNIXTIME(?) ORDER BY done_at LIMIT ?) old ON jobs.id = old.id";
+        let removed = sqlx::query(query)
+            .bind(self.get_config().namespace())
+            .bind(state.to_sql_status())
+            .bind(before)
+            .bind(i64::try_from(limit)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.get_config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn purge_beyond(
+        &self,
+        state: &JobState,
+        keep: usize,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE jobs FROM jobs JOIN (SELECT id FROM jobs WHERE job_type = ? AND status = ? ORDER BY done_at DESC LIMIT ? OFFSET ?) old ON jobs.id = old.id";
+        let removed = sqlx::query(query)
+            .bind(self.get_config().namespace())
+            .bind(state.to_sql_status())
+            .bind(i64::try_from(limit)?)
+            .bind(i64::try_from(keep)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.get_config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn reserve_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lease: Duration,
+    ) -> Result<Option<ReservedKey>, Self::Error> {
+        let now = i64::try_from(unix_now())?;
+        let expires_at = now.saturating_add(i64::try_from(lease.as_secs())?);
+        sqlx::query("DELETE FROM board_idempotency WHERE namespace = ? AND idempotency_key = ? AND expires_at <= ?")
+            .bind(self.get_config().namespace())
+            .bind(key)
+            .bind(now)
+            .execute(self.pool())
+            .await?;
+        let reserved = sqlx::query("INSERT IGNORE INTO board_idempotency (namespace, idempotency_key, fingerprint, outcome, expires_at) VALUES (?, ?, ?, '', ?)")
+            .bind(self.get_config().namespace())
+            .bind(key)
+            .bind(fingerprint)
+            .bind(expires_at)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        if reserved {
+            return Ok(None);
+        }
+        let reserved: Option<(String, String)> = sqlx::query_as(
+            "SELECT fingerprint, outcome FROM board_idempotency WHERE namespace = ? AND idempotency_key = ?",
+        )
+        .bind(self.get_config().namespace())
+        .bind(key)
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(Some(
+            reserved
+                .map(|(fingerprint, outcome)| ReservedKey {
+                    fingerprint,
+                    outcome,
+                })
+                .unwrap_or_default(),
+        ))
+    }
+
+    async fn settle_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        outcome: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), Self::Error> {
+        match outcome {
+            Some(outcome) => {
+                let expires_at = i64::try_from(unix_now().saturating_add(ttl.as_secs()))?;
+                sqlx::query("UPDATE board_idempotency SET outcome = ?, expires_at = ? WHERE namespace = ? AND idempotency_key = ? AND fingerprint = ?")
+                    .bind(outcome)
+                    .bind(expires_at)
+                    .bind(self.get_config().namespace())
+                    .bind(key)
+                    .bind(fingerprint)
+                    .execute(self.pool())
+                    .await?;
+            }
+            None => {
+                sqlx::query(
+                    "DELETE FROM board_idempotency WHERE namespace = ? AND idempotency_key = ? AND fingerprint = ?",
+                )
+                .bind(self.get_config().namespace())
+                .bind(key)
+                .bind(fingerprint)
+                .execute(self.pool())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_progress(&self, progress: &JobProgress) -> Result<(), Self::Error> {
+        let query = "INSERT INTO board_progress (task_id, job_type, percent, message, updated_at) VALUES (?, ?, ?, ?, ?)
+                    ON DUPLICATE KEY UPDATE percent = VALUES(percent), message = VALUES(message), updated_at = VALUES(updated_at)";
+        sqlx::query(query)
+            .bind(&progress.task_id)
+            .bind(self.get_config().namespace())
+            .bind(i64::from(progress.percent))
+            .bind(&progress.message)
+            .bind(i64::try_from(progress.updated_at)?)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn list_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> Result<HashMap<String, JobProgress>, Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut query = QueryBuilder::new(
+            "SELECT task_id, percent, message, updated_at FROM board_progress WHERE task_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for id in task_ids {
+            ids.push_bind(id.to_string());
+        }
+        query.push(")");
+        let res: Vec<(String, i64, Option<String>, i64)> =
+            query.build_query_as().fetch_all(self.pool()).await?;
+        res.into_iter()
+            .map(
+                |(task_id, percent, message, updated_at)| -> Result<_, SqlError> {
+                    Ok((
+                        task_id.clone(),
+                        JobProgress {
+                            task_id,
+                            percent: percent.try_into()?,
+                            message,
+                            updated_at: updated_at.try_into()?,
+                        },
+                    ))
+                },
+            )
+            .collect()
+    }
+
+    async fn clear_progress(&self, task_ids: &[TaskId]) -> Result<(), Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(());
+        }
+        let mut query = QueryBuilder::new("DELETE FROM board_progress WHERE task_id IN (");
+        let mut ids = query.separated(", ");
+        for id in task_ids {
+            ids.push_bind(id.to_string());
+        }
+        query.push(")");
+        query.build().execute(self.pool()).await?;
+        Ok(())
+    }
+}
+
+/// Deletes the progress of the jobs of `namespace` that were removed
+async fn clear_removed_progress(pool: &MySqlPool, namespace: &str) -> Result<(), sqlx::Error> {
+    let query = "DELETE FROM board_progress WHERE job_type = ? AND task_id NOT IN (SELECT id FROM jobs WHERE job_type = ?)";
+    sqlx::query(query)
+        .bind(namespace)
+        .bind(namespace)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> RestoreExt<J>
+    for MysqlStorage<J>
+{
+    async fn restore(
+        &self,
+        task_id: &TaskId,
+        job: &J,
+        state: JobState,
+        history: History,
+    ) -> Result<bool, Self::Error> {
+        let status = match state {
+            JobState::Running => JobState::Pending.to_sql_status(),
+            JobState::Failed if history.retrying => "Retry",
+            state => state.to_sql_status(),
+        };
+        let finished = state.is_finished() && !history.retrying;
+        let query = "INSERT IGNORE INTO jobs (job, id, job_type, status, attempts, run_at, done_at)
+                    VALUES (?, ?, ?, ?, ?, COALESCE(FROM_UNIXTIME(?), NOW()), COALESCE(FROM_UNIXTIME(?), CASE WHEN ? THEN NOW() END))";
+        let restored = sqlx::query(query)
+            .bind(serde_json::to_value(job)?)
+            .bind(task_id.to_string())
+            .bind(self.get_config().namespace())
+            .bind(status)
+            .bind(history.attempts as i32)
+            .bind(history.run_at)
+            .bind(history.done_at)
+            .bind(finished)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        Ok(restored)
+    }
 }
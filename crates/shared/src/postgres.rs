@@ -1,11 +1,49 @@
-use crate::{BackendExt, JobState, SqlError, Stat, Worker};
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    migrate::{History, RestoreExt},
+    unix_now, BackendExt, JobProgress, JobState, ReservedKey, SqlError, Stat, Worker,
+};
 use apalis_core::request::Parts;
+use apalis_core::task::task_id::TaskId;
 use apalis_core::Codec;
 use apalis_core::{codec::json::JsonCodec, request::Request, worker::WorkerId};
-use apalis_sql::{context::SqlContext, from_row::SqlRequest, postgres::PostgresStorage};
+use apalis_sql::{
+    context::SqlContext,
+    from_row::SqlRequest,
+    postgres::{PgPool, PostgresStorage},
+};
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 
+/// Creates the tables used by the board next to the ones created by [`PostgresStorage::setup`]
+pub async fn setup(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS apalis.board_progress (
+            task_id TEXT PRIMARY KEY NOT NULL,
+            job_type TEXT NOT NULL,
+            percent INTEGER NOT NULL,
+            message TEXT,
+            updated_at BIGINT NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS apalis.board_idempotency (
+            namespace TEXT NOT NULL,
+            idempotency_key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            outcome TEXT NOT NULL,
+            expires_at BIGINT NOT NULL,
+            PRIMARY KEY (namespace, idempotency_key)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
 impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt<J>
     for PostgresStorage<J>
 {
@@ -16,12 +54,11 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
                             COUNT(1) FILTER (WHERE status = 'Pending') AS pending,
                             COUNT(1) FILTER (WHERE status = 'Running') AS running,
                             COUNT(1) FILTER (WHERE status = 'Done') AS done,
-                            COUNT(1) FILTER (WHERE status = 'Retry') AS retry,
-                            COUNT(1) FILTER (WHERE status = 'Failed') AS failed,
+                            COUNT(1) FILTER (WHERE status IN ('Retry', 'Failed')) AS failed,
                             COUNT(1) FILTER (WHERE status = 'Killed') AS killed
                         FROM apalis.jobs WHERE job_type = $1";
 
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(self.config().namespace())
             .fetch_one(self.pool())
             .await?;
@@ -40,9 +77,10 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
         status: &JobState,
         page: i32,
     ) -> Result<Vec<Self::Request>, Self::Error> {
-        let status = status.to_string();
-        let fetch_query = "SELECT * FROM apalis.jobs WHERE status = $1 AND job_type = $2 ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET $3";
+        let [retry, status] = status.to_sql_statuses();
+        let fetch_query = "SELECT * FROM apalis.jobs WHERE status IN ($1, $2) AND job_type = $3 ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET $4";
         let res: Vec<SqlRequest<serde_json::Value>> = sqlx::query_as(fetch_query)
+            .bind(retry)
             .bind(status)
             .bind(self.config().namespace())
             .bind(((page - 1) * 10).to_string())
@@ -71,4 +109,227 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
             .map(|w| Worker::new::<Self>(WorkerId::new(w.0), w.1))
             .collect())
     }
+
+    async fn ping(&self) -> Result<(), Self::Error> {
+        sqlx::query("SELECT 1").execute(self.pool()).await?;
+        Ok(())
+    }
+
+    async fn purge_before(
+        &self,
+        state: &JobState,
+        before: i64,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE FROM apalis.jobs WHERE id IN (SELECT id FROM apalis.jobs WHERE job_type = $1 AND status = $2 AND done_at < to_timestamp($3) ORDER BY done_at LIMIT $4)";
+        let removed = sqlx::query(query)
+            .bind(self.config().namespace())
+            .bind(state.to_sql_status())
+            .bind(before)
+            .bind(i64::try_from(limit)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn purge_beyond(
+        &self,
+        state: &JobState,
+        keep: usize,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE FROM apalis.jobs WHERE id IN (SELECT id FROM apalis.jobs WHERE job_type = $1 AND status = $2 ORDER BY done_at DESC LIMIT $3 OFFSET $4)";
+        let removed = sqlx::query(query)
+            .bind(self.config().namespace())
+            .bind(state.to_sql_status())
+            .bind(i64::try_from(limit)?)
+            .bind(i64::try_from(keep)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn reserve_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lease: Duration,
+    ) -> Result<Option<ReservedKey>, Self::Error> {
+        let now = i64::try_from(unix_now())?;
+        let expires_at = now.saturating_add(i64::try_from(lease.as_secs())?);
+        sqlx::query("DELETE FROM apalis.board_idempotency WHERE namespace = $1 AND idempotency_key = $2 AND expires_at <= $3")
+            .bind(self.config().namespace())
+            .bind(key)
+            .bind(now)
+            .execute(self.pool())
+            .await?;
+        let reserved = sqlx::query("INSERT INTO apalis.board_idempotency (namespace, idempotency_key, fingerprint, outcome, expires_at) VALUES ($1, $2, $3, '', $4) ON CONFLICT (namespace, idempotency_key) DO NOTHING")
+            .bind(self.config().namespace())
+            .bind(key)
+            .bind(fingerprint)
+            .bind(expires_at)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        if reserved {
+            return Ok(None);
+        }
+        let reserved: Option<(String, String)> = sqlx::query_as("SELECT fingerprint, outcome FROM apalis.board_idempotency WHERE namespace = $1 AND idempotency_key = $2")
+            .bind(self.config().namespace())
+            .bind(key)
+            .fetch_optional(self.pool())
+            .await?;
+        Ok(Some(
+            reserved
+                .map(|(fingerprint, outcome)| ReservedKey {
+                    fingerprint,
+                    outcome,
+                })
+                .unwrap_or_default(),
+        ))
+    }
+
+    async fn settle_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        outcome: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), Self::Error> {
+        match outcome {
+            Some(outcome) => {
+                let expires_at = i64::try_from(unix_now().saturating_add(ttl.as_secs()))?;
+                sqlx::query("UPDATE apalis.board_idempotency SET outcome = $1, expires_at = $2 WHERE namespace = $3 AND idempotency_key = $4 AND fingerprint = $5")
+                    .bind(outcome)
+                    .bind(expires_at)
+                    .bind(self.config().namespace())
+                    .bind(key)
+                    .bind(fingerprint)
+                    .execute(self.pool())
+                    .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM apalis.board_idempotency WHERE namespace = $1 AND idempotency_key = $2 AND fingerprint = $3")
+                    .bind(self.config().namespace())
+                    .bind(key)
+                    .bind(fingerprint)
+                    .execute(self.pool())
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_progress(&self, progress: &JobProgress) -> Result<(), Self::Error> {
+        let query = "INSERT INTO apalis.board_progress (task_id, job_type, percent, message, updated_at) VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (task_id) DO UPDATE SET percent = EXCLUDED.percent, message = EXCLUDED.message, updated_at = EXCLUDED.updated_at";
+        sqlx::query(query)
+            .bind(&progress.task_id)
+            .bind(self.config().namespace())
+            .bind(i32::from(progress.percent))
+            .bind(&progress.message)
+            .bind(i64::try_from(progress.updated_at)?)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn list_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> Result<HashMap<String, JobProgress>, Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let query = "SELECT task_id, percent, message, updated_at FROM apalis.board_progress WHERE task_id = ANY($1)";
+        let res: Vec<(String, i32, Option<String>, i64)> = sqlx::query_as(query)
+            .bind(task_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .fetch_all(self.pool())
+            .await?;
+        res.into_iter()
+            .map(
+                |(task_id, percent, message, updated_at)| -> Result<_, SqlError> {
+                    Ok((
+                        task_id.clone(),
+                        JobProgress {
+                            task_id,
+                            percent: percent.try_into()?,
+                            message,
+                            updated_at: updated_at.try_into()?,
+                        },
+                    ))
+                },
+            )
+            .collect()
+    }
+
+    async fn clear_progress(&self, task_ids: &[TaskId]) -> Result<(), Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(());
+        }
+        let query = "DELETE FROM apalis.board_progress WHERE task_id = ANY($1)";
+        sqlx::query(query)
+            .bind(task_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+}
+
+/// Deletes the progress of the jobs of `namespace` that were removed
+async fn clear_removed_progress(pool: &PgPool, namespace: &str) -> Result<(), sqlx::Error> {
+    let query = "DELETE FROM apalis.board_progress WHERE job_type = $1 AND task_id NOT IN (SELECT id FROM apalis.jobs WHERE job_type = $1)";
+    sqlx::query(query).bind(namespace).execute(pool).await?;
+    Ok(())
+}
+
+impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> RestoreExt<J>
+    for PostgresStorage<J>
+{
+    async fn restore(
+        &self,
+        task_id: &TaskId,
+        job: &J,
+        state: JobState,
+        history: History,
+    ) -> Result<bool, Self::Error> {
+        let status = match state {
+            JobState::Running => JobState::Pending.to_sql_status(),
+            JobState::Failed if history.retrying => "Retry",
+            state => state.to_sql_status(),
+        };
+        let finished = state.is_finished() && !history.retrying;
+        let query = "INSERT INTO apalis.jobs (job, id, job_type, status, attempts, run_at, done_at)
+                    VALUES ($1, $2, $3, $4, $5, COALESCE(to_timestamp($6), NOW()), COALESCE(to_timestamp($7), CASE WHEN $8 THEN NOW() END))
+                    ON CONFLICT (id) DO NOTHING";
+        let restored = sqlx::query(query)
+            .bind(serde_json::to_value(job)?)
+            .bind(task_id.to_string())
+            .bind(self.config().namespace())
+            .bind(status)
+            .bind(history.attempts as i32)
+            .bind(history.run_at)
+            .bind(history.done_at)
+            .bind(finished)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        Ok(restored)
+    }
 }
@@ -1,6 +1,9 @@
-use crate::{BackendExt, JobState, Stat, Worker};
+use std::{collections::HashMap, time::Duration};
+
+use crate::{BackendExt, JobProgress, JobState, RequestExt, ReservedKey, Stat, Worker};
 use apalis_core::codec::json::JsonCodec;
 use apalis_core::request::Request;
+use apalis_core::task::task_id::TaskId;
 use apalis_core::worker::WorkerId;
 use apalis_core::Codec;
 use apalis_redis::RedisContext;
@@ -16,6 +19,12 @@ where
 {
     type Request = Request<T, RedisContext>;
     type Error = redis::RedisError;
+    async fn ping(&self) -> Result<(), redis::RedisError> {
+        let mut conn = self.get_connection().clone();
+        let _: String = redis::cmd("PING").query_async(&mut conn).await?;
+        Ok(())
+    }
+
     async fn stats(&self) -> Result<Stat, redis::RedisError> {
         let mut conn = self.get_connection().clone();
         let queue = self.get_config();
@@ -217,6 +226,222 @@ where
             })
             .collect())
     }
+
+    async fn purge_before(
+        &self,
+        state: &JobState,
+        before: i64,
+        limit: usize,
+    ) -> Result<usize, redis::RedisError> {
+        let Some(set) = finished_set(self, state) else {
+            return Ok(0);
+        };
+        let mut conn = self.get_connection().clone();
+        let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+            .arg(&set)
+            .arg("-inf")
+            .arg(format!("({before}"))
+            .arg("LIMIT")
+            .arg(0)
+            .arg(limit)
+            .query_async(&mut conn)
+            .await?;
+        remove_jobs(self, &set, &ids).await
+    }
+
+    async fn purge_beyond(
+        &self,
+        state: &JobState,
+        keep: usize,
+        limit: usize,
+    ) -> Result<usize, redis::RedisError> {
+        let Some(set) = finished_set(self, state) else {
+            return Ok(0);
+        };
+        let mut conn = self.get_connection().clone();
+        let count: usize = redis::cmd("ZCARD").arg(&set).query_async(&mut conn).await?;
+        let excess = count.saturating_sub(keep).min(limit);
+        if excess == 0 {
+            return Ok(0);
+        }
+        // Scores are the time jobs finished, lowest first
+        let ids: Vec<String> = redis::cmd("ZRANGE")
+            .arg(&set)
+            .arg(0)
+            .arg(excess - 1)
+            .query_async(&mut conn)
+            .await?;
+        remove_jobs(self, &set, &ids).await
+    }
+
+    async fn reserve_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lease: Duration,
+    ) -> Result<Option<ReservedKey>, redis::RedisError> {
+        let mut conn = self.get_connection().clone();
+        let key = idempotency_key(self, key);
+        // Stored as `{fingerprint}:{outcome}`, the fingerprint is hex so it never holds a `:`
+        let reserved: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(format!("{fingerprint}:"))
+            .arg("NX")
+            .arg("EX")
+            .arg(lease.as_secs().max(1))
+            .query_async(&mut conn)
+            .await?;
+        if reserved.is_some() {
+            return Ok(None);
+        }
+        let value: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+        let (fingerprint, outcome) = value
+            .as_deref()
+            .and_then(|value| value.split_once(':'))
+            .unwrap_or_default();
+        Ok(Some(ReservedKey {
+            fingerprint: fingerprint.to_string(),
+            outcome: outcome.to_string(),
+        }))
+    }
+
+    async fn settle_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        outcome: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), redis::RedisError> {
+        let mut conn = self.get_connection().clone();
+        let script = redis::Script::new(SETTLE_KEY);
+        let mut invocation = script.prepare_invoke();
+        invocation.key(idempotency_key(self, key)).arg(fingerprint);
+        if let Some(outcome) = outcome {
+            invocation.arg(outcome).arg(ttl.as_secs().max(1));
+        }
+        // Checked and written in one step, a request whose lease ran out can't overwrite the next one
+        let _: i64 = invocation.invoke_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn set_progress(&self, progress: &JobProgress) -> Result<(), redis::RedisError> {
+        let mut conn = self.get_connection().clone();
+        let value = serde_json::to_string(progress)
+            .map_err(|e| (ErrorKind::IoError, "Encode error", e.to_string()))?;
+        redis::cmd("HSET")
+            .arg(progress_hash(self))
+            .arg(&progress.task_id)
+            .arg(value)
+            .query_async(&mut conn)
+            .await
+    }
+
+    async fn list_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> Result<HashMap<String, JobProgress>, redis::RedisError> {
+        if task_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut conn = self.get_connection().clone();
+        let values: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(progress_hash(self))
+            .arg(task_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .query_async(&mut conn)
+            .await?;
+        Ok(values
+            .into_iter()
+            .flatten()
+            .filter_map(|v| serde_json::from_str::<JobProgress>(&v).ok())
+            .map(|p| (p.task_id.clone(), p))
+            .collect())
+    }
+
+    async fn clear_progress(&self, task_ids: &[TaskId]) -> Result<(), redis::RedisError> {
+        if task_ids.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.get_connection().clone();
+        redis::cmd("HDEL")
+            .arg(progress_hash(self))
+            .arg(task_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>())
+            .query_async(&mut conn)
+            .await
+    }
+}
+
+impl<T> RequestExt for Request<T, RedisContext> {
+    fn task_id(&self) -> &TaskId {
+        &self.parts.task_id
+    }
+
+    fn attempts(&self) -> usize {
+        self.parts.attempt.current()
+    }
+}
+
+/// The hash holding the latest progress of each job in a queue
+fn progress_hash<T>(storage: &RedisStorage<T>) -> String {
+    format!("{}:progress", storage.get_config().get_namespace())
+}
+
+/// The sorted set holding the jobs of a finished state
+fn finished_set<T>(storage: &RedisStorage<T>, state: &JobState) -> Option<String> {
+    let config = storage.get_config();
+    match state {
+        JobState::Success => Some(config.done_jobs_set()),
+        JobState::Failed => Some(config.failed_jobs_set()),
+        JobState::Dead => Some(config.dead_jobs_set()),
+        _ => None,
+    }
+}
+
+/// Removes jobs from a finished set along with their data and progress
+async fn remove_jobs<T>(
+    storage: &RedisStorage<T>,
+    set: &str,
+    ids: &[String],
+) -> Result<usize, redis::RedisError> {
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    let mut conn = storage.get_connection().clone();
+    let _: () = redis::pipe()
+        .atomic()
+        .cmd("ZREM")
+        .arg(set)
+        .arg(ids)
+        .ignore()
+        .cmd("HDEL")
+        .arg(storage.get_config().job_data_hash())
+        .arg(ids)
+        .ignore()
+        .cmd("HDEL")
+        .arg(progress_hash(storage))
+        .arg(ids)
+        .ignore()
+        .query_async(&mut conn)
+        .await?;
+    Ok(ids.len())
+}
+
+/// The key remembering the outcome of a request made with an idempotency key
+/// Settles an idempotency key only while it holds the fingerprint that reserved it,
+/// freeing it without an outcome or storing `{fingerprint}:{outcome}` for the ttl
+const SETTLE_KEY: &str = r"
+local value = redis.call('GET', KEYS[1])
+if not value or string.sub(value, 1, #ARGV[1] + 1) ~= ARGV[1] .. ':' then
+    return 0
+end
+if #ARGV == 1 then
+    return redis.call('DEL', KEYS[1])
+end
+redis.call('SET', KEYS[1], ARGV[1] .. ':' .. ARGV[2], 'EX', ARGV[3])
+return 1
+";
+
+fn idempotency_key<T>(storage: &RedisStorage<T>, key: &str) -> String {
+    format!("{}:idempotency:{key}", storage.get_config().get_namespace())
 }
 
 fn deserialize_multiple_jobs<T, C: Codec<Compact = Vec<u8>>>(
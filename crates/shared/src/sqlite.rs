@@ -1,13 +1,52 @@
+use std::{collections::HashMap, time::Duration};
+
 use apalis_core::{
     codec::json::JsonCodec,
     request::{Parts, Request},
+    task::task_id::TaskId,
     worker::WorkerId,
     Codec,
 };
-use apalis_sql::{context::SqlContext, from_row::SqlRequest, sqlite::SqliteStorage};
+use apalis_sql::{
+    context::SqlContext,
+    from_row::SqlRequest,
+    sqlite::{SqlitePool, SqliteStorage},
+};
 use serde::{de::DeserializeOwned, Serialize};
+use sqlx::QueryBuilder;
 
-use crate::{BackendExt, JobState, SqlError, Stat, Worker};
+use crate::{
+    migrate::{History, RestoreExt},
+    unix_now, BackendExt, JobProgress, JobState, ReservedKey, SqlError, Stat, Worker,
+};
+
+/// Creates the tables used by the board next to the ones created by [`SqliteStorage::setup`]
+pub async fn setup(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS BoardProgress (
+            task_id TEXT PRIMARY KEY NOT NULL,
+            job_type TEXT NOT NULL,
+            percent INTEGER NOT NULL,
+            message TEXT,
+            updated_at INTEGER NOT NULL
+        )",
+    )
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS BoardIdempotency (
+            namespace TEXT NOT NULL,
+            idempotency_key TEXT NOT NULL,
+            fingerprint TEXT NOT NULL,
+            outcome TEXT NOT NULL,
+            expires_at INTEGER NOT NULL,
+            PRIMARY KEY (namespace, idempotency_key)
+        )",
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
 
 impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt<J>
     for SqliteStorage<J, JsonCodec<String>>
@@ -19,11 +58,11 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
                             COUNT(1) FILTER (WHERE status = 'Pending') AS pending,
                             COUNT(1) FILTER (WHERE status = 'Running') AS running,
                             COUNT(1) FILTER (WHERE status = 'Done') AS done,
-                            COUNT(1) FILTER (WHERE status = 'Failed') AS failed,
+                            COUNT(1) FILTER (WHERE status IN ('Retry', 'Failed')) AS failed,
                             COUNT(1) FILTER (WHERE status = 'Killed') AS killed
                         FROM Jobs WHERE job_type = ?";
 
-        let res: (i64, i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
+        let res: (i64, i64, i64, i64, i64) = sqlx::query_as(fetch_query)
             .bind(self.get_config().namespace())
             .fetch_one(self.pool())
             .await?;
@@ -42,9 +81,10 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
         status: &JobState,
         page: i32,
     ) -> Result<Vec<Self::Request>, Self::Error> {
-        let status = status.to_string();
-        let fetch_query = "SELECT * FROM Jobs WHERE status = ? AND job_type = ? ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET ?";
+        let [retry, status] = status.to_sql_statuses();
+        let fetch_query = "SELECT * FROM Jobs WHERE status IN (?, ?) AND job_type = ? ORDER BY done_at DESC, run_at DESC LIMIT 10 OFFSET ?";
         let res: Vec<SqlRequest<String>> = sqlx::query_as(fetch_query)
+            .bind(retry)
             .bind(status)
             .bind(self.get_config().namespace())
             .bind(((page - 1) * 10).to_string())
@@ -73,4 +113,462 @@ impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> BackendExt
             .map(|w| Worker::new::<Self>(WorkerId::new(w.0), w.1))
             .collect())
     }
+
+    async fn ping(&self) -> Result<(), Self::Error> {
+        sqlx::query("SELECT 1").execute(self.pool()).await?;
+        Ok(())
+    }
+
+    async fn purge_before(
+        &self,
+        state: &JobState,
+        before: i64,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE FROM Jobs WHERE id IN (SELECT id FROM Jobs WHERE job_type = ? AND status = ? AND done_at < ? ORDER BY done_at LIMIT ?)";
+        let removed = sqlx::query(query)
+            .bind(self.get_config().namespace())
+            .bind(state.to_sql_status())
+            .bind(before)
+            .bind(i64::try_from(limit)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.get_config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn purge_beyond(
+        &self,
+        state: &JobState,
+        keep: usize,
+        limit: usize,
+    ) -> Result<usize, Self::Error> {
+        if !state.is_finished() {
+            return Ok(0);
+        }
+        let query = "DELETE FROM Jobs WHERE id IN (SELECT id FROM Jobs WHERE job_type = ? AND status = ? ORDER BY done_at DESC LIMIT ? OFFSET ?)";
+        let removed = sqlx::query(query)
+            .bind(self.get_config().namespace())
+            .bind(state.to_sql_status())
+            .bind(i64::try_from(limit)?)
+            .bind(i64::try_from(keep)?)
+            .execute(self.pool())
+            .await?
+            .rows_affected();
+        if removed > 0 {
+            clear_removed_progress(self.pool(), self.get_config().namespace()).await?;
+        }
+        Ok(usize::try_from(removed)?)
+    }
+
+    async fn reserve_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        lease: Duration,
+    ) -> Result<Option<ReservedKey>, Self::Error> {
+        let now = i64::try_from(unix_now())?;
+        let expires_at = now.saturating_add(i64::try_from(lease.as_secs())?);
+        sqlx::query("DELETE FROM BoardIdempotency WHERE namespace = ? AND idempotency_key = ? AND expires_at <= ?")
+            .bind(self.get_config().namespace())
+            .bind(key)
+            .bind(now)
+            .execute(self.pool())
+            .await?;
+        let reserved = sqlx::query("INSERT INTO BoardIdempotency (namespace, idempotency_key, fingerprint, outcome, expires_at) VALUES (?, ?, ?, '', ?) ON CONFLICT (namespace, idempotency_key) DO NOTHING")
+            .bind(self.get_config().namespace())
+            .bind(key)
+            .bind(fingerprint)
+            .bind(expires_at)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        if reserved {
+            return Ok(None);
+        }
+        let reserved: Option<(String, String)> = sqlx::query_as(
+            "SELECT fingerprint, outcome FROM BoardIdempotency WHERE namespace = ? AND idempotency_key = ?",
+        )
+        .bind(self.get_config().namespace())
+        .bind(key)
+        .fetch_optional(self.pool())
+        .await?;
+        Ok(Some(
+            reserved
+                .map(|(fingerprint, outcome)| ReservedKey {
+                    fingerprint,
+                    outcome,
+                })
+                .unwrap_or_default(),
+        ))
+    }
+
+    async fn settle_key(
+        &self,
+        key: &str,
+        fingerprint: &str,
+        outcome: Option<&str>,
+        ttl: Duration,
+    ) -> Result<(), Self::Error> {
+        match outcome {
+            Some(outcome) => {
+                let expires_at = i64::try_from(unix_now().saturating_add(ttl.as_secs()))?;
+                sqlx::query("UPDATE BoardIdempotency SET outcome = ?, expires_at = ? WHERE namespace = ? AND idempotency_key = ? AND fingerprint = ?")
+                    .bind(outcome)
+                    .bind(expires_at)
+                    .bind(self.get_config().namespace())
+                    .bind(key)
+                    .bind(fingerprint)
+                    .execute(self.pool())
+                    .await?;
+            }
+            None => {
+                sqlx::query(
+                    "DELETE FROM BoardIdempotency WHERE namespace = ? AND idempotency_key = ? AND fingerprint = ?",
+                )
+                .bind(self.get_config().namespace())
+                .bind(key)
+                .bind(fingerprint)
+                .execute(self.pool())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_progress(&self, progress: &JobProgress) -> Result<(), Self::Error> {
+        let query = "INSERT INTO BoardProgress (task_id, job_type, percent, message, updated_at) VALUES (?, ?, ?, ?, ?)
+                    ON CONFLICT (task_id) DO UPDATE SET percent = excluded.percent, message = excluded.message, updated_at = excluded.updated_at";
+        sqlx::query(query)
+            .bind(&progress.task_id)
+            .bind(self.get_config().namespace())
+            .bind(i64::from(progress.percent))
+            .bind(&progress.message)
+            .bind(i64::try_from(progress.updated_at)?)
+            .execute(self.pool())
+            .await?;
+        Ok(())
+    }
+
+    async fn list_progress(
+        &self,
+        task_ids: &[TaskId],
+    ) -> Result<HashMap<String, JobProgress>, Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut query = QueryBuilder::new(
+            "SELECT task_id, percent, message, updated_at FROM BoardProgress WHERE task_id IN (",
+        );
+        let mut ids = query.separated(", ");
+        for id in task_ids {
+            ids.push_bind(id.to_string());
+        }
+        query.push(")");
+        let res: Vec<(String, i64, Option<String>, i64)> =
+            query.build_query_as().fetch_all(self.pool()).await?;
+        res.into_iter()
+            .map(
+                |(task_id, percent, message, updated_at)| -> Result<_, SqlError> {
+                    Ok((
+                        task_id.clone(),
+                        JobProgress {
+                            task_id,
+                            percent: percent.try_into()?,
+                            message,
+                            updated_at: updated_at.try_into()?,
+                        },
+                    ))
+                },
+            )
+            .collect()
+    }
+
+    async fn clear_progress(&self, task_ids: &[TaskId]) -> Result<(), Self::Error> {
+        if task_ids.is_empty() {
+            return Ok(());
+        }
+        let mut query = QueryBuilder::new("DELETE FROM BoardProgress WHERE task_id IN (");
+        let mut ids = query.separated(", ");
+        for id in task_ids {
+            ids.push_bind(id.to_string());
+        }
+        query.push(")");
+        query.build().execute(self.pool()).await?;
+        Ok(())
+    }
+}
+
+/// Deletes the progress of the jobs of `namespace` that were removed
+async fn clear_removed_progress(pool: &SqlitePool, namespace: &str) -> Result<(), sqlx::Error> {
+    let query = "DELETE FROM BoardProgress WHERE job_type = ? AND task_id NOT IN (SELECT id FROM Jobs WHERE job_type = ?)";
+    sqlx::query(query)
+        .bind(namespace)
+        .bind(namespace)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+impl<J: 'static + Serialize + DeserializeOwned + Unpin + Send + Sync> RestoreExt<J>
+    for SqliteStorage<J, JsonCodec<String>>
+{
+    async fn restore(
+        &self,
+        task_id: &TaskId,
+        job: &J,
+        state: JobState,
+        history: History,
+    ) -> Result<bool, Self::Error> {
+        let status = match state {
+            JobState::Running => JobState::Pending.to_sql_status(),
+            JobState::Failed if history.retrying => "Retry",
+            state => state.to_sql_status(),
+        };
+        let finished = state.is_finished() && !history.retrying;
+        let query = "INSERT INTO Jobs (job, id, job_type, status, attempts, run_at, done_at)
+                    VALUES (?, ?, ?, ?, ?, COALESCE(?, strftime('%s', 'now')), COALESCE(?, CASE WHEN ? THEN strftime('%s', 'now') END))
+                    ON CONFLICT (id) DO NOTHING";
+        let restored = sqlx::query(query)
+            .bind(serde_json::to_string(job)?)
+            .bind(task_id.to_string())
+            .bind(self.get_config().namespace())
+            .bind(status)
+            .bind(history.attempts as i32)
+            .bind(history.run_at)
+            .bind(history.done_at)
+            .bind(finished)
+            .execute(self.pool())
+            .await?
+            .rows_affected()
+            == 1;
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use apalis_sql::Config;
+    use serde_json::Value;
+
+    use super::*;
+
+    async fn storage() -> SqliteStorage<Value> {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        SqliteStorage::setup(&pool).await.unwrap();
+        setup(&pool).await.unwrap();
+        SqliteStorage::new_with_config(pool, Config::default().set_namespace("emails"))
+    }
+
+    #[tokio::test]
+    async fn keys_are_held_until_settled() {
+        let storage = storage().await;
+        let lease = Duration::from_secs(60);
+        assert_eq!(
+            storage.reserve_key("key", "abc", lease).await.unwrap(),
+            None
+        );
+        let running = ReservedKey {
+            fingerprint: "abc".to_string(),
+            outcome: String::new(),
+        };
+        assert_eq!(
+            storage.reserve_key("key", "abc", lease).await.unwrap(),
+            Some(running)
+        );
+
+        storage
+            .settle_key("key", "abc", Some("[]"), Duration::from_secs(3600))
+            .await
+            .unwrap();
+        let settled = storage.reserve_key("key", "def", lease).await.unwrap();
+        assert_eq!(
+            settled.map(|key| (key.fingerprint, key.outcome)),
+            Some(("abc".into(), "[]".into()))
+        );
+
+        storage.settle_key("key", "abc", None, lease).await.unwrap();
+        assert_eq!(
+            storage.reserve_key("key", "def", lease).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_only_settled_by_their_reservation() {
+        let storage = storage().await;
+        // The lease of the first request runs out and another one reserves the key
+        storage
+            .reserve_key("key", "abc", Duration::ZERO)
+            .await
+            .unwrap();
+        let lease = Duration::from_secs(60);
+        assert_eq!(
+            storage.reserve_key("key", "def", lease).await.unwrap(),
+            None
+        );
+
+        let ttl = Duration::from_secs(3600);
+        storage
+            .settle_key("key", "abc", Some("[]"), ttl)
+            .await
+            .unwrap();
+        storage.settle_key("key", "abc", None, ttl).await.unwrap();
+        let running = ReservedKey {
+            fingerprint: "def".to_string(),
+            outcome: String::new(),
+        };
+        assert_eq!(
+            storage.reserve_key("key", "def", lease).await.unwrap(),
+            Some(running)
+        );
+    }
+
+    async fn insert(
+        storage: &SqliteStorage<Value>,
+        status: &str,
+        attempts: i32,
+        run_at: i64,
+        done_at: Option<i64>,
+    ) -> String {
+        let id = TaskId::new().to_string();
+        sqlx::query("INSERT INTO Jobs (job, id, job_type, status, attempts, run_at, done_at) VALUES ('{}', ?, 'emails', ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(status)
+            .bind(attempts)
+            .bind(run_at)
+            .bind(done_at)
+            .execute(storage.pool())
+            .await
+            .unwrap();
+        id
+    }
+
+    #[cfg(feature = "cache")]
+    #[tokio::test]
+    async fn stats_are_cached_until_invalidated() {
+        let storage = crate::cache::Cached::new(storage().await).ttl(Duration::from_secs(60));
+        insert(storage.inner(), "Pending", 0, 0, None).await;
+        assert_eq!(storage.stats().await.unwrap().pending, 1);
+
+        insert(storage.inner(), "Pending", 0, 0, None).await;
+        let clone = storage.clone();
+        let (first, second) = futures::join!(storage.stats(), clone.stats());
+        assert_eq!(first.unwrap().pending, 1);
+        assert_eq!(second.unwrap().pending, 1);
+
+        storage.invalidate().await;
+        assert_eq!(storage.stats().await.unwrap().pending, 2);
+    }
+
+    #[tokio::test]
+    async fn migrations_stop_and_keep_history() {
+        let (source, target) = (storage().await, storage().await);
+        for run_at in 0..12 {
+            insert(&source, "Pending", 0, run_at, None).await;
+        }
+        let done = insert(&source, "Done", 3, 500, Some(1000)).await;
+
+        let checkpoint = crate::migrate::Migration::new(&source, &target)
+            .run(|_| {})
+            .await
+            .unwrap();
+        assert!(checkpoint.finished);
+        assert_eq!(checkpoint.copied, 13);
+        // scheduled jobs are listed with the pending ones, so they are seen twice
+        assert_eq!(checkpoint.skipped, 12);
+
+        let row: (i32, i64, Option<i64>) =
+            sqlx::query_as("SELECT attempts, run_at, done_at FROM Jobs WHERE id = ?")
+                .bind(&done)
+                .fetch_one(target.pool())
+                .await
+                .unwrap();
+        assert_eq!(row, (3, 500, Some(1000)));
+    }
+
+    #[tokio::test]
+    async fn retried_jobs_are_migrated_and_verified() {
+        let (source, target) = (storage().await, storage().await);
+        let retry = insert(&source, "Retry", 1, 100, None).await;
+        insert(&source, "Failed", 3, 100, Some(200)).await;
+        insert(&source, "Killed", 5, 100, Some(300)).await;
+        insert(&source, "Running", 1, 100, None).await;
+
+        let stats = source.stats().await.unwrap();
+        assert_eq!((stats.failed, stats.dead), (2, 1));
+
+        let checkpoint = crate::migrate::Migration::new(&source, &target)
+            .run(|_| {})
+            .await
+            .unwrap();
+        assert_eq!(checkpoint.copied, 4);
+
+        let row: (String, Option<i64>) =
+            sqlx::query_as("SELECT status, done_at FROM Jobs WHERE id = ?")
+                .bind(&retry)
+                .fetch_one(target.pool())
+                .await
+                .unwrap();
+        assert_eq!(row, ("Retry".to_string(), None));
+        let verification = crate::migrate::verify::<Value, _, _>(&source, &target)
+            .await
+            .unwrap();
+        assert!(verification.matches(), "{verification:?}");
+
+        insert(&source, "Killed", 5, 100, Some(300)).await;
+        let verification = crate::migrate::verify::<Value, _, _>(&source, &target)
+            .await
+            .unwrap();
+        assert!(!verification.matches());
+    }
+
+    #[tokio::test]
+    async fn progress_is_forgotten_with_its_job() {
+        let storage = storage().await;
+        let done = insert(&storage, "Done", 1, 0, Some(10)).await;
+        let running = insert(&storage, "Running", 1, 0, None).await;
+        let ids: Vec<TaskId> = [&done, &running]
+            .iter()
+            .map(|id| id.parse().unwrap())
+            .collect();
+        for id in &ids {
+            let progress = JobProgress::new(id, 50, None);
+            storage.set_progress(&progress).await.unwrap();
+        }
+
+        let removed = storage.purge_before(&JobState::Success, 100, 10).await;
+        assert_eq!(removed.unwrap(), 1);
+        let left = storage.list_progress(&ids).await.unwrap();
+        assert_eq!(left.into_keys().collect::<Vec<_>>(), [running]);
+
+        storage.clear_progress(&ids[1..]).await.unwrap();
+        assert!(storage.list_progress(&ids).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn leases_run_out() {
+        let storage = storage().await;
+        assert_eq!(
+            storage
+                .reserve_key("key", "abc", Duration::ZERO)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            storage
+                .reserve_key("key", "def", Duration::ZERO)
+                .await
+                .unwrap(),
+            None
+        );
+    }
 }
@@ -0,0 +1,80 @@
+//! Two boards serving a storage each, like two services embedding `ApiBuilder`, and a third one federating them.
+//!
+//! ```sh
+//! cargo run -p federation
+//! curl http://127.0.0.1:8000/api/v1/backend
+//! curl http://127.0.0.1:8000/api/v1/backend/billing:invoices
+//! ```
+use std::{io, time::Duration};
+
+use actix_web::{dev::Server, rt, web, App, HttpServer};
+use apalis::prelude::Storage;
+use apalis_sql::sqlite::{SqlitePool, SqliteStorage};
+use backend::{
+    api::ApiBuilder,
+    bridge::poll::Watcher,
+    federation::{Federation, Remote},
+    sse::Broadcaster,
+};
+use serde_json::{json, Value};
+
+async fn storage(namespace: &str) -> SqliteStorage<Value> {
+    let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+    SqliteStorage::setup(&pool).await.unwrap();
+    SqliteStorage::new_with_config(pool, apalis_sql::Config::default().set_namespace(namespace))
+}
+
+/// A board of its own on `port`, what each service runs
+fn serve(storage: SqliteStorage<Value>, namespace: &'static str, port: u16) -> io::Result<Server> {
+    let broadcaster = Broadcaster::create();
+    let watcher = Watcher::new(storage).interval(Duration::from_secs(1));
+    let server = HttpServer::new(move || {
+        let scope = ApiBuilder::new()
+            .add_storage_watched(&watcher, namespace, &broadcaster)
+            .events(&broadcaster)
+            .build();
+        App::new().service(web::scope("/api/v1").service(scope))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", port))?
+    .run();
+    Ok(server)
+}
+
+#[actix_web::main]
+async fn main() -> io::Result<()> {
+    let mut invoices = storage("invoices").await;
+    let mut emails = storage("emails").await;
+    for i in 1..=5 {
+        invoices
+            .push(json!({ "customer": format!("customer-{i}"), "amount": i * 100 }))
+            .await
+            .unwrap();
+        emails
+            .push(json!({ "to": format!("test{i}@example.com"), "subject": "Your invoice" }))
+            .await
+            .unwrap();
+    }
+    rt::spawn(serve(invoices, "invoices", 8001)?);
+    rt::spawn(serve(emails, "emails", 8002)?);
+
+    let federation = Federation::new()
+        .remote(Remote::new("billing", "http://127.0.0.1:8001/api/v1"))
+        .remote(Remote::new("mailer", "http://127.0.0.1:8002/api/v1"))
+        .connect()
+        .await
+        .map_err(io::Error::other)?;
+    let broadcaster = Broadcaster::create();
+    federation.relay(broadcaster.clone());
+
+    HttpServer::new(move || {
+        let scope = ApiBuilder::new()
+            .federate(&federation)
+            .events(&broadcaster)
+            .build();
+        App::new().service(web::scope("/api/v1").service(scope))
+    })
+    .bind("127.0.0.1:8000")?
+    .run()
+    .await
+}
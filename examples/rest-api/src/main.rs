@@ -2,7 +2,7 @@ use actix::clock::sleep;
 use actix_web::{rt::signal::ctrl_c, web, App, HttpServer};
 use apalis::{layers::tracing::TraceLayer, prelude::*};
 use apalis_redis::RedisStorage;
-use backend::{api::ApiBuilder, sse::Broadcaster};
+use backend::{api::ApiBuilder, progress::Progress, sse::Broadcaster};
 use core::fmt;
 use futures::{
     future::{self, BoxFuture},
@@ -33,9 +33,11 @@ pub struct Email {
     pub text: String,
 }
 
-pub async fn send_email(_job: Email) {
+pub async fn send_email(_job: Email, task_id: TaskId, progress: Data<Progress>) {
+    progress.update(&task_id, 0, "Preparing email").await;
     sleep(Duration::from_secs(10)).await;
     // log::info!("Attempting to send email to {}", job.to);
+    progress.update(&task_id, 100, "Email sent").await;
 }
 
 #[actix_web::main]
@@ -51,6 +53,11 @@ async fn main() -> std::io::Result<()> {
             WorkerBuilder::new("tasty-apple")
                 .layer(TraceLayer::new())
                 .layer(SseLogLayer::new(broadcaster.clone()))
+                .data(
+                    Progress::new("apalis::redis")
+                        .broadcast_to(broadcaster.clone())
+                        .persist_to(&redis),
+                )
                 .backend(redis.clone())
                 .build_fn(send_email),
         )